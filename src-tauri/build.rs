@@ -1,3 +1,5 @@
 fn main() {
+    // CLI だけをビルドする場合（gui 無効）は Tauri の生成処理を行わない
+    #[cfg(feature = "gui")]
     tauri_build::build()
 }
@@ -0,0 +1,464 @@
+// GUI なしでシリアル/TCP 通信を行うコマンドラインツール。
+// CI やヘッドレスの検査機で、アプリと同じ通信処理・プロファイル・マクロ・テストスイートを使う
+// cargo build --no-default-features --bin serialterm-cli でビルドすると Tauri（GTK/WebKit）に依存しない
+use clap::{Args, Parser, Subcommand};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri_app_with_cc_lib::communication::{
+    message_pipeline, run_trigger_responder, ConnectionManager, PipelineConfig, SerialHandler,
+};
+use tauri_app_with_cc_lib::models::{parse_hex, ConnectionConfig, SerialConfig, TcpConfig, TestSuite};
+use tauri_app_with_cc_lib::services::macros::{self, MacroContext, MacroEvent, MacroOutcome, ReceiveBuffer};
+use tauri_app_with_cc_lib::services::test_runner::{self, StepStatus};
+use tauri_app_with_cc_lib::services::{
+    session, CredentialVault, DataMasker, ResolvedProfile, SessionLogger, SettingsPersistence,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::Level;
+
+// アプリと同じ識別子。設定ディレクトリの既定値に使う
+const APP_IDENTIFIER: &str = "com.embedded-dev.terminal";
+
+// 資格情報を参照するプロファイルで接続する場合のマスターパスワード
+const VAULT_PASSWORD_ENV: &str = "SERIALTERM_VAULT_PASSWORD";
+
+#[derive(Parser)]
+#[command(name = "serialterm-cli", version, about = "Headless serial/TCP terminal sharing the app's communication core")]
+struct Cli {
+    #[arg(long, global = true, help = "Settings directory (defaults to the app's config directory)")]
+    config_dir: Option<PathBuf>,
+    #[arg(short, long, global = true, help = "Print debug logs to stderr")]
+    verbose: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "List available serial ports")]
+    Ports,
+    #[command(about = "List saved profiles")]
+    Profiles,
+    #[command(about = "Bridge stdin/stdout to a connection")]
+    Connect {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    #[command(about = "Send a line and print the response")]
+    Send {
+        #[command(flatten)]
+        target: TargetArgs,
+        text: String,
+        #[arg(long, help = "Wait until the response matches this regex")]
+        expect: Option<String>,
+        #[arg(long, default_value_t = 2000, help = "How long to wait for the response")]
+        timeout_ms: u64,
+        #[arg(long, help = "Treat TEXT as hex bytes such as \"0D 0A\"")]
+        hex: bool,
+        #[arg(long, help = "Do not append the line ending")]
+        no_line_ending: bool,
+    },
+    #[command(about = "Run a saved macro")]
+    Macro {
+        #[command(flatten)]
+        target: TargetArgs,
+        #[arg(help = "Macro name or ID")]
+        name: String,
+    },
+    #[command(about = "Run a YAML/JSON test suite")]
+    Test {
+        #[command(flatten)]
+        target: TargetArgs,
+        suite: PathBuf,
+        #[arg(long, help = "Write a JUnit XML report")]
+        junit: Option<PathBuf>,
+        #[arg(long, help = "Write a JSON report")]
+        json: Option<PathBuf>,
+    },
+}
+
+// 接続先。保存済みプロファイルか、その場で指定したシリアルポート/TCP
+#[derive(Args)]
+struct TargetArgs {
+    #[arg(long, conflicts_with_all = ["serial", "tcp"], help = "Saved profile name or ID")]
+    profile: Option<String>,
+    #[arg(long, conflicts_with = "tcp", help = "Serial port such as /dev/ttyUSB0 or COM3")]
+    serial: Option<String>,
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+    #[arg(long, value_name = "HOST:PORT")]
+    tcp: Option<String>,
+    #[arg(long, help = "Write session logs to this directory")]
+    log_dir: Option<PathBuf>,
+}
+
+// 接続中のセッション
+struct Session {
+    manager: Arc<Mutex<ConnectionManager>>,
+    context: MacroContext,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(if cli.verbose { Level::DEBUG } else { Level::WARN })
+        .init();
+
+    let config_dir = cli.config_dir.clone().unwrap_or_else(default_config_dir);
+    let result = match cli.command {
+        Command::Ports => list_ports().await,
+        Command::Profiles => list_profiles(&config_dir),
+        Command::Connect { target } => bridge(&config_dir, &target).await,
+        Command::Send { target, text, expect, timeout_ms, hex, no_line_ending } => {
+            send_and_wait(&config_dir, &target, &text, expect.as_deref(), timeout_ms, hex, no_line_ending).await
+        }
+        Command::Macro { target, name } => run_macro(&config_dir, &target, &name).await,
+        Command::Test { target, suite, junit, json } => {
+            run_test_suite(&config_dir, &target, &suite, junit.as_deref(), json.as_deref()).await
+        }
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+// Tauri の app_config_dir と同じ場所
+fn default_config_dir() -> PathBuf {
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from).unwrap_or(home)
+    } else if cfg!(target_os = "macos") {
+        home.join("Library").join("Application Support")
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".config"))
+    };
+    base.join(APP_IDENTIFIER)
+}
+
+async fn list_ports() -> Result<ExitCode, String> {
+    let ports = SerialHandler::get_port_info().await.map_err(|e| e.to_string())?;
+    for port in ports {
+        let description = [port.manufacturer, port.product]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("{}\t{}\t{}", port.port_name, port.port_type.unwrap_or_default(), description);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_profiles(config_dir: &Path) -> Result<ExitCode, String> {
+    let profile_manager = SettingsPersistence::new(config_dir.to_path_buf()).load_profiles();
+    for profile in &profile_manager.profiles {
+        let target = match (&profile.serial_config, &profile.tcp_config) {
+            (Some(serial), _) => format!("{} @ {}", serial.port, serial.baud_rate),
+            (None, Some(tcp)) => format!("{}:{}", tcp.host, tcp.port),
+            (None, None) => String::new(),
+        };
+        println!("{}\t{}\t{}", profile.id, profile.name, target);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// 接続設定を決め、アプリと同じ ConnectionManager で接続する
+async fn open_session(config_dir: &Path, target: &TargetArgs) -> Result<Session, String> {
+    let persistence = SettingsPersistence::new(config_dir.to_path_buf());
+    let app_config = persistence.load_app_config();
+
+    let resolved = match (&target.profile, &target.serial, &target.tcp) {
+        (Some(profile), _, _) => {
+            let profile_manager = persistence.load_profiles();
+            let profile = profile_manager
+                .get_profile(profile)
+                .or_else(|| profile_manager.profiles.iter().find(|p| &p.name == profile))
+                .cloned()
+                .ok_or_else(|| format!("Profile not found: {}", profile))?;
+
+            let mut vault = CredentialVault::open(config_dir.join("vault.json")).map_err(|e| e.to_string())?;
+            if !profile.secret_refs.is_empty() {
+                let password = std::env::var(VAULT_PASSWORD_ENV)
+                    .map_err(|_| format!("Set {} to unlock the credential vault", VAULT_PASSWORD_ENV))?;
+                vault.unlock(&password).map_err(|e| e.to_string())?;
+            }
+            ResolvedProfile::resolve(profile, &mut vault)?
+        }
+        (None, Some(port), _) => {
+            let serial_config = SerialConfig {
+                port: port.clone(),
+                baud_rate: target.baud,
+                ..SerialConfig::default()
+            };
+            ResolvedProfile::temporary(ConnectionConfig::new_serial(port.clone(), serial_config))
+        }
+        (None, None, Some(address)) => {
+            let (host, port) = address
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host.to_string(), port.parse::<u16>().ok()?)))
+                .ok_or_else(|| format!("Invalid TCP address: {}", address))?;
+            let tcp_config = TcpConfig {
+                host,
+                port,
+                ..TcpConfig::default()
+            };
+            ResolvedProfile::temporary(ConnectionConfig::new_tcp(address.clone(), tcp_config))
+        }
+        (None, None, None) => return Err("Specify --profile, --serial or --tcp".to_string()),
+    };
+
+    let mut manager = ConnectionManager::new();
+    manager.set_default_terminal(app_config.terminal.clone());
+    if let Some(log_dir) = &target.log_dir {
+        let mut logging = app_config.logging.clone();
+        logging.enabled = true;
+        logging.auto_save = true;
+        manager.set_session_logger(Arc::new(Mutex::new(SessionLogger::new(log_dir.clone(), logging))));
+    }
+    let trigger_responses = manager.take_trigger_responses();
+
+    // 受信データは subscribe で受け取るため、表示用パイプラインの出力は読み捨てる
+    let (tx, mut rx) = message_pipeline(PipelineConfig::default());
+    tokio::spawn(async move { while rx.recv_batch().await.is_some() {} });
+
+    let line_ending = app_config.terminal.with_overrides(&resolved.config.terminal_overrides).line_ending;
+    session::connect(&mut manager, &resolved, tx).await.map_err(|e| e.to_string())?;
+
+    let manager = Arc::new(Mutex::new(manager));
+    if let Some(responses) = trigger_responses {
+        tokio::spawn(run_trigger_responder(manager.clone(), responses));
+    }
+    Ok(Session {
+        manager,
+        context: MacroContext {
+            line_ending,
+            secrets: resolved.secrets,
+        },
+    })
+}
+
+async fn close_session(session: &Session) {
+    if let Err(e) = session.manager.lock().await.disconnect().await {
+        eprintln!("warning: failed to disconnect: {}", e);
+    }
+}
+
+// 標準入力を閉じた後、残りの応答を待つ時間
+const DRAIN_IDLE: Duration = Duration::from_millis(500);
+
+// 標準入力の各行を送信し、受信データを標準出力へ書き出す。EOF か Ctrl+C で終了する
+async fn bridge(config_dir: &Path, target: &TargetArgs) -> Result<ExitCode, String> {
+    let session = open_session(config_dir, target).await?;
+    let mut received = session.manager.lock().await.subscribe();
+    eprintln!("Connected. Press Ctrl+C or close stdin to exit.");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    let mut check_connection = tokio::time::interval(Duration::from_millis(500));
+
+    let code = loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    if let Err(e) = session.manager.lock().await.send_line(&line).await {
+                        eprintln!("error: {}", e);
+                        break ExitCode::FAILURE;
+                    }
+                }
+                Ok(None) => {
+                    // パイプで流し込んだ場合に最後の応答を取りこぼさないよう、受信が途切れるまで待つ
+                    while let Ok(Ok(message)) = tokio::time::timeout(DRAIN_IDLE, received.recv()).await {
+                        let _ = stdout.write_all(message.content.as_bytes()).await;
+                    }
+                    let _ = stdout.flush().await;
+                    break ExitCode::SUCCESS;
+                }
+                Err(e) => {
+                    eprintln!("error: failed to read stdin: {}", e);
+                    break ExitCode::FAILURE;
+                }
+            },
+            message = received.recv() => match message {
+                Ok(message) => {
+                    let _ = stdout.write_all(message.content.as_bytes()).await;
+                    let _ = stdout.flush().await;
+                }
+                Err(RecvError::Lagged(skipped)) => eprintln!("warning: {} messages skipped", skipped),
+                Err(RecvError::Closed) => break ExitCode::FAILURE,
+            },
+            _ = check_connection.tick() => {
+                if !session.manager.lock().await.is_connected() {
+                    eprintln!("Connection closed");
+                    break ExitCode::FAILURE;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break ExitCode::SUCCESS,
+        }
+    };
+
+    close_session(&session).await;
+    Ok(code)
+}
+
+// 1行送信して応答を表示する。expect を指定した場合は一致するまで待ち、一致しなければ失敗とする
+async fn send_and_wait(
+    config_dir: &Path,
+    target: &TargetArgs,
+    text: &str,
+    expect: Option<&str>,
+    timeout_ms: u64,
+    hex: bool,
+    no_line_ending: bool,
+) -> Result<ExitCode, String> {
+    let regex = expect
+        .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e)))
+        .transpose()?;
+    let hex_data = if hex { Some(parse_hex(text)?) } else { None };
+
+    let session = open_session(config_dir, target).await?;
+    let mut received = session.manager.lock().await.subscribe();
+
+    // テキストはプロファイルの文字コード・改行コードで送信する
+    let mut manager = session.manager.lock().await;
+    let sent = match hex_data {
+        Some(mut data) => {
+            let mut content = text.to_string();
+            if !no_line_ending {
+                data.extend_from_slice(session.context.line_ending.to_bytes());
+                content.push_str(session.context.line_ending.to_string());
+            }
+            manager.send_data(&data, content, "HEX").await
+        }
+        None if no_line_ending => manager.send_message(text.to_string()).await,
+        None => manager.send_line(text).await,
+    };
+    drop(manager);
+    sent.map_err(|e| e.to_string())?;
+
+    let mut buffer = ReceiveBuffer::default();
+    let mut stdout = tokio::io::stdout();
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let code = loop {
+        if let Some(regex) = &regex {
+            if buffer.take_match(regex).is_some() {
+                break ExitCode::SUCCESS;
+            }
+        }
+        match tokio::time::timeout_at(deadline, received.recv()).await {
+            Err(_) if regex.is_some() => {
+                eprintln!("Timed out waiting for /{}/", expect.unwrap_or_default());
+                break ExitCode::FAILURE;
+            }
+            Err(_) => break ExitCode::SUCCESS,
+            Ok(Ok(message)) => {
+                let _ = stdout.write_all(message.content.as_bytes()).await;
+                buffer.push(&message);
+            }
+            Ok(Err(RecvError::Lagged(skipped))) => eprintln!("warning: {} messages skipped", skipped),
+            Ok(Err(RecvError::Closed)) => break ExitCode::FAILURE,
+        }
+    };
+    let _ = stdout.flush().await;
+
+    close_session(&session).await;
+    Ok(code)
+}
+
+async fn run_macro(config_dir: &Path, target: &TargetArgs, name: &str) -> Result<ExitCode, String> {
+    let profile_manager = SettingsPersistence::new(config_dir.to_path_buf()).load_profiles();
+    let macro_def = profile_manager
+        .get_macro(name)
+        .or_else(|| profile_manager.macros.iter().find(|m| m.name == name))
+        .cloned()
+        .ok_or_else(|| format!("Macro not found: {}", name))?;
+    macro_def.validate()?;
+
+    let session = open_session(config_dir, target).await?;
+    let received = session.manager.lock().await.subscribe();
+    let mut target = session.manager.clone();
+
+    let outcome = macros::run_macro(&macro_def, &mut target, received, &session.context, |event| match event {
+        MacroEvent::StepStarted { step } => eprintln!("[{}] {:?}", step + 1, macro_def.steps[step]),
+        MacroEvent::PatternMatched { step, text } => eprintln!("[{}] matched: {}", step + 1, text),
+        MacroEvent::PatternTimedOut { step } => eprintln!("[{}] timed out", step + 1),
+        MacroEvent::StepCompleted { .. } | MacroEvent::Finished { .. } => {}
+    })
+    .await;
+
+    close_session(&session).await;
+    match outcome {
+        MacroOutcome::Completed | MacroOutcome::Stopped { .. } => {
+            eprintln!("Macro '{}' completed", macro_def.name);
+            Ok(ExitCode::SUCCESS)
+        }
+        MacroOutcome::Failed { reason, .. } => {
+            eprintln!("Macro '{}' failed: {}", macro_def.name, reason);
+            Ok(ExitCode::FAILURE)
+        }
+        MacroOutcome::Aborted => Ok(ExitCode::FAILURE),
+    }
+}
+
+async fn run_test_suite(
+    config_dir: &Path,
+    target: &TargetArgs,
+    suite_path: &Path,
+    junit: Option<&Path>,
+    json: Option<&Path>,
+) -> Result<ExitCode, String> {
+    let source = std::fs::read_to_string(suite_path)
+        .map_err(|e| format!("Failed to read {}: {}", suite_path.display(), e))?;
+    let suite = TestSuite::parse(&source)?;
+
+    let session = open_session(config_dir, target).await?;
+    let received = session.manager.lock().await.subscribe();
+    let mut target = session.manager.clone();
+    let mut report = test_runner::run_test_suite(&suite, &mut target, received, session.context.line_ending.clone()).await;
+    close_session(&session).await;
+
+    // アプリと同じマスキングルールで、表示・保存する前に機密情報を隠す
+    let logging = SettingsPersistence::new(config_dir.to_path_buf()).load_app_config().logging;
+    report.mask(&DataMasker::new_lossy(&logging));
+
+    for step in &report.steps {
+        let status = match step.status {
+            StepStatus::Passed => "PASS",
+            StepStatus::Failed => "FAIL",
+            StepStatus::Skipped => "SKIP",
+        };
+        println!("{} {}. {} ({} ms)", status, step.index + 1, step.name, step.duration_ms);
+        if let Some(message) = &step.message {
+            println!("     {}", message);
+        }
+    }
+    println!(
+        "{}: {} passed, {} failed, {} skipped",
+        report.suite,
+        report.count(StepStatus::Passed),
+        report.count(StepStatus::Failed),
+        report.count(StepStatus::Skipped)
+    );
+
+    if let Some(path) = junit {
+        std::fs::write(path, report.to_junit_xml()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    if let Some(path) = json {
+        std::fs::write(path, report.to_json()?).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    Ok(if report.passed { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
\ No newline at end of file
@@ -0,0 +1,236 @@
+use crate::models::{ApiServerConfig, TerminalMessage};
+use crate::services::api_server::{ApiHandler, ApiServer, RpcError};
+use crate::utils::crypto;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::{broadcast, Mutex};
+use tracing::{info, warn};
+
+use super::{
+    connect_device, connect_profile, disconnect_device, get_connected_profile, get_connection_info,
+    get_connection_status, send_message, ApiResponse, AppState, FrontendConnectionConfig, SettingsState,
+};
+
+// 起動中のローカル API サーバー
+pub struct ApiServerState {
+    pub server: Mutex<Option<ApiServer>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiServerStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub address: Option<String>,
+    pub token: String,
+}
+
+// 接続中のセッション。接続は1つなので0件か1件になる
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionInfo {
+    pub profile_id: Option<String>, // 一時的な設定で接続している場合は None
+    pub name: String,
+    pub info: Option<String>,
+}
+
+impl ApiServerState {
+    pub fn new() -> Self {
+        Self {
+            server: Mutex::new(None),
+        }
+    }
+
+    // 設定に合わせて起動し直す。無効の場合は停止する。起動できなかった場合は元の状態のまま
+    pub async fn apply(&self, config: &ApiServerConfig, app_handle: &AppHandle) -> Result<(), String> {
+        let mut server = self.server.lock().await;
+        if !config.enabled {
+            if let Some(running) = server.take() {
+                running.stop();
+            }
+            return Ok(());
+        }
+        if config.token.is_empty() {
+            return Err("API token is not set".to_string());
+        }
+
+        // 同じポートで起動中であれば待ち受けを続けたままトークンだけを差し替える
+        if let Some(running) = server.as_ref().filter(|running| running.address().port() == config.port) {
+            running.set_token(config.token.clone());
+            return Ok(());
+        }
+
+        // 新しいサーバーを起動できてから古いサーバーを止める。起動できなければ古いサーバーを使い続ける
+        let handler = Arc::new(TauriApiHandler {
+            app_handle: app_handle.clone(),
+        });
+        let started = ApiServer::start(config.port, config.token.clone(), handler)
+            .await
+            .map_err(|e| format!("Failed to start API server on port {}: {}", config.port, e))?;
+        if let Some(running) = server.replace(started) {
+            running.stop();
+        }
+        Ok(())
+    }
+
+    async fn status(&self, config: &ApiServerConfig) -> ApiServerStatus {
+        let server = self.server.lock().await;
+        ApiServerStatus {
+            enabled: config.enabled,
+            running: server.is_some(),
+            address: server.as_ref().map(|server| server.address().to_string()),
+            token: config.token.clone(),
+        }
+    }
+}
+
+fn generate_token() -> String {
+    crypto::random_bytes(32).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// API のメソッドを既存の Tauri コマンドに対応させる。GUI から操作した場合と同じイベントが発生する
+struct TauriApiHandler {
+    app_handle: AppHandle,
+}
+
+fn into_result<T: Serialize>(response: Result<ApiResponse<T>, String>) -> Result<Value, RpcError> {
+    let response = response.map_err(RpcError::failed)?;
+    if !response.success {
+        return Err(RpcError::failed(response.error.unwrap_or_default()));
+    }
+    serde_json::to_value(response.data).map_err(RpcError::failed)
+}
+
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, RpcError> {
+    let value = params
+        .get(name)
+        .cloned()
+        .ok_or_else(|| RpcError::invalid_params(format!("{} is required", name)))?;
+    serde_json::from_value(value).map_err(|e| RpcError::invalid_params(format!("Invalid {}: {}", name, e)))
+}
+
+#[async_trait]
+impl ApiHandler for TauriApiHandler {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let app_handle = &self.app_handle;
+        match method {
+            "status" => {
+                let connected = into_result(get_connection_status(app_handle.state()).await)?;
+                let info = into_result(get_connection_info(app_handle.state()).await)?;
+                let profile = into_result(get_connected_profile(app_handle.state()).await)?;
+                Ok(json!({ "connected": connected, "info": info, "profile": profile }))
+            }
+            "list_sessions" => {
+                let app_state = app_handle.state::<AppState>();
+                let info = app_state.connection_manager.lock().await.get_connection_info();
+                let sessions: Vec<SessionInfo> = match info {
+                    Some(info) => {
+                        let active_profile = app_state.active_profile.lock().await;
+                        let profile = active_profile.as_ref().map(|resolved| &resolved.config);
+                        vec![SessionInfo {
+                            profile_id: profile.map(|profile| profile.id.clone()),
+                            name: profile.map(|profile| profile.name.clone()).unwrap_or_else(|| info.clone()),
+                            info: Some(info),
+                        }]
+                    }
+                    None => Vec::new(),
+                };
+                serde_json::to_value(sessions).map_err(RpcError::failed)
+            }
+            // profile_id で保存済みプロファイル、config で一時的な設定に接続する
+            "connect" => {
+                if params.get("profile_id").is_some() {
+                    let profile_id: String = param(&params, "profile_id")?;
+                    into_result(
+                        connect_profile(
+                            profile_id,
+                            app_handle.clone(),
+                            app_handle.state(),
+                            app_handle.state(),
+                            app_handle.state(),
+                        )
+                        .await,
+                    )
+                } else {
+                    let config: FrontendConnectionConfig = param(&params, "config")?;
+                    into_result(connect_device(config, app_handle.clone(), app_handle.state()).await)
+                }
+            }
+            "disconnect" => into_result(disconnect_device(app_handle.clone(), app_handle.state()).await),
+            // 改行コードは付加しない（send_message コマンドと同じ）
+            "send" => {
+                let message: String = param(&params, "message")?;
+                into_result(send_message(message, app_handle.state()).await)
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    async fn subscribe(&self) -> broadcast::Receiver<TerminalMessage> {
+        let app_state = self.app_handle.state::<AppState>();
+        let connection_manager = app_state.connection_manager.lock().await;
+        connection_manager.subscribe()
+    }
+}
+
+// Tauri コマンド
+
+#[tauri::command]
+pub async fn get_api_server_status(
+    settings_state: State<'_, SettingsState>,
+    api_state: State<'_, ApiServerState>,
+) -> Result<ApiResponse<ApiServerStatus>, String> {
+    let config = settings_state.app_config.lock().await.api_server.clone();
+    Ok(ApiResponse::success(api_state.status(&config).await))
+}
+
+// API サーバーを有効化・無効化する。初めて有効化したときにトークンを生成する
+#[tauri::command]
+pub async fn set_api_server_enabled(
+    enabled: bool,
+    port: Option<u16>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+    api_state: State<'_, ApiServerState>,
+) -> Result<ApiResponse<ApiServerStatus>, String> {
+    let mut app_config = settings_state.app_config.lock().await;
+    let mut config = app_config.api_server.clone();
+    config.enabled = enabled;
+    if let Some(port) = port {
+        config.port = port;
+    }
+    if config.token.is_empty() {
+        config.token = generate_token();
+    }
+
+    if let Err(e) = api_state.apply(&config, &app_handle).await {
+        warn!("{}", e);
+        return Ok(ApiResponse::error(e));
+    }
+    info!("Local API server {}", if enabled { "enabled" } else { "disabled" });
+
+    app_config.api_server = config.clone();
+    settings_state.save_app_config(&app_config);
+    Ok(ApiResponse::success(api_state.status(&config).await))
+}
+
+// トークンを作り直す。起動中の場合は新しいトークンに差し替え、接続中のクライアントを切断する
+#[tauri::command]
+pub async fn regenerate_api_token(
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+    api_state: State<'_, ApiServerState>,
+) -> Result<ApiResponse<ApiServerStatus>, String> {
+    let mut app_config = settings_state.app_config.lock().await;
+    app_config.api_server.token = generate_token();
+    settings_state.save_app_config(&app_config);
+
+    let config = app_config.api_server.clone();
+    if let Err(e) = api_state.apply(&config, &app_handle).await {
+        warn!("{}", e);
+        return Ok(ApiResponse::error(e));
+    }
+    Ok(ApiResponse::success(api_state.status(&config).await))
+}
\ No newline at end of file
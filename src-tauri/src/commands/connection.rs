@@ -1,29 +1,63 @@
-use crate::communication::{ConnectionError, ConnectionManager, SerialHandler};
-use crate::models::{ConnectionConfig, ConnectionType, SerialConfig, TcpConfig, DataBits, StopBits, Parity, FlowControl, TerminalMessage, MessageDirection};
+use crate::communication::pty::PtyBridge;
+use crate::communication::{message_pipeline, run_trigger_responder, ConnectionError, ConnectionManager, MessageBatch, MessageReceiver, MessageSender, PipelineConfig, SerialHandler, TriggerResponse};
+use crate::models::{ConnectionConfig, ConnectionType, SerialConfig, TcpConfig, DataBits, StopBits, Parity, FlowControl, TerminalMessage, MessageDirection, MessageStore, TriggerRule, HighlightRule, HighlightSpan, Alert, TerminalConfig};
+use crate::services::masking::PartialLineBuffer;
+use crate::services::tcp_bridge::TcpBridge;
+use crate::services::{session, DataMasker, ResolvedProfile, SessionLogger};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::{AppHandle, Emitter, State};
-use tokio::sync::{mpsc, Mutex};
-use tracing::{debug, error, info};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
 use chrono::Utc;
 
+use super::{forward_alerts, SettingsState, VaultState};
+
 // アプリケーション状態
 pub struct AppState {
     pub connection_manager: Arc<Mutex<ConnectionManager>>,
-    pub message_receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<TerminalMessage>>>>,
-    pub message_sender: Arc<Mutex<Option<mpsc::UnboundedSender<TerminalMessage>>>>,
+    pub message_receiver: Arc<Mutex<Option<MessageReceiver>>>,
+    pub message_sender: Arc<Mutex<Option<MessageSender>>>,
     pub message_handler_started: Arc<Mutex<bool>>,
+    pub session_logger: Arc<Mutex<SessionLogger>>,
+    pub data_masker: Arc<RwLock<DataMasker>>,
+    pub active_profile: Arc<Mutex<Option<ResolvedProfile>>>, // 保存済みプロファイルで接続中の場合のみ
+    pub trigger_responses: Arc<Mutex<Option<UnboundedReceiver<TriggerResponse>>>>,
+    pub alerts: Arc<Mutex<Option<UnboundedReceiver<Alert>>>>,
+    pub pty_bridge: Arc<Mutex<Option<PtyBridge>>>, // 開いている場合のみ。切断時に閉じる
+    pub tcp_bridge: Arc<Mutex<Option<TcpBridge>>>, // 起動している場合のみ。切断時に停止する
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
+    pub fn new(
+        message_store: Arc<Mutex<MessageStore>>,
+        session_logger: Arc<Mutex<SessionLogger>>,
+        data_masker: DataMasker,
+        highlight_rules: &[HighlightRule],
+        terminal: TerminalConfig,
+    ) -> Self {
+        let (tx, rx) = message_pipeline(PipelineConfig::default());
+        let mut connection_manager = ConnectionManager::with_message_store(message_store);
+        connection_manager.set_session_logger(session_logger.clone());
+        let trigger_responses = connection_manager.take_trigger_responses();
+        connection_manager.set_highlight_rules(highlight_rules);
+        connection_manager.set_default_terminal(terminal);
+        let alerts = connection_manager.take_alerts();
         Self {
-            connection_manager: Arc::new(Mutex::new(ConnectionManager::new())),
+            connection_manager: Arc::new(Mutex::new(connection_manager)),
             message_receiver: Arc::new(Mutex::new(Some(rx))),
             message_sender: Arc::new(Mutex::new(Some(tx))),
             message_handler_started: Arc::new(Mutex::new(false)),
+            session_logger,
+            data_masker: Arc::new(RwLock::new(data_masker)),
+            active_profile: Arc::new(Mutex::new(None)),
+            trigger_responses: Arc::new(Mutex::new(trigger_responses)),
+            alerts: Arc::new(Mutex::new(alerts)),
+            pty_bridge: Arc::new(Mutex::new(None)),
+            tcp_bridge: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -86,10 +120,27 @@ pub struct FrontendConnectionConfig {
 pub struct FrontendTerminalMessage {
     pub id: String,
     pub timestamp: String,
-    pub direction: String, // "sent" or "received"
+    pub direction: String, // "sent", "received" or "system"
     pub content: String,
     #[serde(rename = "type")]
     pub message_type: String, // "text" or "hex"
+    pub highlights: Vec<HighlightSpan>,
+}
+
+// フロントエンドへまとめて送信するメッセージ
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FrontendMessageBatch {
+    pub messages: Vec<FrontendTerminalMessage>,
+    pub dropped: u64, // バッファ溢れで破棄されたメッセージ数
+}
+
+impl From<MessageBatch> for FrontendMessageBatch {
+    fn from(batch: MessageBatch) -> Self {
+        Self {
+            messages: batch.messages.into_iter().map(FrontendTerminalMessage::from).collect(),
+            dropped: batch.dropped,
+        }
+    }
 }
 
 // 型変換関数
@@ -120,6 +171,11 @@ impl FrontendConnectionConfig {
                     tcp_config: None,
                     created_at: now,
                     updated_at: now,
+                    secret_refs: Vec::new(),
+                    tags: Vec::new(),
+                    last_connected_at: None,
+                    terminal_overrides: Default::default(),
+                    triggers: Vec::new(),
                 })
             },
             "tcp" => {
@@ -143,6 +199,11 @@ impl FrontendConnectionConfig {
                     tcp_config: Some(tcp_config),
                     created_at: now,
                     updated_at: now,
+                    secret_refs: Vec::new(),
+                    tags: Vec::new(),
+                    last_connected_at: None,
+                    terminal_overrides: Default::default(),
+                    triggers: Vec::new(),
                 })
             },
             _ => Err(format!("サポートされていない接続タイプです: {}", self.connection_type)),
@@ -156,6 +217,7 @@ impl From<TerminalMessage> for FrontendTerminalMessage {
         let direction = match msg.direction {
             MessageDirection::Sent => "sent",
             MessageDirection::Received => "received",
+            MessageDirection::System => "system",
         };
         
         Self {
@@ -164,6 +226,7 @@ impl From<TerminalMessage> for FrontendTerminalMessage {
             direction: direction.to_string(),
             content: msg.content,
             message_type: "text".to_string(),
+            highlights: msg.highlights,
         }
     }
 }
@@ -232,6 +295,61 @@ pub async fn connect_device(
         }
     };
     
+    match establish_connection(&ResolvedProfile::temporary(backend_config), &app_handle, &state).await {
+        Ok(info) => {
+            // 一時的な設定での接続はプロファイルに紐づけない
+            *state.active_profile.lock().await = None;
+            Ok(ApiResponse::success(info))
+        }
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
+
+// 保存済みプロファイルで接続する。
+// 参照している秘密情報を資格情報ストアから取り出し、接続に成功したら使用履歴を更新する
+#[tauri::command]
+pub async fn connect_profile(
+    profile_id: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    settings_state: State<'_, SettingsState>,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<String>, String> {
+    info!("Attempting to connect with profile: {}", profile_id);
+    
+    let profile = {
+        let profile_manager = settings_state.profile_manager.lock().await;
+        match profile_manager.get_profile(&profile_id) {
+            Some(profile) => profile.clone(),
+            None => return Ok(ApiResponse::error(format!("Profile not found: {}", profile_id))),
+        }
+    };
+    
+    let resolved = match ResolvedProfile::resolve(profile, &mut *vault_state.vault.lock().await) {
+        Ok(resolved) => resolved,
+        Err(e) => return Ok(ApiResponse::error(e)),
+    };
+    
+    match establish_connection(&resolved, &app_handle, &state).await {
+        Ok(info) => {
+            *state.active_profile.lock().await = Some(resolved);
+            
+            let mut profile_manager = settings_state.profile_manager.lock().await;
+            if profile_manager.record_connection(&profile_id) {
+                settings_state.save_profiles(&profile_manager);
+            }
+            Ok(ApiResponse::success(info))
+        }
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
+
+// 接続を確立し、接続状態の変化をフロントエンドへ通知する
+async fn establish_connection(
+    profile: &ResolvedProfile,
+    app_handle: &AppHandle,
+    state: &AppState,
+) -> Result<String, String> {
     let mut connection_manager = state.connection_manager.lock().await;
     
     // メッセージチャンネルを取得
@@ -241,34 +359,49 @@ pub async fn connect_device(
             Some(tx) => tx.clone(),
             None => {
                 error!("Message sender not available");
-                return Ok(ApiResponse::error("Internal error: message sender not available".to_string()));
+                return Err("Internal error: message sender not available".to_string());
             }
         }
     };
 
     // 受信メッセージ処理を開始（初回のみ）
-    start_message_handling(app_handle.clone(), state.message_receiver.clone(), state.message_handler_started.clone()).await;
+    start_message_handling(
+        app_handle.clone(),
+        state.message_receiver.clone(),
+        state.message_handler_started.clone(),
+        state.data_masker.clone(),
+    )
+    .await;
+
+    // トリガーの応答送信を開始（初回のみ）
+    if let Some(responses) = state.trigger_responses.lock().await.take() {
+        tokio::spawn(run_trigger_responder(state.connection_manager.clone(), responses));
+    }
+
+    // 強調表示ルールの通知を開始（初回のみ）
+    if let Some(alerts) = state.alerts.lock().await.take() {
+        tokio::spawn(forward_alerts(app_handle.clone(), alerts));
+    }
 
     // 接続実行
-    match connection_manager.connect(backend_config.clone(), message_tx).await {
+    let config = &profile.config;
+    match session::connect(&mut connection_manager, profile, message_tx).await {
         Ok(_) => {
-            info!("Successfully connected to device: {}", backend_config.name);
+            info!("Successfully connected to device: {}", config.name);
             
             // 接続成功イベントを送信
-            let _ = app_handle.emit("connection-status-changed", ("connected", &backend_config.name));
-            
-            let info = connection_manager.get_connection_info()
-                .unwrap_or_else(|| "Connected".to_string());
+            let _ = app_handle.emit("connection-status-changed", ("connected", &config.name));
             
-            Ok(ApiResponse::success(info))
+            Ok(connection_manager.get_connection_info()
+                .unwrap_or_else(|| "Connected".to_string()))
         }
         Err(e) => {
-            error!("Failed to connect to device {}: {}", backend_config.name, e);
+            error!("Failed to connect to device {}: {}", config.name, e);
             
             // 接続失敗イベントを送信
             let _ = app_handle.emit("connection-status-changed", ("error", e.to_string()));
             
-            Ok(ApiResponse::error(e.to_string()))
+            Err(e.to_string())
         }
     }
 }
@@ -280,11 +413,15 @@ pub async fn disconnect_device(
 ) -> Result<ApiResponse<String>, String> {
     info!("Attempting to disconnect device");
     
-    let mut connection_manager = state.connection_manager.lock().await;
+    // ブリッジなどの状態を破棄する前に connection_manager を解放しておく
+    let result = state.connection_manager.lock().await.disconnect().await;
     
-    match connection_manager.disconnect().await {
+    match result {
         Ok(_) => {
             info!("Successfully disconnected device");
+            *state.active_profile.lock().await = None;
+            *state.pty_bridge.lock().await = None;
+            *state.tcp_bridge.lock().await = None;
             
             // 切断イベントを送信
             let _ = app_handle.emit("connection-status-changed", ("disconnected", ""));
@@ -307,7 +444,7 @@ pub async fn send_message(
     
     let mut connection_manager = state.connection_manager.lock().await;
     
-    match connection_manager.send_message(message).await {
+    match connection_manager.send_line(&message).await {
         Ok(_) => {
             debug!("Message sent successfully");
             Ok(ApiResponse::success("Message sent".to_string()))
@@ -341,15 +478,69 @@ pub async fn get_connection_info(
     Ok(ApiResponse::success(info))
 }
 
+// 接続中のプロファイル（一時的な設定で接続している場合は None）
+#[tauri::command]
+pub async fn get_connected_profile(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<Option<ConnectionConfig>>, String> {
+    let active_profile = state.active_profile.lock().await;
+    Ok(ApiResponse::success(active_profile.as_ref().map(|resolved| resolved.config.clone())))
+}
+
+// プロファイルのトリガールールを保存する。そのプロファイルで接続中であれば直ちに反映する
+#[tauri::command]
+pub async fn set_profile_triggers(
+    profile_id: String,
+    triggers: Vec<TriggerRule>,
+    state: State<'_, AppState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<ConnectionConfig>, String> {
+    for trigger in &triggers {
+        if let Err(e) = trigger.validate() {
+            return Ok(ApiResponse::error(format!("Trigger '{}': {}", trigger.name, e)));
+        }
+    }
+
+    let profile = {
+        let mut profile_manager = settings_state.profile_manager.lock().await;
+        let Some(profile) = profile_manager.get_profile_mut(&profile_id) else {
+            return Ok(ApiResponse::error(format!("Profile not found: {}", profile_id)));
+        };
+        profile.triggers = triggers;
+        profile.updated_at = Utc::now();
+        let profile = profile.clone();
+        settings_state.save_profiles(&profile_manager);
+        profile
+    };
+
+    // 切断処理と同じく connection_manager より先に active_profile を解放しておく
+    let is_active = {
+        let mut active_profile = state.active_profile.lock().await;
+        match active_profile.as_mut().filter(|resolved| resolved.config.id == profile_id) {
+            Some(resolved) => {
+                resolved.config.triggers = profile.triggers.clone();
+                true
+            }
+            None => false,
+        }
+    };
+    if is_active {
+        state.connection_manager.lock().await.set_triggers(&profile.triggers).await;
+        info!("Applied {} trigger(s) to the active connection", profile.triggers.len());
+    }
+    Ok(ApiResponse::success(profile))
+}
+
 // メッセージハンドリングの開始（一度だけ実行される）
 async fn start_message_handling(
     app_handle: AppHandle,
-    message_receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<TerminalMessage>>>>,
+    message_receiver: Arc<Mutex<Option<MessageReceiver>>>,
     handler_started: Arc<Mutex<bool>>,
+    data_masker: Arc<RwLock<DataMasker>>,
 ) {
     let mut started_guard = handler_started.lock().await;
     if *started_guard {
-        info!("⚠️ メッセージハンドリングループは既に開始済みです");
+        debug!("Message handling loop already started");
         return;
     }
     *started_guard = true;
@@ -361,22 +552,51 @@ async fn start_message_handling(
         tokio::spawn(async move {
             info!("🚀 メッセージハンドリングループを開始します");
             
-            while let Some(message) = rx.recv().await {
-                debug!("Received message: {:?}", message);
-                
-                // バックエンドメッセージをフロントエンド形式に変換
-                let frontend_message = FrontendTerminalMessage::from(message);
-                info!("🔄 メッセージ変換完了: {:?}", frontend_message);
-                
-                // フロントエンドにメッセージを送信
-                match app_handle.emit("terminal-message-received", &frontend_message) {
-                    Ok(_) => {
-                        info!("✅ フロントエンドへメッセージ送信成功: {}", frontend_message.content);
+            // 表示内容をマスクする場合は、行の途中で分かれた機密情報も検出できるよう行末まで保留する
+            let mut partial_lines = PartialLineBuffer::default();
+            
+            // 一定間隔ごとにまとめてフロントエンドへ送信する
+            loop {
+                let deadline = partial_lines.deadline();
+                let (mut batch, held_back) = tokio::select! {
+                    batch = rx.recv_batch() => match batch {
+                        Some(batch) => (batch, false),
+                        None => break,
+                    },
+                    // 行末が届かないまま保留期限を過ぎた
+                    _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                        (MessageBatch { messages: partial_lines.flush(), dropped: 0 }, true)
                     }
-                    Err(e) => {
-                        error!("❌ フロントエンドへのメッセージ送信失敗: {}", e);
+                };
+                if batch.dropped > 0 {
+                    warn!("Dropped {} messages due to receive buffer overflow", batch.dropped);
+                }
+                
+                // 設定に応じて表示内容もマスクする
+                {
+                    let masker = data_masker.read().await;
+                    if masker.applies_to_live_view() {
+                        if !held_back {
+                            batch.messages = partial_lines.push(std::mem::take(&mut batch.messages), Instant::now());
+                        }
+                        masker.mask_messages_by_line(&mut batch.messages);
+                    } else {
+                        // 表示のマスキングを無効にした場合は保留中のメッセージもそのまま送る
+                        let mut messages = partial_lines.flush();
+                        messages.append(&mut batch.messages);
+                        batch.messages = messages;
                     }
                 }
+                if batch.messages.is_empty() && batch.dropped == 0 {
+                    continue;
+                }
+                
+                let frontend_batch = FrontendMessageBatch::from(batch);
+                debug!("Emitting batch of {} messages", frontend_batch.messages.len());
+                
+                if let Err(e) = app_handle.emit("terminal-messages-received", &frontend_batch) {
+                    error!("❌ フロントエンドへのメッセージ送信失敗: {}", e);
+                }
             }
             
             info!("Message handling loop ended");
@@ -394,7 +614,7 @@ impl From<ConnectionError> for String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::{ConnectionType, DataBits, FlowControl, Parity, SerialConfig, StopBits, TcpConfig};
+    use crate::models::{ConnectionType, DataBits, FlowControl, LoggingConfig, Parity, SerialConfig, StopBits, TcpConfig};
     use chrono::Utc;
     use std::time::Duration;
 
@@ -414,6 +634,11 @@ mod tests {
             tcp_config: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
         }
     }
 
@@ -431,12 +656,23 @@ mod tests {
             }),
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
         }
     }
 
     #[test]
     fn test_app_state_new() {
-        let _state = AppState::new();
+        let _state = AppState::new(
+            Arc::new(Mutex::new(MessageStore::default())),
+            Arc::new(Mutex::new(SessionLogger::new(std::env::temp_dir(), LoggingConfig::default()))),
+            DataMasker::default(),
+            &[],
+            TerminalConfig::default(),
+        );
         
         // 状態が正しく初期化されることを確認
         // 内部フィールドは直接アクセスできないが、構造体の作成は成功する
@@ -549,6 +785,24 @@ mod tests {
         assert_eq!(string_error, "Port not found: COM1");
     }
 
+    #[test]
+    fn test_frontend_message_batch_from_batch() {
+        let batch = MessageBatch {
+            messages: vec![
+                TerminalMessage::new_received("first".to_string(), "UTF-8".to_string()),
+                TerminalMessage::new_received("second".to_string(), "UTF-8".to_string()),
+            ],
+            dropped: 3,
+        };
+        
+        let frontend_batch = FrontendMessageBatch::from(batch);
+        
+        assert_eq!(frontend_batch.messages.len(), 2);
+        assert_eq!(frontend_batch.messages[0].content, "first");
+        assert_eq!(frontend_batch.messages[1].direction, "received");
+        assert_eq!(frontend_batch.dropped, 3);
+    }
+
     #[test]
     fn test_api_response_serialization() {
         let response = ApiResponse::success(vec!["port1".to_string(), "port2".to_string()]);
@@ -0,0 +1,60 @@
+use crate::models::{Alert, AlertCount, HighlightRule};
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{error, info, warn};
+
+use super::{ApiResponse, AppState, SettingsState};
+
+// 重要度が Critical（または notify を指定）のルールに一致したら alert-raised を送る
+pub async fn forward_alerts(app_handle: AppHandle, mut alerts: UnboundedReceiver<Alert>) {
+    while let Some(alert) = alerts.recv().await {
+        warn!("Alert '{}' raised: {}", alert.rule_name, alert.matched.escape_debug());
+        if let Err(e) = app_handle.emit("alert-raised", &alert) {
+            error!("Failed to emit alert: {}", e);
+        }
+    }
+}
+
+// Tauri コマンド
+
+#[tauri::command]
+pub async fn get_highlight_rules(
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<HighlightRule>>, String> {
+    let app_config = settings_state.app_config.lock().await;
+    Ok(ApiResponse::success(app_config.highlight_rules.clone()))
+}
+
+// ルールの一覧を保存し、接続中のセッションにも直ちに反映する。ID が空のルールには新しい ID を付ける
+#[tauri::command]
+pub async fn save_highlight_rules(
+    mut rules: Vec<HighlightRule>,
+    state: State<'_, AppState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<HighlightRule>>, String> {
+    for rule in rules.iter_mut() {
+        if let Err(e) = rule.validate() {
+            return Ok(ApiResponse::error(format!("Highlight rule '{}': {}", rule.name, e)));
+        }
+        if rule.id.is_empty() {
+            rule.id = uuid::Uuid::new_v4().to_string();
+        }
+    }
+
+    let mut app_config = settings_state.app_config.lock().await;
+    app_config.highlight_rules = rules.clone();
+    settings_state.save_app_config(&app_config);
+
+    state.connection_manager.lock().await.set_highlight_rules(&rules);
+    info!("Saved {} highlight rule(s)", rules.len());
+    Ok(ApiResponse::success(rules))
+}
+
+// 接続中（切断後は直前）のセッションでルールに一致した回数
+#[tauri::command]
+pub async fn get_alert_counts(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<Vec<AlertCount>>, String> {
+    let connection_manager = state.connection_manager.lock().await;
+    Ok(ApiResponse::success(connection_manager.alert_counts()))
+}
\ No newline at end of file
@@ -0,0 +1,206 @@
+use crate::models::Macro;
+use crate::services::macros::{self, MacroContext, MacroEvent, MacroOutcome};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use super::{ApiResponse, AppState, SettingsState, TerminalState};
+
+// 実行中のマクロ（接続は1つなので同時に1つまで）
+pub struct MacroState {
+    pub current: Arc<Mutex<Option<MacroRun>>>,
+}
+
+pub struct MacroRun {
+    pub run_id: String,
+    pub macro_id: String,
+    handle: JoinHandle<()>,
+}
+
+impl MacroState {
+    pub fn new() -> Self {
+        Self {
+            current: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+// "macro-progress" イベントのペイロード
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MacroProgress {
+    pub run_id: String,
+    pub macro_id: String,
+    pub event: MacroEvent,
+}
+
+fn emit_progress(app_handle: &AppHandle, run_id: &str, macro_id: &str, event: MacroEvent) {
+    let progress = MacroProgress {
+        run_id: run_id.to_string(),
+        macro_id: macro_id.to_string(),
+        event,
+    };
+    if let Err(e) = app_handle.emit("macro-progress", &progress) {
+        error!("Failed to emit macro-progress event: {}", e);
+    }
+}
+
+// Tauri コマンド
+
+#[tauri::command]
+pub async fn list_macros(
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<Macro>>, String> {
+    let profile_manager = settings_state.profile_manager.lock().await;
+    Ok(ApiResponse::success(profile_manager.macros.clone()))
+}
+
+// 新規作成・更新の両方に使う。ID が空の場合は新しく採番する
+#[tauri::command]
+pub async fn save_macro(
+    macro_def: Macro,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Macro>, String> {
+    let mut macro_def = if macro_def.id.is_empty() {
+        Macro::new(macro_def.name, macro_def.description, macro_def.steps)
+    } else {
+        macro_def
+    };
+    if let Err(e) = macro_def.validate() {
+        return Ok(ApiResponse::error(e));
+    }
+    macro_def.updated_at = Utc::now();
+
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    profile_manager.save_macro(macro_def.clone());
+    settings_state.save_profiles(&profile_manager);
+
+    info!("Saved macro: {}", macro_def.name);
+    Ok(ApiResponse::success(profile_manager.get_macro(&macro_def.id).cloned().unwrap_or(macro_def)))
+}
+
+#[tauri::command]
+pub async fn delete_macro(
+    macro_id: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<bool>, String> {
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    let removed = profile_manager.remove_macro(&macro_id);
+    if removed {
+        settings_state.save_profiles(&profile_manager);
+    }
+    Ok(ApiResponse::success(removed))
+}
+
+// 接続中のセッションでマクロを開始し、実行IDを返す。進捗は "macro-progress" で通知する
+#[tauri::command]
+pub async fn run_macro(
+    macro_id: String,
+    app_handle: AppHandle,
+    macro_state: State<'_, MacroState>,
+    app_state: State<'_, AppState>,
+    terminal_state: State<'_, TerminalState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    let macro_def = {
+        let profile_manager = settings_state.profile_manager.lock().await;
+        match profile_manager.get_macro(&macro_id) {
+            Some(macro_def) => macro_def.clone(),
+            None => return Ok(ApiResponse::error(format!("Macro not found: {}", macro_id))),
+        }
+    };
+    if let Err(e) = macro_def.validate() {
+        return Ok(ApiResponse::error(e));
+    }
+
+    let mut current = macro_state.current.lock().await;
+    if let Some(run) = current.as_ref() {
+        if !run.handle.is_finished() {
+            return Ok(ApiResponse::error("Another macro is already running".to_string()));
+        }
+    }
+
+    // 受信データを取りこぼさないよう、開始前に購読しておく
+    let received = {
+        let connection_manager = app_state.connection_manager.lock().await;
+        if !connection_manager.is_connected() {
+            return Ok(ApiResponse::error("Not connected".to_string()));
+        }
+        connection_manager.subscribe()
+    };
+
+    // 接続中プロファイルの改行コードと資格情報を使う
+    let global = terminal_state.config.lock().await.clone();
+    let context = match app_state.active_profile.lock().await.as_ref() {
+        Some(resolved) => MacroContext {
+            line_ending: global.with_overrides(&resolved.config.terminal_overrides).line_ending,
+            secrets: resolved.secrets.clone(),
+        },
+        None => MacroContext {
+            line_ending: global.line_ending,
+            secrets: HashMap::new(),
+        },
+    };
+
+    let run_id = uuid::Uuid::new_v4().to_string();
+    info!("Running macro '{}' ({})", macro_def.name, run_id);
+
+    let mut target = app_state.connection_manager.clone();
+    let current_run = macro_state.current.clone();
+    let task_run_id = run_id.clone();
+    let handle = tokio::spawn(async move {
+        let outcome = macros::run_macro(&macro_def, &mut target, received, &context, |event| {
+            emit_progress(&app_handle, &task_run_id, &macro_def.id, event)
+        })
+        .await;
+        debug!("Macro '{}' finished: {:?}", macro_def.name, outcome);
+
+        let mut current = current_run.lock().await;
+        if current.as_ref().map(|run| run.run_id == task_run_id).unwrap_or(false) {
+            *current = None;
+        }
+    });
+
+    *current = Some(MacroRun {
+        run_id: run_id.clone(),
+        macro_id,
+        handle,
+    });
+    Ok(ApiResponse::success(run_id))
+}
+
+// 実行中のマクロを中断する。run_id を省略した場合は実行中のものを対象にする
+#[tauri::command]
+pub async fn abort_macro(
+    run_id: Option<String>,
+    app_handle: AppHandle,
+    macro_state: State<'_, MacroState>,
+) -> Result<ApiResponse<bool>, String> {
+    let mut current = macro_state.current.lock().await;
+    let matches = match (current.as_ref(), &run_id) {
+        (Some(run), Some(run_id)) => &run.run_id == run_id,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    if !matches {
+        return Ok(ApiResponse::success(false));
+    }
+
+    let Some(run) = current.take() else {
+        return Ok(ApiResponse::success(false));
+    };
+    run.handle.abort();
+    info!("Aborted macro run {}", run.run_id);
+
+    emit_progress(
+        &app_handle,
+        &run.run_id,
+        &run.macro_id,
+        MacroEvent::Finished { outcome: MacroOutcome::Aborted },
+    );
+    Ok(ApiResponse::success(true))
+}
\ No newline at end of file
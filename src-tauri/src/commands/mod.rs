@@ -1,7 +1,25 @@
+pub mod api_server;
 pub mod connection;
+pub mod highlights;
+pub mod macros;
+pub mod pty;
+pub mod scheduler;
+pub mod scripting;
 pub mod terminal;
 pub mod settings;
+pub mod tcp_bridge;
+pub mod test_runner;
+pub mod vault;
 
+pub use api_server::*;
 pub use connection::*;
+pub use highlights::*;
+pub use macros::*;
+pub use pty::*;
+pub use scheduler::*;
+pub use scripting::*;
 pub use terminal::*;
-pub use settings::*;
\ No newline at end of file
+pub use settings::*;
+pub use tcp_bridge::*;
+pub use test_runner::*;
+pub use vault::*;
\ No newline at end of file
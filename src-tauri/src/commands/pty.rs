@@ -0,0 +1,85 @@
+use crate::communication::pty::PtyBridge;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{error, warn};
+
+use super::{ApiResponse, AppState};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PtyBridgeInfo {
+    pub path: String,
+}
+
+// Tauri コマンド
+
+// 接続中のセッションを PTY として公開する。既に開いている場合はそのパスを返す。
+// 入出力エラーなどで転送が止まった場合はブリッジを閉じ、pty-bridge-closed イベントで理由を送る
+#[tauri::command]
+pub async fn open_pty_bridge(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<PtyBridgeInfo>, String> {
+    // 切断処理とロックの順序が逆にならないよう、connection_manager を解放してから pty_bridge をロックする
+    let raw = {
+        let connection_manager = state.connection_manager.lock().await;
+        if !connection_manager.is_connected() {
+            return Ok(ApiResponse::error("Not connected".to_string()));
+        }
+        connection_manager.subscribe_raw()
+    };
+
+    let mut pty_bridge = state.pty_bridge.lock().await;
+    if let Some(bridge) = pty_bridge.as_ref() {
+        return Ok(ApiResponse::success(PtyBridgeInfo { path: bridge.path().to_string() }));
+    }
+
+    match PtyBridge::open(state.connection_manager.clone(), raw) {
+        Ok(bridge) => {
+            let info = PtyBridgeInfo { path: bridge.path().to_string() };
+            let mut closed = bridge.watch_closed();
+            let pty_bridge_state = state.pty_bridge.clone();
+            tokio::spawn(async move {
+                // ブリッジを先に閉じた場合は送信側が破棄されて終了する
+                let reason = match closed.wait_for(|reason| reason.is_some()).await {
+                    Ok(reason) => reason.clone().unwrap_or_default(),
+                    Err(_) => return,
+                };
+                // 後から開き直したブリッジは閉じない
+                let mut pty_bridge = pty_bridge_state.lock().await;
+                if pty_bridge.as_ref().is_some_and(|bridge| bridge.closed_reason().is_some()) {
+                    *pty_bridge = None;
+                    warn!("PTY bridge stopped: {}", reason);
+                    if let Err(e) = app_handle.emit("pty-bridge-closed", &reason) {
+                        warn!("Failed to emit pty-bridge-closed event: {}", e);
+                    }
+                }
+            });
+            *pty_bridge = Some(bridge);
+            Ok(ApiResponse::success(info))
+        }
+        Err(e) => {
+            error!("Failed to open PTY bridge: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn close_pty_bridge(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<String>, String> {
+    match state.pty_bridge.lock().await.take() {
+        Some(_) => Ok(ApiResponse::success("PTY bridge closed".to_string())),
+        None => Ok(ApiResponse::error("PTY bridge is not open".to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_pty_bridge(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<Option<PtyBridgeInfo>>, String> {
+    let pty_bridge = state.pty_bridge.lock().await;
+    Ok(ApiResponse::success(
+        pty_bridge.as_ref().map(|bridge| PtyBridgeInfo { path: bridge.path().to_string() }),
+    ))
+}
\ No newline at end of file
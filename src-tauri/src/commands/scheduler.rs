@@ -0,0 +1,88 @@
+use crate::communication::ConnectionManager;
+use crate::models::{SendJob, SendJobSpec};
+use crate::services::scheduler::JobScheduler;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use super::ApiResponse;
+
+// 定期送信ジョブ。接続中の ConnectionManager を通して送信する。
+// ジョブは保存されず、アプリを終了すると破棄される
+pub struct ScheduleState {
+    pub scheduler: Mutex<JobScheduler<Arc<Mutex<ConnectionManager>>>>,
+}
+
+impl ScheduleState {
+    pub fn new(connection_manager: Arc<Mutex<ConnectionManager>>) -> Self {
+        Self {
+            scheduler: Mutex::new(JobScheduler::new(connection_manager)),
+        }
+    }
+}
+
+// Tauri コマンド
+
+// start を false にすると一時停止状態で追加する。ジョブはアプリを再起動すると残らない
+#[tauri::command]
+pub async fn add_scheduled_job(
+    spec: SendJobSpec,
+    start: Option<bool>,
+    schedule_state: State<'_, ScheduleState>,
+) -> Result<ApiResponse<SendJob>, String> {
+    debug!("Adding scheduled job: {:?}", spec);
+
+    let mut scheduler = schedule_state.scheduler.lock().await;
+    match scheduler.add_job(spec, start.unwrap_or(true)).await {
+        Ok(job) => Ok(ApiResponse::success(job)),
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
+
+#[tauri::command]
+pub async fn list_scheduled_jobs(
+    schedule_state: State<'_, ScheduleState>,
+) -> Result<ApiResponse<Vec<SendJob>>, String> {
+    let scheduler = schedule_state.scheduler.lock().await;
+    Ok(ApiResponse::success(scheduler.list_jobs().await))
+}
+
+#[tauri::command]
+pub async fn pause_scheduled_job(
+    job_id: String,
+    schedule_state: State<'_, ScheduleState>,
+) -> Result<ApiResponse<SendJob>, String> {
+    let mut scheduler = schedule_state.scheduler.lock().await;
+    match scheduler.pause_job(&job_id).await {
+        Ok(job) => {
+            info!("Paused scheduled job '{}'", job.spec.name);
+            Ok(ApiResponse::success(job))
+        }
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
+
+#[tauri::command]
+pub async fn resume_scheduled_job(
+    job_id: String,
+    schedule_state: State<'_, ScheduleState>,
+) -> Result<ApiResponse<SendJob>, String> {
+    let mut scheduler = schedule_state.scheduler.lock().await;
+    match scheduler.resume_job(&job_id).await {
+        Ok(job) => {
+            info!("Resumed scheduled job '{}'", job.spec.name);
+            Ok(ApiResponse::success(job))
+        }
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
+
+#[tauri::command]
+pub async fn remove_scheduled_job(
+    job_id: String,
+    schedule_state: State<'_, ScheduleState>,
+) -> Result<ApiResponse<bool>, String> {
+    let mut scheduler = schedule_state.scheduler.lock().await;
+    Ok(ApiResponse::success(scheduler.remove_job(&job_id).await))
+}
\ No newline at end of file
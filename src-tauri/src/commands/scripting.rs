@@ -0,0 +1,162 @@
+use crate::services::scripting::{self, ScriptContext, ScriptResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tokio::runtime::Handle;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use super::{ApiResponse, AppState, TerminalState};
+
+// 実行中のスクリプト（接続は1つなので同時に1つまで）
+pub struct ScriptState {
+    pub current: Arc<Mutex<Option<ScriptRun>>>,
+}
+
+pub struct ScriptRun {
+    pub run_id: String,
+    cancel: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl ScriptState {
+    pub fn new() -> Self {
+        Self {
+            current: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum ScriptEvent {
+    Output { line: String },
+    Finished { result: ScriptResult },
+}
+
+// "script-progress" イベントのペイロード
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScriptProgress {
+    pub run_id: String,
+    pub event: ScriptEvent,
+}
+
+fn emit_progress(app_handle: &AppHandle, run_id: &str, event: ScriptEvent) {
+    let progress = ScriptProgress {
+        run_id: run_id.to_string(),
+        event,
+    };
+    if let Err(e) = app_handle.emit("script-progress", &progress) {
+        error!("Failed to emit script-progress event: {}", e);
+    }
+}
+
+// Tauri コマンド
+
+// 接続中のセッションで Rhai スクリプトをバックグラウンド実行し、実行IDを返す。
+// 出力と結果は "script-progress" で通知する
+#[tauri::command]
+pub async fn run_script(
+    source: String,
+    app_handle: AppHandle,
+    script_state: State<'_, ScriptState>,
+    app_state: State<'_, AppState>,
+    terminal_state: State<'_, TerminalState>,
+) -> Result<ApiResponse<String>, String> {
+    let mut current = script_state.current.lock().await;
+    if let Some(run) = current.as_ref() {
+        if !run.handle.is_finished() {
+            return Ok(ApiResponse::error("Another script is already running".to_string()));
+        }
+    }
+
+    // 受信データを取りこぼさないよう、開始前に購読しておく
+    let received = {
+        let connection_manager = app_state.connection_manager.lock().await;
+        if !connection_manager.is_connected() {
+            return Ok(ApiResponse::error("Not connected".to_string()));
+        }
+        connection_manager.subscribe()
+    };
+
+    // 接続中プロファイルの改行コード・資格情報・設定値を使う
+    let global = terminal_state.config.lock().await.clone();
+    let context = match app_state.active_profile.lock().await.as_ref() {
+        Some(resolved) => ScriptContext {
+            line_ending: global.with_overrides(&resolved.config.terminal_overrides).line_ending,
+            secrets: resolved.secrets.clone(),
+            profile: Some(resolved.config.clone()),
+        },
+        None => ScriptContext {
+            line_ending: global.line_ending,
+            secrets: HashMap::new(),
+            profile: None,
+        },
+    };
+
+    let run_id = uuid::Uuid::new_v4().to_string();
+    info!("Running script ({})", run_id);
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let target = app_state.connection_manager.clone();
+    let current_run = script_state.current.clone();
+    let task_run_id = run_id.clone();
+    let task_cancel = cancel.clone();
+    let handle = tokio::spawn(async move {
+        let runtime = Handle::current();
+        let output_handle = app_handle.clone();
+        let output_run_id = task_run_id.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            scripting::run_script(&source, target, received, context, task_cancel, runtime, move |line| {
+                emit_progress(&output_handle, &output_run_id, ScriptEvent::Output { line: line.to_string() })
+            })
+        })
+        .await;
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Script task failed: {}", e);
+                ScriptResult {
+                    outcome: scripting::ScriptOutcome::Failed { reason: e.to_string() },
+                    output: Vec::new(),
+                }
+            }
+        };
+        info!("Script {} finished: {:?}", task_run_id, result.outcome);
+        emit_progress(&app_handle, &task_run_id, ScriptEvent::Finished { result });
+
+        let mut current = current_run.lock().await;
+        if current.as_ref().map(|run| run.run_id == task_run_id).unwrap_or(false) {
+            *current = None;
+        }
+    });
+
+    *current = Some(ScriptRun {
+        run_id: run_id.clone(),
+        cancel,
+        handle,
+    });
+    Ok(ApiResponse::success(run_id))
+}
+
+// 実行中のスクリプトに中断を要求する。run_id を省略した場合は実行中のものを対象にする。
+// 結果は Cancelled として "script-progress" で通知される
+#[tauri::command]
+pub async fn cancel_script(
+    run_id: Option<String>,
+    script_state: State<'_, ScriptState>,
+) -> Result<ApiResponse<bool>, String> {
+    let current = script_state.current.lock().await;
+    match current.as_ref() {
+        Some(run) if run_id.as_ref().map(|id| id == &run.run_id).unwrap_or(true) => {
+            run.cancel.store(true, Ordering::SeqCst);
+            info!("Cancelling script run {}", run.run_id);
+            Ok(ApiResponse::success(true))
+        }
+        _ => Ok(ApiResponse::success(false)),
+    }
+}
\ No newline at end of file
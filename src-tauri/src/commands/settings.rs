@@ -1,30 +1,43 @@
-use crate::models::{AppConfig, ProfileManager, ConnectionConfig};
+use crate::communication::SerialHandler;
+use crate::models::{AppConfig, ConflictStrategy, ConnectionConfig, ConnectionType, MergeEntry, MergeSummary, ProfileGroup, ProfileManager};
+use crate::services::import::{self, ImportReport, ImportSource};
+use crate::services::validation::{self, ValidationIssue};
+use crate::services::{DataMasker, SettingsFile, SettingsPersistence};
 // use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tauri::State;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info};
 
-use super::ApiResponse;
+use super::{ApiResponse, AppState, TerminalState, VaultState};
 
 // 設定状態
 pub struct SettingsState {
     pub app_config: Arc<Mutex<AppConfig>>,
     pub profile_manager: Arc<Mutex<ProfileManager>>,
+    pub persistence: Arc<SettingsPersistence>,
 }
 
 impl SettingsState {
-    pub fn new() -> Self {
+    pub fn new(
+        app_config: AppConfig,
+        profile_manager: ProfileManager,
+        persistence: Arc<SettingsPersistence>,
+    ) -> Self {
         Self {
-            app_config: Arc::new(Mutex::new(AppConfig::default())),
-            profile_manager: Arc::new(Mutex::new(ProfileManager::default())),
+            app_config: Arc::new(Mutex::new(app_config)),
+            profile_manager: Arc::new(Mutex::new(profile_manager)),
+            persistence,
         }
     }
-}
 
-impl Default for SettingsState {
-    fn default() -> Self {
-        Self::new()
+    pub fn save_app_config(&self, config: &AppConfig) {
+        self.persistence.schedule_save(SettingsFile::AppConfig, config);
+    }
+
+    pub fn save_profiles(&self, profile_manager: &ProfileManager) {
+        self.persistence.schedule_save(SettingsFile::Profiles, profile_manager);
     }
 }
 
@@ -40,13 +53,57 @@ pub async fn get_app_config(
 
 #[tauri::command]
 pub async fn update_app_config(
-    config: AppConfig,
+    mut config: AppConfig,
     settings_state: State<'_, SettingsState>,
+    app_state: State<'_, AppState>,
+    terminal_state: State<'_, TerminalState>,
+    vault_state: State<'_, VaultState>,
 ) -> Result<ApiResponse<String>, String> {
     debug!("Updating app config");
     
+    // マスキングルールの正規表現を検証
+    let masker = match DataMasker::new(&config.logging) {
+        Ok(masker) => masker,
+        Err(e) => {
+            error!("Invalid masking rule: {}", e);
+            return Ok(ApiResponse::error(e));
+        }
+    };
+    
+    // 履歴の上限と除外ルールを既存のコマンド履歴にも適用する
+    {
+        let mut history = terminal_state.command_history.lock().await;
+        history.set_max_size(config.terminal.max_history_size);
+        let removed = history.remove_matching(|command| masker.excludes_from_history(command));
+        if removed > 0 {
+            info!("Removed {} sensitive commands from history", removed);
+        }
+        settings_state.persistence.schedule_save(SettingsFile::CommandHistory, &*history);
+    }
+    *app_state.data_masker.write().await = masker;
+    
+    // ログ設定をセッションログに反映
+    app_state.session_logger.lock().await.update_config(config.logging.clone());
+    
+    // 資格情報ストアの自動ロック設定を反映
+    vault_state
+        .vault
+        .lock()
+        .await
+        .set_auto_lock_timeout(config.security.auto_lock_timeout_minutes);
+    
+    // ターミナル設定は TerminalState と共有する。接続に使う設定は次の接続から反映する
+    app_state.connection_manager.lock().await.set_default_terminal(config.terminal.clone());
+    terminal_state.messages.lock().await.set_max_size(config.terminal.max_history_size);
+    *terminal_state.config.lock().await = config.terminal.clone();
+    
     let mut current_config = settings_state.app_config.lock().await;
+    // ローカル API の設定は set_api_server_enabled でのみ変更する
+    config.api_server = current_config.api_server.clone();
+    // 強調表示ルールは save_highlight_rules でのみ変更する
+    config.highlight_rules = current_config.highlight_rules.clone();
     *current_config = config;
+    settings_state.save_app_config(&current_config);
     
     info!("App config updated successfully");
     Ok(ApiResponse::success("App config updated".to_string()))
@@ -69,6 +126,7 @@ pub async fn add_profile(
     
     let mut profile_manager = settings_state.profile_manager.lock().await;
     profile_manager.add_profile(profile);
+    settings_state.save_profiles(&profile_manager);
     
     info!("Profile added successfully");
     Ok(ApiResponse::success("Profile added".to_string()))
@@ -85,6 +143,7 @@ pub async fn update_profile(
     
     if let Some(existing_profile) = profile_manager.get_profile_mut(&profile.id) {
         *existing_profile = profile;
+        settings_state.save_profiles(&profile_manager);
         info!("Profile updated successfully");
         Ok(ApiResponse::success("Profile updated".to_string()))
     } else {
@@ -103,6 +162,7 @@ pub async fn delete_profile(
     let mut profile_manager = settings_state.profile_manager.lock().await;
     
     if profile_manager.remove_profile(&profile_id) {
+        settings_state.save_profiles(&profile_manager);
         info!("Profile deleted successfully");
         Ok(ApiResponse::success("Profile deleted".to_string()))
     } else {
@@ -131,6 +191,7 @@ pub async fn set_active_profile(
     
     if profile_manager.get_profile(&profile_id).is_some() {
         profile_manager.set_active_profile(profile_id);
+        settings_state.save_profiles(&profile_manager);
         info!("Active profile set successfully");
         Ok(ApiResponse::success("Active profile set".to_string()))
     } else {
@@ -176,6 +237,7 @@ pub async fn duplicate_profile(
         new_profile.updated_at = chrono::Utc::now();
         
         profile_manager.add_profile(new_profile.clone());
+        settings_state.save_profiles(&profile_manager);
         
         info!("Profile duplicated successfully");
         Ok(ApiResponse::success(new_profile))
@@ -236,52 +298,321 @@ pub async fn import_profiles(
         profile_manager.add_profile(profile);
         imported_count += 1;
     }
+    // 置き換えた場合はグループが存在しないプロファイルを参照したままになる
+    profile_manager.repair_groups();
+    settings_state.save_profiles(&profile_manager);
     
     info!("Imported {} profiles", imported_count);
     Ok(ApiResponse::success(format!("Imported {} profiles", imported_count)))
 }
 
+// チームで共有しているファイルなどを既存のプロファイルとマージする前の確認
+#[tauri::command]
+pub async fn preview_profile_merge(
+    profiles_json: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<MergeEntry>>, String> {
+    info!("Previewing profile merge");
+    
+    let incoming: Vec<ConnectionConfig> = match serde_json::from_str(&profiles_json) {
+        Ok(profiles) => profiles,
+        Err(e) => return Ok(ApiResponse::error(format!("Invalid JSON: {}", e))),
+    };
+    
+    let profile_manager = settings_state.profile_manager.lock().await;
+    Ok(ApiResponse::success(profile_manager.preview_merge(&incoming)))
+}
+
+// resolutions には競合したプロファイルの（取り込む側の）IDごとに解決方法を指定する
+#[tauri::command]
+pub async fn merge_profiles(
+    profiles_json: String,
+    resolutions: HashMap<String, ConflictStrategy>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<MergeSummary>, String> {
+    info!("Merging profiles ({} resolutions)", resolutions.len());
+    
+    let incoming: Vec<ConnectionConfig> = match serde_json::from_str(&profiles_json) {
+        Ok(profiles) => profiles,
+        Err(e) => return Ok(ApiResponse::error(format!("Invalid JSON: {}", e))),
+    };
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    match profile_manager.apply_merge(incoming, &resolutions) {
+        Ok(summary) => {
+            settings_state.save_profiles(&profile_manager);
+            info!("Merged profiles: {:?}", summary);
+            Ok(ApiResponse::success(summary))
+        }
+        Err(e) => {
+            error!("Failed to merge profiles: {}", e);
+            Ok(ApiResponse::error(e))
+        }
+    }
+}
+
+// 他のターミナルソフトのセッション設定を取り込む
+#[tauri::command]
+pub async fn import_external_sessions(
+    path: String,
+    source: Option<ImportSource>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<ImportReport>, String> {
+    info!("Importing external sessions from {} ({:?})", path, source);
+    
+    let mut report = match import::import_file(std::path::Path::new(&path), source) {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to import sessions: {}", e);
+            return Ok(ApiResponse::error(format!("Import failed: {}", e)));
+        }
+    };
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    // 取り込み済みのセッション（同じ名前で同じ接続先）は追加しない
+    let mut already_imported = Vec::new();
+    report.profiles.retain(|imported| {
+        if profile_manager.find_same_profile(&imported.profile).is_some() {
+            already_imported.push(format!("{}: already imported", imported.source_name));
+            return false;
+        }
+        profile_manager.add_profile(imported.profile.clone());
+        true
+    });
+    report.skipped.extend(already_imported);
+    settings_state.save_profiles(&profile_manager);
+    
+    info!(
+        "Imported {} sessions ({} skipped)",
+        report.profiles.len(),
+        report.skipped.len()
+    );
+    Ok(ApiResponse::success(report))
+}
+
+// プロファイルグループ
+
+#[tauri::command]
+pub async fn get_profile_groups(
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<ProfileGroup>>, String> {
+    let profile_manager = settings_state.profile_manager.lock().await;
+    let groups = profile_manager.ordered_groups().into_iter().cloned().collect();
+    Ok(ApiResponse::success(groups))
+}
+
+#[tauri::command]
+pub async fn get_group_profiles(
+    group_id: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<ConnectionConfig>>, String> {
+    let profile_manager = settings_state.profile_manager.lock().await;
+    
+    if !profile_manager.groups.contains_key(&group_id) {
+        return Ok(ApiResponse::error("Group not found".to_string()));
+    }
+    
+    let profiles = profile_manager.get_profiles_by_group(&group_id).into_iter().cloned().collect();
+    Ok(ApiResponse::success(profiles))
+}
+
+#[tauri::command]
+pub async fn create_profile_group(
+    name: String,
+    description: Option<String>,
+    color: Option<String>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<ProfileGroup>, String> {
+    debug!("Creating profile group: {}", name);
+    
+    if name.trim().is_empty() {
+        return Ok(ApiResponse::error("Group name must not be empty".to_string()));
+    }
+    
+    let group = ProfileGroup {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        description,
+        profile_ids: Vec::new(),
+        color,
+    };
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    profile_manager.add_group(group.clone());
+    settings_state.save_profiles(&profile_manager);
+    
+    info!("Profile group created: {}", group.id);
+    Ok(ApiResponse::success(group))
+}
+
+#[tauri::command]
+pub async fn rename_profile_group(
+    group_id: String,
+    name: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Renaming profile group: {} -> {}", group_id, name);
+    
+    if name.trim().is_empty() {
+        return Ok(ApiResponse::error("Group name must not be empty".to_string()));
+    }
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    
+    if profile_manager.rename_group(&group_id, name.trim().to_string()) {
+        settings_state.save_profiles(&profile_manager);
+        info!("Profile group renamed successfully");
+        Ok(ApiResponse::success("Group renamed".to_string()))
+    } else {
+        error!("Group not found: {}", group_id);
+        Ok(ApiResponse::error("Group not found".to_string()))
+    }
+}
+
+#[tauri::command]
+pub async fn delete_profile_group(
+    group_id: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Deleting profile group: {}", group_id);
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    
+    // グループに属していたプロファイルは削除せず、未分類になる
+    if profile_manager.remove_group(&group_id) {
+        settings_state.save_profiles(&profile_manager);
+        info!("Profile group deleted successfully");
+        Ok(ApiResponse::success("Group deleted".to_string()))
+    } else {
+        error!("Group not found: {}", group_id);
+        Ok(ApiResponse::error("Group not found".to_string()))
+    }
+}
+
+#[tauri::command]
+pub async fn reorder_profile_groups(
+    group_ids: Vec<String>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Reordering profile groups: {:?}", group_ids);
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    
+    match profile_manager.reorder_groups(group_ids) {
+        Ok(_) => {
+            settings_state.save_profiles(&profile_manager);
+            Ok(ApiResponse::success("Groups reordered".to_string()))
+        }
+        Err(e) => {
+            error!("Failed to reorder groups: {}", e);
+            Ok(ApiResponse::error(e))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn move_profile_to_group(
+    profile_id: String,
+    group_id: Option<String>,
+    position: Option<usize>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Moving profile {} to group {:?}", profile_id, group_id);
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    
+    match profile_manager.move_profile_to_group(&profile_id, group_id.as_deref(), position) {
+        Ok(_) => {
+            settings_state.save_profiles(&profile_manager);
+            Ok(ApiResponse::success("Profile moved".to_string()))
+        }
+        Err(e) => {
+            error!("Failed to move profile: {}", e);
+            Ok(ApiResponse::error(e))
+        }
+    }
+}
+
+// プロファイルのタグと検索
+
+#[tauri::command]
+pub async fn set_profile_tags(
+    profile_id: String,
+    tags: Vec<String>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Setting tags for profile {}: {:?}", profile_id, tags);
+    
+    let mut profile_manager = settings_state.profile_manager.lock().await;
+    
+    if profile_manager.set_profile_tags(&profile_id, tags) {
+        settings_state.save_profiles(&profile_manager);
+        Ok(ApiResponse::success("Tags updated".to_string()))
+    } else {
+        error!("Profile not found: {}", profile_id);
+        Ok(ApiResponse::error("Profile not found".to_string()))
+    }
+}
+
+#[tauri::command]
+pub async fn get_profile_tags(
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<String>>, String> {
+    let profile_manager = settings_state.profile_manager.lock().await;
+    Ok(ApiResponse::success(profile_manager.all_tags()))
+}
+
+#[tauri::command]
+pub async fn search_profiles(
+    query: String,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<Vec<ConnectionConfig>>, String> {
+    debug!("Searching profiles: {}", query);
+    
+    let profile_manager = settings_state.profile_manager.lock().await;
+    let results = profile_manager.search_profiles(&query).into_iter().cloned().collect();
+    Ok(ApiResponse::success(results))
+}
+
 // プロファイルバリデーション
+// 結果はフォームの各項目に表示できるよう、項目ごとの {field, code, severity, message} で返す
 #[tauri::command]
 pub async fn validate_profile(
     profile: ConnectionConfig,
-) -> Result<ApiResponse<Vec<String>>, String> {
+    settings_state: State<'_, SettingsState>,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<Vec<ValidationIssue>>, String> {
     debug!("Validating profile: {}", profile.name);
     
-    let mut errors = Vec::new();
+    let available_ports = if profile.connection_type == ConnectionType::Serial {
+        SerialHandler::list_available_ports().await.ok()
+    } else {
+        None
+    };
     
-    // 名前チェック
-    if profile.name.trim().is_empty() {
-        errors.push("プロファイル名を入力してください".to_string());
-    }
+    let mut issues = {
+        let profile_manager = settings_state.profile_manager.lock().await;
+        validation::validate_profile(&profile, &profile_manager.profiles, available_ports.as_deref())
+    };
     
-    // 接続設定チェック
-    match profile.connection_type {
-        crate::models::ConnectionType::Serial => {
-            if let Some(serial_config) = &profile.serial_config {
-                if serial_config.port.trim().is_empty() {
-                    errors.push("シリアルポートを選択してください".to_string());
-                }
-                if serial_config.baud_rate == 0 {
-                    errors.push("有効なボーレートを入力してください".to_string());
-                }
-            } else {
-                errors.push("シリアル設定が見つかりません".to_string());
-            }
+    // 書式が正しい場合のみ名前解決を試す
+    if let Some(tcp_config) = profile.tcp_config.as_ref().filter(|_| profile.connection_type == ConnectionType::Tcp) {
+        if !issues.iter().any(|issue| issue.field == "tcp_config.host") {
+            issues.extend(validation::check_host_resolves(&tcp_config.host, tcp_config.port).await);
         }
-        crate::models::ConnectionType::Tcp => {
-            if let Some(tcp_config) = &profile.tcp_config {
-                if tcp_config.host.trim().is_empty() {
-                    errors.push("ホストアドレスを入力してください".to_string());
-                }
-                if tcp_config.port == 0 {
-                    errors.push("有効なポート番号（1-65535）を入力してください".to_string());
-                }
-            } else {
-                errors.push("TCP設定が見つかりません".to_string());
-            }
+    }
+    
+    // 参照している秘密情報が存在するか
+    let vault = vault_state.vault.lock().await;
+    for (index, secret_ref) in profile.secret_refs.iter().enumerate() {
+        if !vault.contains_secret(&secret_ref.secret_id) {
+            issues.push(ValidationIssue::error(
+                &format!("secret_refs[{}]", index),
+                "secret_not_found",
+                format!("Referenced secret '{}' was not found", secret_ref.secret_id),
+            ));
         }
     }
     
-    Ok(ApiResponse::success(errors))
+    Ok(ApiResponse::success(issues))
 }
\ No newline at end of file
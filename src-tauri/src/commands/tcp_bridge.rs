@@ -0,0 +1,114 @@
+use crate::models::{BridgeClient, ClientRole, TcpBridgeConfig};
+use crate::services::tcp_bridge::TcpBridge;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{error, info, warn};
+
+use super::{ApiResponse, AppState};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TcpBridgeStatus {
+    pub config: TcpBridgeConfig,
+    pub address: String,
+    pub clients: Vec<BridgeClient>,
+}
+
+fn bridge_status(bridge: &TcpBridge) -> TcpBridgeStatus {
+    TcpBridgeStatus {
+        config: bridge.config().clone(),
+        address: bridge.address().to_string(),
+        clients: bridge.clients(),
+    }
+}
+
+// Tauri コマンド
+
+// 接続中のセッションを TCP で公開する。起動中のブリッジがあれば停止してから起動し直す。
+// クライアントの一覧が変わるたびに bridge-clients-changed イベントを送る
+#[tauri::command]
+pub async fn start_tcp_bridge(
+    config: TcpBridgeConfig,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<TcpBridgeStatus>, String> {
+    if let Err(e) = config.validate() {
+        return Ok(ApiResponse::error(e));
+    }
+
+    // 切断処理とロックの順序が逆にならないよう、connection_manager を解放してから tcp_bridge をロックする
+    let (serial, raw) = {
+        let connection_manager = state.connection_manager.lock().await;
+        if !connection_manager.is_connected() {
+            return Ok(ApiResponse::error("Not connected".to_string()));
+        }
+        (connection_manager.serial_config().cloned(), connection_manager.subscribe_raw())
+    };
+
+    let mut tcp_bridge = state.tcp_bridge.lock().await;
+    *tcp_bridge = None;
+
+    let bridge = match TcpBridge::start(config.clone(), serial, state.connection_manager.clone(), raw).await {
+        Ok(bridge) => bridge,
+        Err(e) => {
+            error!("Failed to start TCP bridge on port {}: {}", config.port, e);
+            return Ok(ApiResponse::error(format!(
+                "Failed to start TCP bridge on port {}: {}",
+                config.port, e
+            )));
+        }
+    };
+
+    let mut clients = bridge.watch_clients();
+    tokio::spawn(async move {
+        while clients.changed().await.is_ok() {
+            let current = clients.borrow_and_update().clone();
+            if let Err(e) = app_handle.emit("bridge-clients-changed", &current) {
+                warn!("Failed to emit bridge clients: {}", e);
+            }
+        }
+    });
+
+    let status = bridge_status(&bridge);
+    *tcp_bridge = Some(bridge);
+    Ok(ApiResponse::success(status))
+}
+
+#[tauri::command]
+pub async fn stop_tcp_bridge(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<String>, String> {
+    match state.tcp_bridge.lock().await.take() {
+        Some(bridge) => {
+            bridge.stop();
+            Ok(ApiResponse::success("TCP bridge stopped".to_string()))
+        }
+        None => Ok(ApiResponse::error("TCP bridge is not running".to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_tcp_bridge_status(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<Option<TcpBridgeStatus>>, String> {
+    let tcp_bridge = state.tcp_bridge.lock().await;
+    Ok(ApiResponse::success(tcp_bridge.as_ref().map(bridge_status)))
+}
+
+#[tauri::command]
+pub async fn set_bridge_client_role(
+    client_id: String,
+    role: ClientRole,
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<BridgeClient>, String> {
+    let tcp_bridge = state.tcp_bridge.lock().await;
+    let Some(bridge) = tcp_bridge.as_ref() else {
+        return Ok(ApiResponse::error("TCP bridge is not running".to_string()));
+    };
+    match bridge.set_client_role(&client_id, role) {
+        Ok(client) => {
+            info!("Bridge client {} ({}) is now {:?}", client.id, client.address, client.role);
+            Ok(ApiResponse::success(client))
+        }
+        Err(e) => Ok(ApiResponse::error(e)),
+    }
+}
\ No newline at end of file
@@ -1,25 +1,36 @@
-use crate::models::{TerminalConfig, TerminalMessage, CommandHistory};
+use crate::models::{TerminalConfig, TerminalMessage, CommandHistory, HistoryEntry, MessageDirection, MessageStore};
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use tauri::State;
+use std::ops::Bound;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
-use super::ApiResponse;
+use crate::services::{LogFileInfo, SettingsFile};
+
+use super::{ApiResponse, AppState, SettingsState};
 
 // ターミナル状態
 pub struct TerminalState {
     pub config: Arc<Mutex<TerminalConfig>>,
-    pub messages: Arc<Mutex<Vec<TerminalMessage>>>,
+    pub messages: Arc<Mutex<MessageStore>>,
     pub command_history: Arc<Mutex<CommandHistory>>,
 }
 
 impl TerminalState {
     pub fn new() -> Self {
+        Self::with_config(TerminalConfig::default(), CommandHistory::default())
+    }
+
+    pub fn with_config(config: TerminalConfig, mut command_history: CommandHistory) -> Self {
+        let messages = MessageStore::new(config.max_history_size);
+        command_history.set_max_size(config.max_history_size);
         Self {
-            config: Arc::new(Mutex::new(TerminalConfig::default())),
-            messages: Arc::new(Mutex::new(Vec::new())),
-            command_history: Arc::new(Mutex::new(CommandHistory::default())),
+            config: Arc::new(Mutex::new(config)),
+            messages: Arc::new(Mutex::new(messages)),
+            command_history: Arc::new(Mutex::new(command_history)),
         }
     }
 }
@@ -31,15 +42,152 @@ impl Default for TerminalState {
 }
 
 // メッセージフィルター
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MessageFilter {
-    pub direction: Option<String>, // "sent" | "received"
-    pub start_time: Option<String>,
-    pub end_time: Option<String>,
+    pub direction: Option<String>, // "sent" | "received" | "system"
+    pub start_time: Option<String>, // RFC 3339
+    pub end_time: Option<String>,   // RFC 3339
     pub search_query: Option<String>,
+    #[serde(default)]
+    pub use_regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    pub before_id: Option<String>, // このメッセージより前を取得
+    pub after_id: Option<String>,  // このメッセージより後を取得
     pub limit: Option<usize>,
 }
 
+// ページ単位のメッセージ取得結果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessagePage {
+    pub messages: Vec<TerminalMessage>,
+    pub has_more: bool, // 取得方向にまだ該当メッセージが残っているか
+}
+
+// 検索クエリの照合方法
+enum QueryMatcher {
+    Regex(Regex),
+    Plain { query: String, case_sensitive: bool },
+}
+
+// フィルター条件をあらかじめ解析しておき、メッセージごとの判定を軽くする
+struct MessageMatcher {
+    direction: Option<MessageDirection>,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    query: Option<QueryMatcher>,
+}
+
+impl MessageMatcher {
+    fn from_filter(filter: &MessageFilter) -> Result<Self, String> {
+        let direction = match filter.direction.as_deref() {
+            Some("sent") => Some(MessageDirection::Sent),
+            Some("received") => Some(MessageDirection::Received),
+            Some("system") => Some(MessageDirection::System),
+            _ => None,
+        };
+
+        let query = match &filter.search_query {
+            Some(query) if !query.is_empty() => {
+                if filter.use_regex {
+                    let regex = RegexBuilder::new(query)
+                        .case_insensitive(!filter.case_sensitive)
+                        .build()
+                        .map_err(|e| format!("Invalid regex: {}", e))?;
+                    Some(QueryMatcher::Regex(regex))
+                } else if filter.case_sensitive {
+                    Some(QueryMatcher::Plain { query: query.clone(), case_sensitive: true })
+                } else {
+                    Some(QueryMatcher::Plain { query: query.to_lowercase(), case_sensitive: false })
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            direction,
+            start_time: parse_time(filter.start_time.as_deref())?,
+            end_time: parse_time(filter.end_time.as_deref())?,
+            query,
+        })
+    }
+
+    fn matches(&self, message: &TerminalMessage) -> bool {
+        if let Some(direction) = &self.direction {
+            if &message.direction != direction {
+                return false;
+            }
+        }
+
+        if let Some(start_time) = &self.start_time {
+            if &message.timestamp < start_time {
+                return false;
+            }
+        }
+
+        if let Some(end_time) = &self.end_time {
+            if &message.timestamp > end_time {
+                return false;
+            }
+        }
+
+        match &self.query {
+            Some(QueryMatcher::Regex(regex)) => regex.is_match(&message.content),
+            Some(QueryMatcher::Plain { query, case_sensitive: true }) => message.content.contains(query.as_str()),
+            Some(QueryMatcher::Plain { query, case_sensitive: false }) => {
+                message.content.to_lowercase().contains(query.as_str())
+            }
+            None => true,
+        }
+    }
+}
+
+fn parse_time(value: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    match value {
+        Some(value) if !value.is_empty() => DateTime::parse_from_rfc3339(value)
+            .map(|time| Some(time.with_timezone(&Utc)))
+            .map_err(|e| format!("Invalid RFC 3339 time '{}': {}", value, e)),
+        _ => Ok(None),
+    }
+}
+
+// フィルターとカーソルに従ってストアからメッセージを取得する。
+// 該当するメッセージだけを複製するため、ストア全体はコピーしない
+fn query_messages(store: &MessageStore, filter: &MessageFilter) -> Result<MessagePage, String> {
+    let matcher = MessageMatcher::from_filter(filter)?;
+    let limit = filter.limit.unwrap_or(usize::MAX);
+
+    let find_cursor = |id: &str| {
+        store
+            .position(id)
+            .ok_or_else(|| format!("Cursor message not found: {}", id))
+    };
+
+    let (mut messages, has_more, reversed) = if let Some(after_id) = &filter.after_id {
+        // カーソルより後を古い順に取得
+        let start = find_cursor(after_id)? + 1;
+        let mut iter = store.range(start..).filter(|m| matcher.matches(m));
+        let messages: Vec<TerminalMessage> = iter.by_ref().take(limit).cloned().collect();
+        (messages, iter.next().is_some(), false)
+    } else {
+        // カーソル（指定がなければ末尾）より前を新しい順に取得
+        let end = match &filter.before_id {
+            Some(before_id) => Bound::Excluded(find_cursor(before_id)?),
+            None => Bound::Unbounded,
+        };
+        let mut iter = store.range((Bound::Unbounded, end)).rev().filter(|m| matcher.matches(m));
+        let messages: Vec<TerminalMessage> = iter.by_ref().take(limit).cloned().collect();
+        (messages, iter.next().is_some(), true)
+    };
+
+    // 常に古い順で返す
+    if reversed {
+        messages.reverse();
+    }
+
+    Ok(MessagePage { messages, has_more })
+}
+
 // エクスポートオプション
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportOptions {
@@ -63,63 +211,88 @@ pub async fn get_terminal_config(
 pub async fn update_terminal_config(
     config: TerminalConfig,
     terminal_state: State<'_, TerminalState>,
+    settings_state: State<'_, SettingsState>,
+    app_state: State<'_, AppState>,
 ) -> Result<ApiResponse<String>, String> {
     debug!("Updating terminal config");
     
     let mut current_config = terminal_state.config.lock().await;
+    
+    // 最大履歴サイズをメッセージストアとコマンド履歴にも反映
+    terminal_state.messages.lock().await.set_max_size(config.max_history_size);
+    terminal_state.command_history.lock().await.set_max_size(config.max_history_size);
+    
+    // アプリ設定の一部として保存
+    let mut app_config = settings_state.app_config.lock().await;
+    app_config.terminal = config.clone();
+    settings_state.save_app_config(&app_config);
+    
+    // 文字コード・改行コード・区切り方などは次の接続から反映する
+    app_state.connection_manager.lock().await.set_default_terminal(config.clone());
+    
     *current_config = config;
     
     info!("Terminal config updated successfully");
     Ok(ApiResponse::success("Terminal config updated".to_string()))
 }
 
+// セッションで実際に使われる端末設定（全体の設定 + プロファイルの上書き）。
+// profile_id を省略した場合は現在の接続について返す
+#[tauri::command]
+pub async fn get_effective_terminal_config(
+    profile_id: Option<String>,
+    terminal_state: State<'_, TerminalState>,
+    app_state: State<'_, AppState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<ApiResponse<TerminalConfig>, String> {
+    let global = terminal_state.config.lock().await.clone();
+    
+    let overrides = match profile_id {
+        Some(profile_id) => {
+            let profile_manager = settings_state.profile_manager.lock().await;
+            match profile_manager.get_profile(&profile_id) {
+                Some(profile) => Some(profile.terminal_overrides.clone()),
+                None => return Ok(ApiResponse::error(format!("Profile not found: {}", profile_id))),
+            }
+        }
+        None => app_state
+            .active_profile
+            .lock()
+            .await
+            .as_ref()
+            .map(|resolved| resolved.config.terminal_overrides.clone()),
+    };
+    
+    let effective = match overrides {
+        Some(overrides) => global.with_overrides(&overrides),
+        None => global,
+    };
+    Ok(ApiResponse::success(effective))
+}
+
 #[tauri::command]
 pub async fn get_terminal_messages(
     filter: Option<MessageFilter>,
     terminal_state: State<'_, TerminalState>,
-) -> Result<ApiResponse<Vec<TerminalMessage>>, String> {
+    app_state: State<'_, AppState>,
+) -> Result<ApiResponse<MessagePage>, String> {
     debug!("Getting terminal messages with filter: {:?}", filter);
     
+    let filter = filter.unwrap_or_default();
     let messages = terminal_state.messages.lock().await;
-    let mut filtered_messages = messages.clone();
     
-    // フィルタリング適用
-    if let Some(filter) = filter {
-        // 方向フィルター
-        if let Some(direction) = &filter.direction {
-            filtered_messages.retain(|msg| {
-                match direction.as_str() {
-                    "sent" => matches!(msg.direction, crate::models::MessageDirection::Sent),
-                    "received" => matches!(msg.direction, crate::models::MessageDirection::Received),
-                    _ => true,
-                }
-            });
-        }
-        
-        // 検索クエリフィルター
-        if let Some(query) = &filter.search_query {
-            let query_lower = query.to_lowercase();
-            filtered_messages.retain(|msg| {
-                msg.content.to_lowercase().contains(&query_lower)
-            });
-        }
-        
-        // 時間範囲フィルター（簡易実装）
-        if filter.start_time.is_some() || filter.end_time.is_some() {
-            // 実装が必要な場合は chrono を使用して時間比較
-        }
-        
-        // リミット適用
-        if let Some(limit) = filter.limit {
-            if filtered_messages.len() > limit {
-                let start_index = filtered_messages.len() - limit;
-                filtered_messages = filtered_messages[start_index..].to_vec();
+    match query_messages(&messages, &filter) {
+        Ok(mut page) => {
+            let masker = app_state.data_masker.read().await;
+            if masker.applies_to_live_view() {
+                masker.mask_messages_by_line(&mut page.messages);
             }
+            
+            debug!("Returning {} filtered messages", page.messages.len());
+            Ok(ApiResponse::success(page))
         }
+        Err(e) => Ok(ApiResponse::error(e)),
     }
-    
-    debug!("Returning {} filtered messages", filtered_messages.len());
-    Ok(ApiResponse::success(filtered_messages))
 }
 
 #[tauri::command]
@@ -130,17 +303,10 @@ pub async fn add_terminal_message(
     debug!("Adding terminal message: {:?}", message.id);
     
     let mut messages = terminal_state.messages.lock().await;
-    let config = terminal_state.config.lock().await;
     
-    // メッセージを追加
+    // メッセージを追加（最大履歴サイズはストア側で管理）
     messages.push(message);
     
-    // 最大履歴サイズを超えた場合は古いものを削除
-    if messages.len() > config.max_history_size {
-        let remove_count = messages.len() - config.max_history_size;
-        messages.drain(0..remove_count);
-    }
-    
     Ok(ApiResponse::success("Message added".to_string()))
 }
 
@@ -156,23 +322,45 @@ pub async fn clear_terminal_messages(
     Ok(ApiResponse::success("Messages cleared".to_string()))
 }
 
+// profile_id を省略した場合は全プロファイルをまとめた履歴を返す
 #[tauri::command]
 pub async fn get_command_history(
+    profile_id: Option<String>,
     terminal_state: State<'_, TerminalState>,
-) -> Result<ApiResponse<Vec<String>>, String> {
+) -> Result<ApiResponse<Vec<HistoryEntry>>, String> {
     let history = terminal_state.command_history.lock().await;
-    Ok(ApiResponse::success(history.commands.clone()))
+    Ok(ApiResponse::success(history.entries_for(profile_id.as_deref())))
 }
 
+// profile_id を省略した場合は接続中のプロファイルの履歴に追加する
 #[tauri::command]
 pub async fn add_command_to_history(
     command: String,
+    profile_id: Option<String>,
     terminal_state: State<'_, TerminalState>,
+    app_state: State<'_, AppState>,
+    settings_state: State<'_, SettingsState>,
 ) -> Result<ApiResponse<String>, String> {
+    // パスワードなどを含むコマンドは履歴に残さない
+    if app_state.data_masker.read().await.excludes_from_history(&command) {
+        debug!("Skipping sensitive command for history");
+        return Ok(ApiResponse::success("Command not recorded".to_string()));
+    }
     debug!("Adding command to history: {}", command);
     
+    let profile_id = match profile_id {
+        Some(profile_id) => Some(profile_id),
+        None => app_state
+            .active_profile
+            .lock()
+            .await
+            .as_ref()
+            .map(|resolved| resolved.config.id.clone()),
+    };
+    
     let mut history = terminal_state.command_history.lock().await;
-    history.add_command(command);
+    history.add_command(command, profile_id.as_deref());
+    settings_state.persistence.schedule_save(SettingsFile::CommandHistory, &*history);
     
     Ok(ApiResponse::success("Command added to history".to_string()))
 }
@@ -180,48 +368,37 @@ pub async fn add_command_to_history(
 #[tauri::command]
 pub async fn search_command_history(
     query: String,
+    profile_id: Option<String>,
     terminal_state: State<'_, TerminalState>,
-) -> Result<ApiResponse<Vec<String>>, String> {
+) -> Result<ApiResponse<Vec<HistoryEntry>>, String> {
     debug!("Searching command history: {}", query);
     
     let history = terminal_state.command_history.lock().await;
-    let results: Vec<String> = history.search(&query)
-        .into_iter()
-        .cloned()
-        .collect();
-    
-    Ok(ApiResponse::success(results))
+    Ok(ApiResponse::success(history.search(&query, profile_id.as_deref())))
 }
 
 #[tauri::command]
 pub async fn export_terminal_messages(
     options: ExportOptions,
     terminal_state: State<'_, TerminalState>,
+    app_state: State<'_, AppState>,
 ) -> Result<ApiResponse<String>, String> {
     info!("Exporting terminal messages with format: {}", options.format);
     
     let messages = terminal_state.messages.lock().await;
-    let mut export_messages = messages.clone();
     
     // フィルター適用
-    if let Some(filter) = &options.filter {
-        if let Some(direction) = &filter.direction {
-            export_messages.retain(|msg| {
-                match direction.as_str() {
-                    "sent" => matches!(msg.direction, crate::models::MessageDirection::Sent),
-                    "received" => matches!(msg.direction, crate::models::MessageDirection::Received),
-                    _ => true,
-                }
-            });
-        }
-        
-        if let Some(query) = &filter.search_query {
-            let query_lower = query.to_lowercase();
-            export_messages.retain(|msg| {
-                msg.content.to_lowercase().contains(&query_lower)
-            });
-        }
-    }
+    let export_messages = match &options.filter {
+        Some(filter) => match query_messages(&messages, filter) {
+            Ok(page) => page.messages,
+            Err(e) => return Ok(ApiResponse::error(e)),
+        },
+        None => messages.iter().cloned().collect(),
+    };
+    drop(messages);
+    
+    // 機密情報をマスク
+    let export_messages = app_state.data_masker.read().await.mask_transcript(export_messages);
     
     // フォーマットに応じてエクスポート
     let exported_data = match options.format.as_str() {
@@ -237,6 +414,29 @@ pub async fn export_terminal_messages(
     }
 }
 
+#[tauri::command]
+pub async fn get_session_log_files(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<Vec<LogFileInfo>>, String> {
+    let session_logger = state.session_logger.lock().await;
+    
+    match session_logger.list_log_files() {
+        Ok(files) => Ok(ApiResponse::success(files)),
+        Err(e) => {
+            error!("Failed to list session log files: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_session_log_dir(
+    state: State<'_, AppState>,
+) -> Result<ApiResponse<String>, String> {
+    let session_logger = state.session_logger.lock().await;
+    Ok(ApiResponse::success(session_logger.log_dir().to_string_lossy().to_string()))
+}
+
 // エクスポート関数
 
 fn export_as_text(messages: &[TerminalMessage], options: &ExportOptions) -> Result<String, String> {
@@ -253,6 +453,7 @@ fn export_as_text(messages: &[TerminalMessage], options: &ExportOptions) -> Resu
             let direction = match message.direction {
                 crate::models::MessageDirection::Sent => "送信",
                 crate::models::MessageDirection::Received => "受信",
+                crate::models::MessageDirection::System => "システム",
             };
             line.push_str(&format!("{}: ", direction));
         }
@@ -295,6 +496,7 @@ fn export_as_csv(messages: &[TerminalMessage], options: &ExportOptions) -> Resul
             let direction = match message.direction {
                 crate::models::MessageDirection::Sent => "送信",
                 crate::models::MessageDirection::Received => "受信",
+                crate::models::MessageDirection::System => "システム",
             };
             row.push(direction.to_string());
         }
@@ -335,6 +537,7 @@ mod tests {
             direction,
             timestamp: Utc::now(),
             encoding: "UTF-8".to_string(),
+            highlights: Vec::new(),
         }
     }
 
@@ -357,6 +560,7 @@ mod tests {
             },
             max_history_size: 1000,
             auto_scroll: true,
+            framing: Default::default(),
         }
     }
 
@@ -379,6 +583,10 @@ mod tests {
             start_time: Some("2024-01-01T00:00:00Z".to_string()),
             end_time: Some("2024-12-31T23:59:59Z".to_string()),
             search_query: Some("test".to_string()),
+            use_regex: false,
+            case_sensitive: false,
+            before_id: None,
+            after_id: None,
             limit: Some(100),
         };
         
@@ -435,7 +643,8 @@ mod tests {
         }
         
         // メッセージを取得
-        let messages = state.messages.lock().await;
+        let store = state.messages.lock().await;
+        let messages: Vec<&TerminalMessage> = store.iter().collect();
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0].content, "Hello");
         assert_eq!(messages[1].content, "World");
@@ -465,6 +674,116 @@ mod tests {
         assert!(text.contains("受信: World"));
     }
 
+    fn create_test_store(contents: &[(&str, MessageDirection)]) -> MessageStore {
+        let mut store = MessageStore::new(100);
+        for (content, direction) in contents {
+            store.push(create_test_message(content, direction.clone()));
+        }
+        store
+    }
+
+    fn contents(page: &MessagePage) -> Vec<&str> {
+        page.messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_query_messages_tail_with_limit() {
+        let store = create_test_store(&[
+            ("one", MessageDirection::Sent),
+            ("two", MessageDirection::Received),
+            ("three", MessageDirection::Received),
+        ]);
+        let filter = MessageFilter { limit: Some(2), ..Default::default() };
+
+        let page = query_messages(&store, &filter).unwrap();
+
+        assert_eq!(contents(&page), vec!["two", "three"]);
+        assert!(page.has_more);
+    }
+
+    #[test]
+    fn test_query_messages_cursor_pagination() {
+        let store = create_test_store(&[
+            ("a", MessageDirection::Received),
+            ("b", MessageDirection::Received),
+            ("c", MessageDirection::Received),
+            ("d", MessageDirection::Received),
+        ]);
+        let ids: Vec<String> = store.iter().map(|m| m.id.clone()).collect();
+
+        let before = MessageFilter { before_id: Some(ids[2].clone()), limit: Some(1), ..Default::default() };
+        let page = query_messages(&store, &before).unwrap();
+        assert_eq!(contents(&page), vec!["b"]);
+        assert!(page.has_more);
+
+        let after = MessageFilter { after_id: Some(ids[1].clone()), limit: Some(5), ..Default::default() };
+        let page = query_messages(&store, &after).unwrap();
+        assert_eq!(contents(&page), vec!["c", "d"]);
+        assert!(!page.has_more);
+
+        let missing = MessageFilter { after_id: Some("missing".to_string()), ..Default::default() };
+        assert!(query_messages(&store, &missing).is_err());
+    }
+
+    #[test]
+    fn test_query_messages_search_options() {
+        let store = create_test_store(&[
+            ("ERROR: boot failed", MessageDirection::Received),
+            ("error code 42", MessageDirection::Received),
+            ("ok", MessageDirection::Sent),
+        ]);
+
+        let insensitive = MessageFilter { search_query: Some("error".to_string()), ..Default::default() };
+        assert_eq!(query_messages(&store, &insensitive).unwrap().messages.len(), 2);
+
+        let sensitive = MessageFilter {
+            search_query: Some("ERROR".to_string()),
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(contents(&query_messages(&store, &sensitive).unwrap()), vec!["ERROR: boot failed"]);
+
+        let regex = MessageFilter {
+            search_query: Some(r"code \d+$".to_string()),
+            use_regex: true,
+            ..Default::default()
+        };
+        assert_eq!(contents(&query_messages(&store, &regex).unwrap()), vec!["error code 42"]);
+
+        let invalid = MessageFilter {
+            search_query: Some("(".to_string()),
+            use_regex: true,
+            ..Default::default()
+        };
+        assert!(query_messages(&store, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_query_messages_time_range_and_direction() {
+        let mut store = MessageStore::new(100);
+        for (content, timestamp, direction) in [
+            ("old", "2024-01-01T00:00:00Z", MessageDirection::Received),
+            ("mid", "2024-06-01T00:00:00Z", MessageDirection::Received),
+            ("sent", "2024-06-02T00:00:00Z", MessageDirection::Sent),
+            ("new", "2024-12-01T00:00:00Z", MessageDirection::Received),
+        ] {
+            let mut message = create_test_message(content, direction);
+            message.timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&Utc);
+            store.push(message);
+        }
+
+        let filter = MessageFilter {
+            direction: Some("received".to_string()),
+            start_time: Some("2024-03-01T00:00:00+09:00".to_string()),
+            end_time: Some("2024-12-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(contents(&query_messages(&store, &filter).unwrap()), vec!["mid", "new"]);
+
+        let invalid = MessageFilter { start_time: Some("yesterday".to_string()), ..Default::default() };
+        assert!(query_messages(&store, &invalid).is_err());
+    }
+
     #[test]
     fn test_export_as_json() {
         let messages = vec![
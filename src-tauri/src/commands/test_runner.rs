@@ -0,0 +1,64 @@
+use crate::models::TestSuite;
+use crate::services::test_runner::{self, TestReport};
+use tauri::State;
+use tracing::info;
+
+use super::{ApiResponse, AppState, TerminalState};
+
+// Tauri コマンド
+
+// YAML/JSON のテストスイートを接続中のセッションで実行し、終了後にレポートを返す
+#[tauri::command]
+pub async fn run_test_suite(
+    source: String,
+    app_state: State<'_, AppState>,
+    terminal_state: State<'_, TerminalState>,
+) -> Result<ApiResponse<TestReport>, String> {
+    let suite = match TestSuite::parse(&source) {
+        Ok(suite) => suite,
+        Err(e) => return Ok(ApiResponse::error(e)),
+    };
+
+    // 受信データを取りこぼさないよう、開始前に購読しておく
+    let received = {
+        let connection_manager = app_state.connection_manager.lock().await;
+        if !connection_manager.is_connected() {
+            return Ok(ApiResponse::error("Not connected".to_string()));
+        }
+        connection_manager.subscribe()
+    };
+
+    let global = terminal_state.config.lock().await.clone();
+    let line_ending = match app_state.active_profile.lock().await.as_ref() {
+        Some(resolved) => global.with_overrides(&resolved.config.terminal_overrides).line_ending,
+        None => global.line_ending,
+    };
+
+    let mut target = app_state.connection_manager.clone();
+    let mut report = test_runner::run_test_suite(&suite, &mut target, received, line_ending).await;
+    info!(
+        "Test suite '{}' finished: {}",
+        report.suite,
+        if report.passed { "passed" } else { "failed" }
+    );
+
+    // 機密情報をマスク
+    report.mask(&*app_state.data_masker.read().await);
+    Ok(ApiResponse::success(report))
+}
+
+// format は "junit"（JUnit XML）または "json"
+#[tauri::command]
+pub async fn export_test_report(
+    report: TestReport,
+    format: String,
+) -> Result<ApiResponse<String>, String> {
+    match format.as_str() {
+        "junit" => Ok(ApiResponse::success(report.to_junit_xml())),
+        "json" => match report.to_json() {
+            Ok(data) => Ok(ApiResponse::success(data)),
+            Err(e) => Ok(ApiResponse::error(e)),
+        },
+        _ => Ok(ApiResponse::error("Unsupported report format".to_string())),
+    }
+}
\ No newline at end of file
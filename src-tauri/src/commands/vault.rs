@@ -0,0 +1,220 @@
+use crate::models::SecretKind;
+use crate::services::{CredentialVault, KeyDerivation, SecretInfo, VaultError, VaultStatus};
+use crate::utils::crypto::SecretKey;
+use crate::utils::fs::backup_file;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+use super::ApiResponse;
+
+// 自動ロックの判定間隔
+const AUTO_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+// 資格情報ストアの状態
+pub struct VaultState {
+    pub vault: Arc<Mutex<CredentialVault>>,
+}
+
+impl VaultState {
+    // 読み込めないファイルは退避して新しいストアを作成する
+    pub fn load(path: PathBuf, auto_lock_timeout_minutes: Option<u32>) -> Result<Self, VaultError> {
+        let mut vault = match CredentialVault::open(path.clone()) {
+            Ok(vault) => vault,
+            Err(VaultError::InvalidFile(e)) => {
+                error!("Credential vault file is corrupt: {}", e);
+                match backup_file(&path, "corrupt") {
+                    Ok(backup) => warn!("Backed up corrupt vault file to {:?}", backup),
+                    Err(e) => error!("Failed to back up corrupt vault file: {}", e),
+                }
+                CredentialVault::open(path)?
+            }
+            Err(e) => return Err(e),
+        };
+        vault.set_auto_lock_timeout(auto_lock_timeout_minutes);
+
+        Ok(Self {
+            vault: Arc::new(Mutex::new(vault)),
+        })
+    }
+}
+
+// 一定間隔で自動ロックを判定し、ロックした場合はフロントエンドに通知する
+pub async fn run_vault_auto_lock(app_handle: AppHandle, vault: Arc<Mutex<CredentialVault>>) {
+    let mut interval = tokio::time::interval(AUTO_LOCK_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if vault.lock().await.check_auto_lock() {
+            info!("Credential vault auto-locked");
+            if let Err(e) = app_handle.emit("vault-locked", ()) {
+                error!("Failed to emit vault-locked event: {}", e);
+            }
+        }
+    }
+}
+
+// 鍵の導出には時間がかかるため、ストアのロックを持たずにブロッキングスレッドで行う
+async fn derive_key(derivation: KeyDerivation, master_password: String) -> Result<SecretKey, VaultError> {
+    tokio::task::spawn_blocking(move || derivation.derive(&master_password))
+        .await
+        .map_err(|e| VaultError::Io(std::io::Error::other(e)))?
+}
+
+// Tauri コマンド
+
+#[tauri::command]
+pub async fn get_vault_status(
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<VaultStatus>, String> {
+    let mut vault = vault_state.vault.lock().await;
+    vault.check_auto_lock();
+    Ok(ApiResponse::success(vault.status()))
+}
+
+#[tauri::command]
+pub async fn create_vault(
+    master_password: String,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<VaultStatus>, String> {
+    debug!("Creating credential vault");
+
+    let result = async {
+        let derivation = vault_state.vault.lock().await.create_derivation()?;
+        let key = derive_key(derivation.clone(), master_password).await?;
+        let mut vault = vault_state.vault.lock().await;
+        vault.finish_create(derivation, key)?;
+        Ok::<_, VaultError>(vault.status())
+    }
+    .await;
+
+    match result {
+        Ok(status) => Ok(ApiResponse::success(status)),
+        Err(e) => {
+            error!("Failed to create credential vault: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn unlock_vault(
+    master_password: String,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<VaultStatus>, String> {
+    debug!("Unlocking credential vault");
+
+    let result = async {
+        let derivation = vault_state.vault.lock().await.unlock_derivation()?;
+        let key = derive_key(derivation, master_password).await?;
+        let mut vault = vault_state.vault.lock().await;
+        vault.finish_unlock(key)?;
+        Ok::<_, VaultError>(vault.status())
+    }
+    .await;
+
+    match result {
+        Ok(status) => Ok(ApiResponse::success(status)),
+        Err(e) => {
+            warn!("Failed to unlock credential vault: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn lock_vault(
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<String>, String> {
+    vault_state.vault.lock().await.lock();
+    Ok(ApiResponse::success("Vault locked".to_string()))
+}
+
+#[tauri::command]
+pub async fn list_secrets(
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<Vec<SecretInfo>>, String> {
+    let mut vault = vault_state.vault.lock().await;
+    match vault.list_secrets() {
+        Ok(secrets) => Ok(ApiResponse::success(secrets)),
+        Err(e) => Ok(ApiResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn store_secret(
+    id: Option<String>,
+    name: String,
+    kind: SecretKind,
+    value: String,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<SecretInfo>, String> {
+    debug!("Storing secret: {}", name);
+
+    let mut vault = vault_state.vault.lock().await;
+    match vault.store_secret(id, name, kind, &value) {
+        Ok(info) => {
+            info!("Secret stored: {}", info.id);
+            Ok(ApiResponse::success(info))
+        }
+        Err(e) => {
+            error!("Failed to store secret: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn reveal_secret(
+    secret_id: String,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<String>, String> {
+    let mut vault = vault_state.vault.lock().await;
+    match vault.get_secret(&secret_id) {
+        Ok(value) => Ok(ApiResponse::success(value)),
+        Err(e) => Ok(ApiResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_secret(
+    secret_id: String,
+    vault_state: State<'_, VaultState>,
+) -> Result<ApiResponse<String>, String> {
+    debug!("Deleting secret: {}", secret_id);
+
+    let mut vault = vault_state.vault.lock().await;
+    match vault.delete_secret(&secret_id) {
+        Ok(_) => {
+            info!("Secret deleted: {}", secret_id);
+            Ok(ApiResponse::success("Secret deleted".to_string()))
+        }
+        Err(e) => {
+            error!("Failed to delete secret: {}", e);
+            Ok(ApiResponse::error(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_vault_state_load_recovers_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+        fs::write(&path, "not json").unwrap();
+
+        let state = VaultState::load(path.clone(), Some(5)).unwrap();
+        let vault = state.vault.try_lock().unwrap();
+
+        assert!(!vault.is_initialized());
+        assert_eq!(vault.status().auto_lock_timeout_minutes, Some(5));
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
\ No newline at end of file
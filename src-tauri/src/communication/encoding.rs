@@ -0,0 +1,80 @@
+use super::{ConnectionError, ConnectionResult};
+use encoding_rs::{Decoder, Encoding, UTF_8};
+
+// 端末設定の文字コード（"UTF-8"、"Shift_JIS" など）。送信データの変換と受信データの復号に使う
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextEncoding(&'static Encoding);
+
+impl TextEncoding {
+    pub fn from_label(label: &str) -> ConnectionResult<Self> {
+        Encoding::for_label(label.trim().as_bytes())
+            .map(Self)
+            .ok_or_else(|| ConnectionError::InvalidConfiguration(format!("Unknown encoding: {}", label)))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    // この文字コードで表せない文字は送れないため、置き換えずにエラーにする
+    pub fn encode(&self, text: &str) -> ConnectionResult<Vec<u8>> {
+        let (bytes, _, had_errors) = self.0.encode(text);
+        if had_errors {
+            return Err(ConnectionError::SendFailed(format!("Text cannot be encoded as {}", self.name())));
+        }
+        Ok(bytes.into_owned())
+    }
+
+    pub fn decoder(&self) -> TextDecoder {
+        TextDecoder(self.0.new_decoder())
+    }
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self(UTF_8)
+    }
+}
+
+// 受信データを順に復号する。マルチバイト文字が受信の区切りで分かれても次の受信とつなげて復号する
+pub struct TextDecoder(Decoder);
+
+impl TextDecoder {
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        let capacity = self.0.max_utf8_buffer_length(data.len()).unwrap_or(data.len() * 3);
+        let mut content = String::with_capacity(capacity);
+        let _ = self.0.decode_to_string(data, &mut content, false);
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode_shift_jis() {
+        let encoding = TextEncoding::from_label("Shift_JIS").unwrap();
+        let data = encoding.encode("温度\r\n").unwrap();
+        assert_eq!(data, vec![0x89, 0xB7, 0x93, 0x78, b'\r', b'\n']);
+        assert_eq!(encoding.decoder().decode(&data), "温度\r\n");
+    }
+
+    #[test]
+    fn test_decode_character_split_across_reads() {
+        let mut decoder = TextEncoding::default().decoder();
+        let data = "温度".as_bytes();
+        assert_eq!(decoder.decode(&data[..2]), "");
+        assert_eq!(decoder.decode(&data[2..]), "温度");
+    }
+
+    #[test]
+    fn test_unknown_label_and_unencodable_text() {
+        assert!(matches!(
+            TextEncoding::from_label("EBCDIC-42"),
+            Err(ConnectionError::InvalidConfiguration(_))
+        ));
+        let latin1 = TextEncoding::from_label("ISO-8859-1").unwrap();
+        assert!(matches!(latin1.encode("温度"), Err(ConnectionError::SendFailed(_))));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,150 @@
+use crate::models::{Framing, MessageDirection, TerminalMessage};
+use tokio::time::{Duration, Instant};
+
+// Line で改行が届かないまま受信が途切れた場合（プロンプトなど）に、そこまでを表示する時間
+const PARTIAL_LINE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// 1つのメッセージにまとめる受信データの上限。バイナリデータで際限なく伸びないようにする
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// 受信データを端末設定の区切り方（Framing）に従ってメッセージにまとめる
+pub struct Framer {
+    framing: Framing,
+    pending: Option<TerminalMessage>,
+    deadline: Option<Instant>,
+}
+
+impl Framer {
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            pending: None,
+            deadline: None,
+        }
+    }
+
+    // 受信したメッセージを渡し、区切りが確定したメッセージを返す
+    pub fn push(&mut self, message: TerminalMessage, now: Instant) -> Vec<TerminalMessage> {
+        if message.direction != MessageDirection::Received {
+            let mut framed: Vec<TerminalMessage> = self.flush().into_iter().collect();
+            framed.push(message);
+            return framed;
+        }
+
+        match self.framing.clone() {
+            Framing::Raw => vec![message],
+            Framing::Line => {
+                let mut framed = Vec::new();
+                let mut rest = message.content.as_str();
+                while let Some(end) = rest.find('\n') {
+                    let (line, tail) = rest.split_at(end + 1);
+                    self.append(&message, line);
+                    framed.extend(self.flush());
+                    rest = tail;
+                }
+                if !rest.is_empty() {
+                    self.append(&message, rest);
+                    self.deadline = Some(now + PARTIAL_LINE_TIMEOUT);
+                }
+                framed.extend(self.take_oversized());
+                framed
+            }
+            Framing::IdleGap { idle_ms } => {
+                self.append(&message, &message.content);
+                self.deadline = Some(now + Duration::from_millis(idle_ms));
+                self.take_oversized().into_iter().collect()
+            }
+        }
+    }
+
+    // 保留中のデータを区切る時刻。保留中のデータがなければ None
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // 保留中のデータを1つのメッセージとして取り出す。期限切れや切断時に呼ぶ
+    pub fn flush(&mut self) -> Option<TerminalMessage> {
+        self.deadline = None;
+        self.pending.take()
+    }
+
+    fn append(&mut self, message: &TerminalMessage, content: &str) {
+        match self.pending.as_mut() {
+            Some(pending) => pending.content.push_str(content),
+            None => {
+                // 最初に受信した時刻をまとめたメッセージの時刻にする
+                let mut pending = TerminalMessage::new_received(content.to_string(), message.encoding.clone());
+                pending.timestamp = message.timestamp;
+                self.pending = Some(pending);
+            }
+        }
+    }
+
+    fn take_oversized(&mut self) -> Option<TerminalMessage> {
+        if self.pending.as_ref().is_some_and(|pending| pending.content.len() >= MAX_FRAME_SIZE) {
+            self.flush()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_message(content: &str) -> TerminalMessage {
+        TerminalMessage::new_received(content.to_string(), "UTF-8".to_string())
+    }
+
+    fn contents(messages: &[TerminalMessage]) -> Vec<&str> {
+        messages.iter().map(|message| message.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_raw_passes_chunks_through() {
+        let mut framer = Framer::new(Framing::Raw);
+        let now = Instant::now();
+
+        let framed = framer.push(create_test_message("par"), now);
+        assert_eq!(contents(&framed), vec!["par"]);
+        assert!(framer.deadline().is_none());
+    }
+
+    #[test]
+    fn test_line_splits_and_joins_chunks() {
+        let mut framer = Framer::new(Framing::Line);
+        let now = Instant::now();
+
+        assert!(framer.push(create_test_message("boot"), now).is_empty());
+        let framed = framer.push(create_test_message("ing\r\nready\r\n> "), now);
+        assert_eq!(contents(&framed), vec!["booting\r\n", "ready\r\n"]);
+
+        // 改行のないプロンプトは受信が途切れたら表示する
+        assert_eq!(framer.deadline(), Some(now + PARTIAL_LINE_TIMEOUT));
+        assert_eq!(framer.flush().unwrap().content, "> ");
+        assert!(framer.deadline().is_none());
+    }
+
+    #[test]
+    fn test_idle_gap_joins_until_deadline() {
+        let mut framer = Framer::new(Framing::IdleGap { idle_ms: 50 });
+        let now = Instant::now();
+
+        assert!(framer.push(create_test_message("a\r\n"), now).is_empty());
+        let later = now + Duration::from_millis(30);
+        assert!(framer.push(create_test_message("b"), later).is_empty());
+        assert_eq!(framer.deadline(), Some(later + Duration::from_millis(50)));
+        assert_eq!(framer.flush().unwrap().content, "a\r\nb");
+    }
+
+    #[test]
+    fn test_other_directions_flush_pending_data() {
+        let mut framer = Framer::new(Framing::Line);
+        let now = Instant::now();
+
+        framer.push(create_test_message("login: "), now);
+        let framed = framer.push(TerminalMessage::new_system("note".to_string()), now);
+        assert_eq!(contents(&framed), vec!["login: ", "note"]);
+    }
+}
\ No newline at end of file
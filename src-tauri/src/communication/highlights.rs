@@ -0,0 +1,225 @@
+use crate::models::{Alert, AlertCount, HighlightRule, HighlightSpan, TerminalMessage};
+use regex::Regex;
+use std::collections::HashMap;
+use tracing::warn;
+
+// 行の途中で分割されて届いた場合に、次のメッセージと合わせて照合するため持ち越すデータの上限
+const CARRY_LIMIT: usize = 1024;
+
+struct CompiledRule {
+    rule: HighlightRule,
+    regex: Regex,
+    scan_from: usize, // carry 内の照合済みの位置
+}
+
+// 受信メッセージを強調表示ルールと照合し、一致回数をセッションごとに数える
+#[derive(Default)]
+pub struct HighlightEngine {
+    rules: Vec<CompiledRule>,
+    counts: HashMap<String, u64>, // rule_id -> 一致回数
+    carry: String, // 直前のメッセージの最後の改行より後ろ
+}
+
+impl HighlightEngine {
+    pub fn new(rules: &[HighlightRule]) -> Self {
+        let mut engine = Self::default();
+        engine.set_rules(rules);
+        engine
+    }
+
+    // ルールを差し替える。一致回数は同じIDのルールに引き継ぎ、受信済みのデータには反応しない
+    pub fn set_rules(&mut self, rules: &[HighlightRule]) {
+        let scan_from = self.carry.len();
+        self.rules = rules
+            .iter()
+            .filter(|rule| rule.enabled)
+            .filter_map(|rule| {
+                if let Err(e) = rule.validate() {
+                    warn!("Skipping highlight rule '{}': {}", rule.name, e);
+                    return None;
+                }
+                let regex = Regex::new(&rule.pattern).ok()?;
+                Some(CompiledRule {
+                    rule: rule.clone(),
+                    regex,
+                    scan_from,
+                })
+            })
+            .collect();
+    }
+
+    // 新しいセッションの開始時に一致回数を数え直し、前のセッションのデータを持ち越さない
+    pub fn reset_counts(&mut self) {
+        self.counts.clear();
+        self.carry.clear();
+        for compiled in &mut self.rules {
+            compiled.scan_from = 0;
+        }
+    }
+
+    pub fn alert_counts(&self) -> Vec<AlertCount> {
+        self.rules
+            .iter()
+            .map(|compiled| AlertCount {
+                rule_id: compiled.rule.id.clone(),
+                rule_name: compiled.rule.name.clone(),
+                severity: compiled.rule.severity,
+                count: self.counts.get(&compiled.rule.id).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    // 一致範囲をメッセージに付け、通知が必要な一致をルールごとに1件ずつ返す。
+    // 前のメッセージから続く行も合わせて照合し、一致範囲はこのメッセージの部分だけを付ける
+    pub fn apply(&mut self, message: &mut TerminalMessage) -> Vec<Alert> {
+        let mut spans = Vec::new();
+        let mut alerts = Vec::new();
+        let content = &message.content;
+        let offset = self.carry.len();
+        let text = format!("{}{}", self.carry, content);
+
+        for compiled in &mut self.rules {
+            let mut first_match = None;
+            let mut matches = 0;
+            for found in compiled.regex.find_iter(&text).filter(|found| !found.is_empty()) {
+                // 前のメッセージだけで一致した範囲は数え済み
+                if found.end() <= offset || found.start() < compiled.scan_from {
+                    continue;
+                }
+                spans.push(HighlightSpan {
+                    rule_id: compiled.rule.id.clone(),
+                    start: utf16_offset(content, found.start().max(offset) - offset),
+                    end: utf16_offset(content, found.end() - offset),
+                    severity: compiled.rule.severity,
+                    color: compiled.rule.color.clone(),
+                });
+                first_match.get_or_insert_with(|| found.as_str().to_string());
+                compiled.scan_from = found.end();
+                matches += 1;
+            }
+            if matches == 0 {
+                continue;
+            }
+
+            *self.counts.entry(compiled.rule.id.clone()).or_insert(0) += matches;
+            if compiled.rule.raises_alert() {
+                alerts.push(Alert {
+                    rule_id: compiled.rule.id.clone(),
+                    rule_name: compiled.rule.name.clone(),
+                    severity: compiled.rule.severity,
+                    notify: compiled.rule.notify,
+                    message_id: message.id.clone(),
+                    matched: first_match.unwrap_or_default(),
+                    timestamp: message.timestamp,
+                });
+            }
+        }
+
+        self.keep_carry(text);
+
+        // 重なった範囲はフロントエンドで重要度の高い方を優先して描画する
+        spans.sort_by_key(|span| (span.start, span.end));
+        message.highlights = spans;
+        alerts
+    }
+
+    // 最後の改行より後ろを次のメッセージへ持ち越す
+    fn keep_carry(&mut self, mut text: String) {
+        let mut cut = text.rfind('\n').map(|end| end + 1).unwrap_or(0);
+        if text.len() - cut > CARRY_LIMIT {
+            cut = text.len() - CARRY_LIMIT;
+            while !text.is_char_boundary(cut) {
+                cut += 1;
+            }
+        }
+        text.drain(..cut);
+        self.carry = text;
+        for compiled in &mut self.rules {
+            compiled.scan_from = compiled.scan_from.saturating_sub(cut);
+        }
+    }
+}
+
+// バイト位置を UTF-16 の位置に変換する
+fn utf16_offset(content: &str, byte_offset: usize) -> usize {
+    if content.is_ascii() {
+        return byte_offset;
+    }
+    content[..byte_offset].encode_utf16().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{default_highlight_rules, Severity};
+
+    #[test]
+    fn test_apply_attaches_spans_and_counts() {
+        let mut engine = HighlightEngine::new(&default_highlight_rules());
+        let mut message = TerminalMessage::new_received(
+            "E (12345) wifi: disconnected\r\nW (12346) wifi: retry\r\n".to_string(),
+            "UTF-8".to_string(),
+        );
+
+        let alerts = engine.apply(&mut message);
+        assert!(alerts.is_empty());
+        assert_eq!(message.highlights.len(), 2);
+        assert_eq!((message.highlights[0].start, message.highlights[0].end), (0, 9));
+        assert_eq!(message.highlights[0].severity, Severity::Error);
+        assert_eq!(message.highlights[1].severity, Severity::Warning);
+
+        let mut panic = TerminalMessage::new_received(
+            "Kernel panic - not syncing\r\npanic again\r\n".to_string(),
+            "UTF-8".to_string(),
+        );
+        let alerts = engine.apply(&mut panic);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule_name, "Panic");
+        assert_eq!(alerts[0].matched, "panic");
+        assert_eq!(alerts[0].message_id, panic.id);
+
+        let counts = engine.alert_counts();
+        let count = |name: &str| counts.iter().find(|count| count.rule_name == name).unwrap().count;
+        assert_eq!(count("Panic"), 2);
+        assert_eq!(count("ESP-IDF error"), 1);
+        assert_eq!(count("HardFault"), 0);
+
+        engine.reset_counts();
+        assert!(engine.alert_counts().iter().all(|count| count.count == 0));
+    }
+
+    #[test]
+    fn test_spans_use_utf16_offsets() {
+        let mut rule = HighlightRule::new("fault".to_string(), "HardFault".to_string(), Severity::Critical);
+        rule.color = Some("#ff0000".to_string());
+        let mut engine = HighlightEngine::new(&[rule]);
+
+        // "異常" は UTF-8 では6バイト、UTF-16 では2単位
+        let mut message = TerminalMessage::new_received("異常: HardFault".to_string(), "UTF-8".to_string());
+        engine.apply(&mut message);
+        assert_eq!((message.highlights[0].start, message.highlights[0].end), (4, 13));
+        assert_eq!(message.highlights[0].color.as_deref(), Some("#ff0000"));
+    }
+
+    #[test]
+    fn test_match_split_across_messages() {
+        let rule = HighlightRule::new("fault".to_string(), "HardFault".to_string(), Severity::Critical);
+        let mut engine = HighlightEngine::new(&[rule]);
+
+        let mut first = TerminalMessage::new_received("boot\r\nHard".to_string(), "UTF-8".to_string());
+        assert!(engine.apply(&mut first).is_empty());
+        assert!(first.highlights.is_empty());
+
+        // 続きのメッセージで一致し、このメッセージの部分に範囲を付ける
+        let mut second = TerminalMessage::new_received("Fault at 0x0\r\n".to_string(), "UTF-8".to_string());
+        let alerts = engine.apply(&mut second);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].matched, "HardFault");
+        assert_eq!((second.highlights[0].start, second.highlights[0].end), (0, 5));
+
+        // 改行の後は持ち越さず、同じ一致を数え直さない
+        let mut third = TerminalMessage::new_received("ok\r\n".to_string(), "UTF-8".to_string());
+        assert!(engine.apply(&mut third).is_empty());
+        assert_eq!(engine.alert_counts()[0].count, 1);
+    }
+}
\ No newline at end of file
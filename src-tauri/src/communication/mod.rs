@@ -1,18 +1,36 @@
+pub mod encoding;
+pub mod framing;
+pub mod highlights;
+pub mod pipeline;
+pub mod pty;
 pub mod serial;
 pub mod tcp;
+pub mod triggers;
+#[cfg(test)]
+pub(crate) mod test_support;
 #[cfg(test)]
 mod tests;
 
-use crate::models::{ConnectionConfig, TerminalMessage};
+use crate::models::{Alert, AlertCount, ConnectionConfig, ControlLine, HighlightRule, SerialConfig, MessageDirection, MessageStore, TerminalConfig, TerminalMessage, TriggerRule};
+use crate::services::SessionLogger;
 use async_trait::async_trait;
-// use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{broadcast, Mutex};
+use tracing::warn;
 #[cfg(test)]
 use mockall::automock;
 
+pub use encoding::{TextDecoder, TextEncoding};
+pub use framing::Framer;
+pub use highlights::HighlightEngine;
+pub use pipeline::{message_pipeline, MessageBatch, MessageReceiver, MessageSender, PipelineConfig};
 pub use serial::SerialHandler;
 pub use tcp::TcpHandler;
+pub use triggers::{run_trigger_responder, TriggerEngine, TriggerResponse};
 
 #[derive(Error, Debug)]
 pub enum ConnectionError {
@@ -37,6 +55,9 @@ pub enum ConnectionError {
     #[error("Connection closed")]
     ConnectionClosed,
     
+    #[error("Not supported: {0}")]
+    NotSupported(String),
+    
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     
@@ -46,42 +67,158 @@ pub enum ConnectionError {
 
 pub type ConnectionResult<T> = Result<T, ConnectionError>;
 
+// 切断時に受信タスクが残りのメッセージを処理し終えるまで待つ時間
+const RECEIVE_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+// 受信メッセージを購読者（マクロなど）へ配信するチャンネルの容量
+const RECEIVED_BROADCAST_CAPACITY: usize = 1024;
+
 #[async_trait]
 #[cfg_attr(test, automock)]
 pub trait ConnectionHandler: Send + Sync {
     async fn connect(&mut self, config: &ConnectionConfig) -> ConnectionResult<()>;
     async fn disconnect(&mut self) -> ConnectionResult<()>;
     async fn send(&mut self, data: &[u8]) -> ConnectionResult<()>;
-    async fn start_receive_loop(&mut self, tx: mpsc::UnboundedSender<TerminalMessage>) -> ConnectionResult<()>;
+    async fn start_receive_loop(&mut self, tx: MessageSender) -> ConnectionResult<()>;
+    // 受信した生のバイト列の複製先（PTY ブリッジなど）。start_receive_loop より前に設定する
+    fn set_raw_tap(&mut self, tap: broadcast::Sender<Vec<u8>>);
+    // 受信データを復号する文字コード。start_receive_loop より前に設定する
+    fn set_encoding(&mut self, encoding: TextEncoding);
+    // DTR/RTS を操作する。制御線のない接続では NotSupported を返す
+    async fn set_control_line(&mut self, line: ControlLine, level: bool) -> ConnectionResult<()>;
     fn is_connected(&self) -> bool;
     fn get_connection_info(&self) -> Option<String>;
 }
 
 pub struct ConnectionManager {
     current_handler: Option<Box<dyn ConnectionHandler>>,
-    message_sender: Option<mpsc::UnboundedSender<TerminalMessage>>,
+    message_sender: Option<MessageSender>,
     receive_handle: Option<tokio::task::JoinHandle<()>>,
+    message_store: Arc<Mutex<MessageStore>>,
+    session_logger: Option<Arc<Mutex<SessionLogger>>>,
+    received_tx: broadcast::Sender<TerminalMessage>,
+    raw_tx: broadcast::Sender<Vec<u8>>,
+    triggers: Arc<Mutex<TriggerEngine>>,
+    trigger_tx: UnboundedSender<TriggerResponse>,
+    trigger_rx: Option<UnboundedReceiver<TriggerResponse>>,
+    highlights: Arc<std::sync::Mutex<HighlightEngine>>,
+    alert_tx: UnboundedSender<Alert>,
+    alert_rx: Option<UnboundedReceiver<Alert>>,
+    session: u64, // 接続ごとに増える番号
+    serial_config: Option<SerialConfig>, // シリアルポートに接続中の場合のみ
+    default_terminal: TerminalConfig, // 全体の端末設定。接続時にプロファイルの上書きを適用する
+    terminal: TerminalConfig, // 接続中のセッションで使う端末設定
+    encoding: TextEncoding, // terminal.encoding に対応する文字コード
+    secrets: HashMap<String, String>, // 接続中プロファイルの資格情報（secret_id -> 値）。切断時に破棄する
+}
+
+// 受信タスクからトリガーの応答を依頼するための情報
+struct TriggerLink {
+    engine: Arc<Mutex<TriggerEngine>>,
+    responses: UnboundedSender<TriggerResponse>,
+    session: u64,
+}
+
+// 受信タスクで強調表示ルールと照合し、通知が必要な一致を送る
+struct HighlightLink {
+    engine: Arc<std::sync::Mutex<HighlightEngine>>,
+    alerts: UnboundedSender<Alert>,
 }
 
 impl ConnectionManager {
     pub fn new() -> Self {
+        Self::with_message_store(Arc::new(Mutex::new(MessageStore::default())))
+    }
+
+    // 送受信したメッセージをすべて指定のストアに記録する
+    pub fn with_message_store(message_store: Arc<Mutex<MessageStore>>) -> Self {
+        let (trigger_tx, trigger_rx) = mpsc::unbounded_channel();
+        let (alert_tx, alert_rx) = mpsc::unbounded_channel();
         Self {
             current_handler: None,
             message_sender: None,
             receive_handle: None,
+            message_store,
+            session_logger: None,
+            received_tx: broadcast::channel(RECEIVED_BROADCAST_CAPACITY).0,
+            raw_tx: broadcast::channel(RECEIVED_BROADCAST_CAPACITY).0,
+            triggers: Arc::new(Mutex::new(TriggerEngine::default())),
+            trigger_tx,
+            trigger_rx: Some(trigger_rx),
+            highlights: Arc::new(std::sync::Mutex::new(HighlightEngine::default())),
+            alert_tx,
+            alert_rx: Some(alert_rx),
+            session: 0,
+            serial_config: None,
+            default_terminal: TerminalConfig::default(),
+            terminal: TerminalConfig::default(),
+            encoding: TextEncoding::default(),
+            secrets: HashMap::new(),
         }
     }
 
-    pub async fn connect(&mut self, config: ConnectionConfig, message_tx: mpsc::UnboundedSender<TerminalMessage>) -> ConnectionResult<()> {
+    // トリガーの応答を受け取る。run_trigger_responder に渡して送信させる
+    pub fn take_trigger_responses(&mut self) -> Option<UnboundedReceiver<TriggerResponse>> {
+        self.trigger_rx.take()
+    }
+
+    // 接続中のトリガールールを差し替える。次の接続では接続設定のルールを使う
+    pub async fn set_triggers(&self, rules: &[TriggerRule]) {
+        self.triggers.lock().await.set_rules(rules);
+    }
+
+    // 強調表示ルールに一致した通知を受け取る。alert-raised イベントとしてフロントエンドへ送る
+    pub fn take_alerts(&mut self) -> Option<UnboundedReceiver<Alert>> {
+        self.alert_rx.take()
+    }
+
+    // 強調表示ルールを差し替える。接続中のセッションにも直ちに反映する
+    pub fn set_highlight_rules(&self, rules: &[HighlightRule]) {
+        self.highlights.lock().unwrap_or_else(|e| e.into_inner()).set_rules(rules);
+    }
+
+    // 接続中（切断後は直前）のセッションでルールに一致した回数
+    pub fn alert_counts(&self) -> Vec<AlertCount> {
+        self.highlights.lock().unwrap_or_else(|e| e.into_inner()).alert_counts()
+    }
+
+    // 接続中のプロファイルが参照している資格情報。トリガーの応答の {{secret:<id>}} を置き換える
+    pub fn set_secrets(&mut self, secrets: HashMap<String, String>) {
+        self.secrets = secrets;
+    }
+
+    pub fn secrets(&self) -> &HashMap<String, String> {
+        &self.secrets
+    }
+
+    // 全体の端末設定。次の接続から反映する
+    pub fn set_default_terminal(&mut self, config: TerminalConfig) {
+        self.default_terminal = config;
+    }
+
+    // 接続中（切断後は直前）のセッションで使う文字コード
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    // 送受信したメッセージをセッションごとのログファイルにも保存する
+    pub fn set_session_logger(&mut self, session_logger: Arc<Mutex<SessionLogger>>) {
+        self.session_logger = Some(session_logger);
+    }
+
+    pub async fn connect(&mut self, config: ConnectionConfig, message_tx: MessageSender) -> ConnectionResult<()> {
         // 既存の接続があれば切断
-        if let Some(handler) = &mut self.current_handler {
+        if let Some(mut handler) = self.current_handler.take() {
             let _ = handler.disconnect().await;
         }
+        self.secrets.clear();
 
         // 受信タスクがあれば停止
-        if let Some(handle) = self.receive_handle.take() {
-            handle.abort();
-        }
+        self.stop_receive_task().await;
+
+        // 全体の端末設定にプロファイルの上書きを適用する
+        let terminal = self.default_terminal.with_overrides(&config.terminal_overrides);
+        let encoding = TextEncoding::from_label(&terminal.encoding)?;
 
         // 新しいハンドラーを作成
         let mut handler: Box<dyn ConnectionHandler> = match config.connection_type {
@@ -104,12 +241,51 @@ impl ConnectionManager {
         // 接続実行
         handler.connect(&config).await?;
 
-        // 受信ループ開始
-        let message_tx_clone = message_tx.clone();
-        handler.start_receive_loop(message_tx_clone).await?;
+        if let Some(logger) = &self.session_logger {
+            logger.lock().await.start_session(&config.name);
+        }
+
+        // 受信ループ開始。ハンドラーからのメッセージは受信タスクで記録してから転送する
+        let (handler_tx, handler_rx) = message_pipeline(PipelineConfig {
+            batch_interval: Duration::ZERO,
+            ..PipelineConfig::default()
+        });
+        handler.set_raw_tap(self.raw_tx.clone());
+        handler.set_encoding(encoding);
+        handler.start_receive_loop(handler_tx).await?;
 
+        self.session += 1;
+        *self.triggers.lock().await = TriggerEngine::new(&config.triggers);
+        self.highlights.lock().unwrap_or_else(|e| e.into_inner()).reset_counts();
+        let highlight_link = HighlightLink {
+            engine: self.highlights.clone(),
+            alerts: self.alert_tx.clone(),
+        };
+        let trigger_link = TriggerLink {
+            engine: self.triggers.clone(),
+            responses: self.trigger_tx.clone(),
+            session: self.session,
+        };
+
+        let framing = terminal.framing.clone();
+        let sink = ReceiveSink {
+            message_store: self.message_store.clone(),
+            session_logger: self.session_logger.clone(),
+            message_tx: message_tx.clone(),
+            received_tx: self.received_tx.clone(),
+            trigger_link,
+            highlight_link,
+        };
+
+        self.receive_handle = Some(tokio::spawn(run_receive_task(handler_rx, Framer::new(framing), sink)));
         self.current_handler = Some(handler);
         self.message_sender = Some(message_tx);
+        self.terminal = terminal;
+        self.encoding = encoding;
+        self.serial_config = match config.connection_type {
+            crate::models::ConnectionType::Serial => config.serial_config,
+            crate::models::ConnectionType::Tcp => None,
+        };
 
         Ok(())
     }
@@ -119,27 +295,77 @@ impl ConnectionManager {
             handler.disconnect().await?;
         }
 
-        if let Some(handle) = self.receive_handle.take() {
-            handle.abort();
+        self.stop_receive_task().await;
+
+        if let Some(logger) = &self.session_logger {
+            logger.lock().await.end_session();
         }
 
         self.current_handler = None;
         self.message_sender = None;
+        self.serial_config = None;
+        self.secrets.clear();
 
         Ok(())
     }
 
+    // 接続中のセッションの文字コードで送信する
     pub async fn send_message(&mut self, message: String) -> ConnectionResult<()> {
-        if let Some(handler) = &mut self.current_handler {
-            let data = message.as_bytes();
-            handler.send(data).await?;
-            
-            // 送信メッセージはフロントエンドで既に表示しているため、
-            // バックエンドでは受信メッセージのみをチャンネルに送信する
-            
-            Ok(())
-        } else {
-            Err(ConnectionError::ConnectionClosed)
+        let data = self.encoding.encode(&message)?;
+        self.send_data(&data, message, self.encoding.name()).await
+    }
+
+    // 入力した1行に接続中のセッションの改行コードを付けて送信する
+    pub async fn send_line(&mut self, line: &str) -> ConnectionResult<()> {
+        let message = format!("{}{}", line, self.terminal.line_ending.to_string());
+        self.send_message(message).await
+    }
+
+    // 任意のバイト列を送信する。content は記録・表示に使う内容
+    pub async fn send_data(&mut self, data: &[u8], content: String, encoding: &str) -> ConnectionResult<()> {
+        let Some(handler) = &mut self.current_handler else {
+            return Err(ConnectionError::ConnectionClosed);
+        };
+
+        // 応答が送信メッセージより先に記録されないよう、送信する前に記録してフロントエンドへ通知する。
+        // ストアのロックはデバイスやファイルの I/O をまたいで保持しない。
+        // ローカルエコーが無効な場合は端末に表示せず、ログにだけ残す
+        let sent_message = TerminalMessage::new_sent(content, encoding.to_string());
+        write_session_log(&self.session_logger, &sent_message).await;
+        if self.terminal.echo_input {
+            self.message_store.lock().await.push(sent_message.clone());
+            if let Some(tx) = &self.message_sender {
+                let _ = tx.send(sent_message);
+            }
+        }
+
+        handler.send(data).await
+    }
+
+    pub async fn set_control_line(&mut self, line: ControlLine, level: bool) -> ConnectionResult<()> {
+        match &mut self.current_handler {
+            Some(handler) => handler.set_control_line(line, level).await,
+            None => Err(ConnectionError::ConnectionClosed),
+        }
+    }
+
+    // 以降に受信したメッセージを購読する。接続をまたいで使用できる
+    pub fn subscribe(&self) -> broadcast::Receiver<TerminalMessage> {
+        self.received_tx.subscribe()
+    }
+
+    // 受信した生のバイト列を購読する。文字コードの変換で失われるデータも含む
+    pub fn subscribe_raw(&self) -> broadcast::Receiver<Vec<u8>> {
+        self.raw_tx.subscribe()
+    }
+
+    // ハンドラーの受信ループが終了していれば受信タスクも残りを処理して終了する
+    async fn stop_receive_task(&mut self) {
+        if let Some(mut handle) = self.receive_handle.take() {
+            if tokio::time::timeout(RECEIVE_DRAIN_TIMEOUT, &mut handle).await.is_err() {
+                warn!("Receive task did not finish in time, aborting");
+                handle.abort();
+            }
         }
     }
 
@@ -155,6 +381,119 @@ impl ConnectionManager {
             .as_ref()
             .and_then(|h| h.get_connection_info())
     }
+
+    // 接続中のシリアルポートの設定。TCP 接続中や未接続の場合は None
+    pub fn serial_config(&self) -> Option<&SerialConfig> {
+        self.serial_config.as_ref()
+    }
+}
+
+// ハンドラーから届いたメッセージを区切り直してストアに記録し、フロントエンド向けのパイプラインへ転送する
+async fn run_receive_task(mut handler_rx: MessageReceiver, mut framer: Framer, sink: ReceiveSink) {
+    loop {
+        let batch = match framer.deadline() {
+            Some(deadline) => tokio::select! {
+                batch = handler_rx.recv_batch() => batch,
+                _ = tokio::time::sleep_until(deadline) => {
+                    if !sink.record(framer.flush().into_iter().collect()).await {
+                        return;
+                    }
+                    continue;
+                }
+            },
+            None => handler_rx.recv_batch().await,
+        };
+
+        // 切断時は保留中のデータも記録する
+        let Some(batch) = batch else {
+            sink.record(framer.flush().into_iter().collect()).await;
+            return;
+        };
+        if batch.dropped > 0 {
+            sink.message_tx.report_dropped(batch.dropped);
+        }
+
+        let now = tokio::time::Instant::now();
+        let messages = batch
+            .messages
+            .into_iter()
+            .flat_map(|message| framer.push(message, now))
+            .collect();
+        if !sink.record(messages).await {
+            return;
+        }
+    }
+}
+
+// 受信タスクが区切ったメッセージの記録先
+struct ReceiveSink {
+    message_store: Arc<Mutex<MessageStore>>,
+    session_logger: Option<Arc<Mutex<SessionLogger>>>,
+    message_tx: MessageSender,
+    received_tx: broadcast::Sender<TerminalMessage>,
+    trigger_link: TriggerLink,
+    highlight_link: HighlightLink,
+}
+
+impl ReceiveSink {
+    // フロントエンド向けのパイプラインが閉じていれば false
+    async fn record(&self, messages: Vec<TerminalMessage>) -> bool {
+        if messages.is_empty() {
+            return true;
+        }
+
+        let mut store = self.message_store.lock().await;
+        for mut message in messages {
+            let fired = if message.direction == MessageDirection::Received {
+                self.trigger_link.engine.lock().await.feed(&message.content, tokio::time::Instant::now())
+            } else {
+                Vec::new()
+            };
+            if message.direction == MessageDirection::Received {
+                let alerts = self.highlight_link.engine.lock().unwrap_or_else(|e| e.into_inner()).apply(&mut message);
+                for alert in alerts {
+                    let _ = self.highlight_link.alerts.send(alert);
+                }
+            }
+
+            write_session_log(&self.session_logger, &message).await;
+            store.push(message.clone());
+            // 購読者がいない場合のエラーは無視する
+            let _ = self.received_tx.send(message.clone());
+            if self.message_tx.send(message).is_err() {
+                warn!("Failed to forward received message to frontend pipeline");
+                return false;
+            }
+
+            // 発火したトリガーは注記として記録し、応答の送信を依頼する
+            for rule in fired {
+                let content = String::from_utf8_lossy(&rule.response_bytes()).into_owned();
+                let note = TerminalMessage::new_system(format!(
+                    "Trigger '{}' matched /{}/, sending \"{}\"",
+                    rule.name,
+                    rule.pattern,
+                    content.escape_debug()
+                ));
+                write_session_log(&self.session_logger, &note).await;
+                store.push(note.clone());
+                let _ = self.message_tx.send(note);
+
+                let _ = self.trigger_link.responses.send(TriggerResponse {
+                    session: self.trigger_link.session,
+                    rule_name: rule.name,
+                    content,
+                    delay: Duration::from_millis(rule.delay_ms),
+                });
+            }
+        }
+        true
+    }
+}
+
+async fn write_session_log(session_logger: &Option<Arc<Mutex<SessionLogger>>>, message: &TerminalMessage) {
+    if let Some(logger) = session_logger {
+        logger.lock().await.write_message(message);
+    }
 }
 
 impl Default for ConnectionManager {
@@ -0,0 +1,283 @@
+use crate::models::TerminalMessage;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub capacity: usize,
+    pub batch_interval: Duration,
+    pub max_batch_size: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            batch_interval: Duration::from_millis(16),
+            max_batch_size: 256,
+        }
+    }
+}
+
+// 受信側に渡されるメッセージのまとまり
+#[derive(Debug, Clone)]
+pub struct MessageBatch {
+    pub messages: Vec<TerminalMessage>,
+    pub dropped: u64, // 前回のバッチ以降に破棄されたメッセージ数
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineClosed;
+
+struct Shared {
+    queue: Mutex<VecDeque<TerminalMessage>>,
+    notify: Notify,
+    dropped: AtomicU64,
+    senders: AtomicUsize,
+    receiver_closed: AtomicBool,
+    config: PipelineConfig,
+}
+
+impl Shared {
+    fn lock_queue(&self) -> std::sync::MutexGuard<'_, VecDeque<TerminalMessage>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn senders_closed(&self) -> bool {
+        self.senders.load(Ordering::SeqCst) == 0
+    }
+}
+
+// 容量制限付きのメッセージパイプラインを作成する
+pub fn message_pipeline(config: PipelineConfig) -> (MessageSender, MessageReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(config.capacity.min(1024))),
+        notify: Notify::new(),
+        dropped: AtomicU64::new(0),
+        senders: AtomicUsize::new(1),
+        receiver_closed: AtomicBool::new(false),
+        config,
+    });
+
+    (
+        MessageSender { shared: shared.clone() },
+        MessageReceiver { shared },
+    )
+}
+
+// 送信側。ブロッキングスレッドからも非同期タスクからも使用できる
+pub struct MessageSender {
+    shared: Arc<Shared>,
+}
+
+impl MessageSender {
+    pub fn send(&self, message: TerminalMessage) -> Result<(), PipelineClosed> {
+        if self.shared.receiver_closed.load(Ordering::SeqCst) {
+            return Err(PipelineClosed);
+        }
+
+        {
+            let mut queue = self.shared.lock_queue();
+            // 満杯の場合は最も古いメッセージを破棄して新しいメッセージを受け入れる
+            if queue.len() >= self.shared.config.capacity {
+                self.shared.dropped.fetch_add(1, Ordering::SeqCst);
+                queue.pop_front();
+            }
+            queue.push_back(message);
+        }
+
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    // 上流のパイプラインで破棄されたメッセージ数を引き継ぐ
+    pub fn report_dropped(&self, count: u64) {
+        self.shared.dropped.fetch_add(count, Ordering::SeqCst);
+        self.shared.notify.notify_one();
+    }
+}
+
+impl Clone for MessageSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for MessageSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // 最後の送信側が破棄されたら受信側を起こす
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+// 受信側。一定間隔または一定件数ごとにメッセージをまとめて受け取る
+pub struct MessageReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MessageReceiver {
+    // 次のバッチを待つ。すべての送信側が破棄され、キューが空になったら None を返す
+    pub async fn recv_batch(&mut self) -> Option<MessageBatch> {
+        // 最初のメッセージ（または破棄通知）が届くまで待機
+        loop {
+            let notified = self.shared.notify.notified();
+            if !self.shared.lock_queue().is_empty() || self.shared.dropped.load(Ordering::SeqCst) > 0 {
+                break;
+            }
+            if self.shared.senders_closed() {
+                return None;
+            }
+            notified.await;
+        }
+
+        // バッチ間隔が経過するか、最大件数に達するまでまとめる
+        let deadline = Instant::now() + self.shared.config.batch_interval;
+        loop {
+            let notified = self.shared.notify.notified();
+            if self.shared.lock_queue().len() >= self.shared.config.max_batch_size
+                || self.shared.senders_closed()
+            {
+                break;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                break;
+            }
+        }
+
+        let messages: Vec<TerminalMessage> = {
+            let mut queue = self.shared.lock_queue();
+            let count = queue.len().min(self.shared.config.max_batch_size);
+            queue.drain(..count).collect()
+        };
+
+        // 残りがあれば次回の呼び出しですぐに処理されるようにする
+        if !self.shared.lock_queue().is_empty() {
+            self.shared.notify.notify_one();
+        }
+
+        Some(MessageBatch {
+            messages,
+            dropped: self.shared.dropped.swap(0, Ordering::SeqCst),
+        })
+    }
+}
+
+impl Drop for MessageReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_config(capacity: usize, max_batch_size: usize) -> PipelineConfig {
+        PipelineConfig {
+            capacity,
+            batch_interval: Duration::from_millis(10),
+            max_batch_size,
+        }
+    }
+
+    fn create_test_message(content: &str) -> TerminalMessage {
+        TerminalMessage::new_received(content.to_string(), "UTF-8".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch_collects_messages() {
+        let (tx, mut rx) = message_pipeline(create_test_config(100, 10));
+
+        tx.send(create_test_message("one")).unwrap();
+        tx.send(create_test_message("two")).unwrap();
+
+        let batch = rx.recv_batch().await.unwrap();
+        assert_eq!(batch.messages.len(), 2);
+        assert_eq!(batch.messages[0].content, "one");
+        assert_eq!(batch.messages[1].content, "two");
+        assert_eq!(batch.dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch_respects_max_batch_size() {
+        let (tx, mut rx) = message_pipeline(create_test_config(100, 3));
+
+        for i in 0..5 {
+            tx.send(create_test_message(&i.to_string())).unwrap();
+        }
+
+        let first = rx.recv_batch().await.unwrap();
+        assert_eq!(first.messages.len(), 3);
+
+        let second = rx.recv_batch().await.unwrap();
+        assert_eq!(second.messages.len(), 2);
+        assert_eq!(second.messages[0].content, "3");
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_policy() {
+        let (tx, mut rx) = message_pipeline(create_test_config(2, 10));
+
+        tx.send(create_test_message("a")).unwrap();
+        tx.send(create_test_message("b")).unwrap();
+        tx.send(create_test_message("c")).unwrap();
+
+        let batch = rx.recv_batch().await.unwrap();
+        let contents: Vec<&str> = batch.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["b", "c"]);
+        assert_eq!(batch.dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_report_dropped() {
+        let (tx, mut rx) = message_pipeline(PipelineConfig::default());
+
+        tx.report_dropped(5);
+
+        let batch = rx.recv_batch().await.unwrap();
+        assert!(batch.messages.is_empty());
+        assert_eq!(batch.dropped, 5);
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch_returns_none_when_senders_dropped() {
+        let (tx, mut rx) = message_pipeline(PipelineConfig::default());
+
+        tx.send(create_test_message("last")).unwrap();
+        drop(tx);
+
+        let batch = rx.recv_batch().await.unwrap();
+        assert_eq!(batch.messages.len(), 1);
+        assert!(rx.recv_batch().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_after_receiver_dropped() {
+        let (tx, rx) = message_pipeline(PipelineConfig::default());
+        drop(rx);
+
+        assert_eq!(tx.send(create_test_message("lost")), Err(PipelineClosed));
+    }
+
+    #[tokio::test]
+    async fn test_send_from_blocking_thread() {
+        let (tx, mut rx) = message_pipeline(PipelineConfig::default());
+
+        let handle = std::thread::spawn(move || {
+            tx.send(create_test_message("from thread")).unwrap();
+        });
+        handle.join().unwrap();
+
+        let batch = rx.recv_batch().await.unwrap();
+        assert_eq!(batch.messages[0].content, "from thread");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,311 @@
+use super::{ConnectionManager, ConnectionResult};
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch, Mutex};
+use tokio::task::JoinHandle;
+
+// 接続中のセッションを擬似端末（PTY）として公開し、他のプログラムから同じデバイスを使えるようにする。
+// PTY に書き込まれたデータはデバイスへ送信し、デバイスからの受信データは PTY にも複製する。
+// どちらの方向もセッションのメッセージとして記録される。
+// 入出力エラーなどでどちらかの方向が止まった場合はブリッジ全体が閉じたものとして扱う
+pub struct PtyBridge {
+    path: String,
+    tasks: Vec<JoinHandle<()>>,
+    closed: watch::Receiver<Option<String>>, // 閉じた理由。開いている間は None
+    // 外部プログラムが閉じても読み込みが EIO にならないよう、スレーブ側を開いたままにしておく
+    #[cfg(unix)]
+    _slave: std::os::fd::OwnedFd,
+}
+
+impl PtyBridge {
+    #[cfg(unix)]
+    pub fn open(
+        connection_manager: Arc<Mutex<ConnectionManager>>,
+        raw: broadcast::Receiver<Vec<u8>>,
+    ) -> ConnectionResult<Self> {
+        use tokio::io::unix::AsyncFd;
+
+        let (master, slave, path) = unix::open_pty()?;
+        let master = Arc::new(AsyncFd::new(master)?);
+
+        let (closed_tx, closed) = watch::channel(None);
+        let closed_tx = Arc::new(closed_tx);
+        let reader = tokio::spawn({
+            let task = forward_to_device(master.clone(), connection_manager, path.clone());
+            let closed_tx = closed_tx.clone();
+            async move {
+                closed_tx.send_replace(Some(task.await));
+            }
+        });
+        let writer = tokio::spawn({
+            let task = forward_to_pty(master, raw, path.clone());
+            async move {
+                closed_tx.send_replace(Some(task.await));
+            }
+        });
+
+        tracing::info!("PTY bridge opened at {}", path);
+        Ok(Self {
+            path,
+            tasks: vec![reader, writer],
+            closed,
+            _slave: slave,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(
+        _connection_manager: Arc<Mutex<ConnectionManager>>,
+        _raw: broadcast::Receiver<Vec<u8>>,
+    ) -> ConnectionResult<Self> {
+        Err(super::ConnectionError::NotSupported(
+            "PTY bridge is only available on Unix-like systems".to_string(),
+        ))
+    }
+
+    // 外部プログラムから開くデバイスのパス（例: /dev/pts/3）
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // 転送が止まっていればその理由
+    pub fn closed_reason(&self) -> Option<String> {
+        self.closed.borrow().clone()
+    }
+
+    // 転送が止まったときに通知を受け取る。ブリッジを破棄すると送信側も閉じる
+    pub fn watch_closed(&self) -> watch::Receiver<Option<String>> {
+        self.closed.clone()
+    }
+}
+
+impl Drop for PtyBridge {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        tracing::info!("PTY bridge closed at {}", self.path);
+    }
+}
+
+// PTY → デバイス。送信メッセージとして記録される。止まった理由を返す
+#[cfg(unix)]
+async fn forward_to_device(
+    master: Arc<tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>>,
+    connection_manager: Arc<Mutex<ConnectionManager>>,
+    path: String,
+) -> String {
+    let mut buffer = [0u8; 1024];
+    loop {
+        let result = match master.readable().await {
+            Ok(mut guard) => match guard.try_io(|fd| unix::read(fd.get_ref(), &mut buffer)) {
+                Ok(result) => result,
+                Err(_would_block) => continue,
+            },
+            Err(e) => Err(e),
+        };
+        let bytes_read = match result {
+            Ok(n) => n,
+            Err(e) => {
+                tracing::error!("PTY bridge read error on {}: {}", path, e);
+                return format!("PTY read error: {}", e);
+            }
+        };
+        if bytes_read == 0 {
+            continue;
+        }
+
+        let data = &buffer[..bytes_read];
+        let content = String::from_utf8_lossy(data).to_string();
+        let mut manager = connection_manager.lock().await;
+        if let Err(e) = manager.send_data(data, content, "UTF-8").await {
+            tracing::warn!("PTY bridge failed to send data from {}: {}", path, e);
+        }
+    }
+}
+
+// デバイス → PTY。外部プログラムが読み込まずにバッファが溢れた分は読み飛ばす。止まった理由を返す
+#[cfg(unix)]
+async fn forward_to_pty(
+    master: Arc<tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>>,
+    mut raw: broadcast::Receiver<Vec<u8>>,
+    path: String,
+) -> String {
+    loop {
+        let data = match raw.recv().await {
+            Ok(data) => data,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("PTY bridge on {} skipped {} chunks of received data", path, skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return "Connection closed".to_string(),
+        };
+
+        let mut written = 0;
+        while written < data.len() {
+            let result = match master.writable().await {
+                Ok(mut guard) => match guard.try_io(|fd| unix::write(fd.get_ref(), &data[written..])) {
+                    Ok(result) => result,
+                    Err(_would_block) => continue,
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(n) => written += n,
+                Err(e) => {
+                    tracing::error!("PTY bridge write error on {}: {}", path, e);
+                    return format!("PTY write error: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::ptr;
+
+    // マスター（ノンブロッキング）、スレーブ、スレーブのパスを返す
+    pub fn open_pty() -> io::Result<(OwnedFd, OwnedFd, String)> {
+        let mut master_fd: libc::c_int = -1;
+        let mut slave_fd: libc::c_int = -1;
+        // SAFETY: 出力先の fd はローカル変数、name/termp/winp は null（使用しない）
+        let result = unsafe {
+            libc::openpty(&mut master_fd, &mut slave_fd, ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty が成功したので両方とも有効な fd で、所有者はここだけ
+        let master = unsafe { OwnedFd::from_raw_fd(master_fd) };
+        let slave = unsafe { OwnedFd::from_raw_fd(slave_fd) };
+
+        set_raw_mode(&slave)?;
+        set_nonblocking(&master)?;
+        let path = tty_name(&slave)?;
+        Ok((master, slave, path))
+    }
+
+    // バイナリデータをそのまま通すため、エコーや改行変換を無効にする
+    fn set_raw_mode(fd: &OwnedFd) -> io::Result<()> {
+        // SAFETY: termios は tcgetattr で初期化してから使う
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd.as_raw_fd(), &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(fd.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn set_nonblocking(fd: &OwnedFd) -> io::Result<()> {
+        // SAFETY: 有効な fd に対するフラグの取得・設定のみ
+        unsafe {
+            let flags = libc::fcntl(fd.as_raw_fd(), libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn tty_name(fd: &OwnedFd) -> io::Result<String> {
+        let mut buffer = [0 as libc::c_char; 256];
+        // SAFETY: バッファの長さを渡しているので範囲外には書き込まれない
+        let result = unsafe { libc::ttyname_r(fd.as_raw_fd(), buffer.as_mut_ptr(), buffer.len()) };
+        if result != 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        // SAFETY: ttyname_r が成功した場合は NUL 終端されている
+        let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    pub fn read(fd: &OwnedFd, buffer: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: buffer の長さを超えて読み込まない
+        let n = unsafe { libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    pub fn write(fd: &OwnedFd, data: &[u8]) -> io::Result<usize> {
+        // SAFETY: data の長さを超えて読み出さない
+        let n = unsafe { libc::write(fd.as_raw_fd(), data.as_ptr().cast(), data.len()) };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::communication::test_support::{create_test_echo_config, spawn_echo_server};
+    use crate::communication::{message_pipeline, PipelineConfig};
+    use crate::models::{MessageDirection, MessageStore};
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_pty_bridge_round_trip() {
+        let port = spawn_echo_server().await;
+        let message_store = Arc::new(Mutex::new(MessageStore::default()));
+        let mut manager = ConnectionManager::with_message_store(message_store.clone());
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        manager.connect(create_test_echo_config(port), tx).await.unwrap();
+        let raw = manager.subscribe_raw();
+        let manager = Arc::new(Mutex::new(manager));
+
+        let bridge = PtyBridge::open(manager.clone(), raw).unwrap();
+        assert!(bridge.path().starts_with("/dev/"));
+
+        // UTF-8 として不正なバイトも変換されずに往復する
+        let payload = b"ping\xff\x00";
+        let path = bridge.path().to_string();
+        let echoed = tokio::task::spawn_blocking(move || {
+            let mut pty = std::fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+            pty.write_all(payload).unwrap();
+            let mut echoed = vec![0u8; payload.len()];
+            pty.read_exact(&mut echoed).unwrap();
+            echoed
+        });
+        let echoed = tokio::time::timeout(Duration::from_secs(5), echoed).await.unwrap().unwrap();
+        assert_eq!(echoed, payload);
+
+        // PTY から書き込んだデータも送信メッセージとして記録される
+        let store = message_store.lock().await;
+        assert!(store
+            .iter()
+            .any(|message| message.direction == MessageDirection::Sent && message.content.starts_with("ping")));
+        assert_eq!(bridge.closed_reason(), None);
+    }
+
+    #[tokio::test]
+    async fn test_pty_bridge_reports_stopped_forwarding() {
+        let manager = Arc::new(Mutex::new(ConnectionManager::new()));
+        let (raw_tx, raw) = broadcast::channel(8);
+
+        let bridge = PtyBridge::open(manager, raw).unwrap();
+        let mut closed = bridge.watch_closed();
+        drop(raw_tx);
+
+        let reason = tokio::time::timeout(Duration::from_secs(5), closed.wait_for(|reason| reason.is_some()))
+            .await
+            .unwrap()
+            .unwrap()
+            .clone();
+        assert_eq!(reason.as_deref(), Some("Connection closed"));
+        assert_eq!(bridge.closed_reason(), reason);
+    }
+}
\ No newline at end of file
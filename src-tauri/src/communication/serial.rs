@@ -1,25 +1,53 @@
-use super::{ConnectionError, ConnectionHandler, ConnectionResult};
-use crate::models::{ConnectionConfig, SerialConfig, TerminalMessage};
+use super::{ConnectionError, ConnectionHandler, ConnectionResult, MessageSender, TextEncoding};
+use crate::models::{ConnectionConfig, ControlLine, SerialConfig, TerminalMessage};
 use async_trait::async_trait;
 use serialport::{SerialPort, SerialPortType};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::{mpsc, Mutex};
-use tokio::time::timeout;
+use tokio::sync::broadcast;
 use tracing::{debug, error, info, warn};
 
+// 読み取りスレッドが停止シグナルを確認する間隔
+const READER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// 書き込み時のタイムアウト
+const WRITE_TIMEOUT: Duration = Duration::from_millis(1000);
+
 pub struct SerialHandler {
     config: SerialConfig,
-    port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
-    is_connected: Arc<Mutex<bool>>,
+    writer: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
+    is_connected: Arc<AtomicBool>,
+    reader: Option<ReaderThread>,
+    raw_tap: Option<broadcast::Sender<Vec<u8>>>,
+    encoding: TextEncoding,
+}
+
+// ポートごとに1本だけ起動する読み取りスレッド
+struct ReaderThread {
+    shutdown: Arc<AtomicBool>,
+    handle: std::thread::JoinHandle<()>,
 }
 
 impl SerialHandler {
     pub fn new(config: SerialConfig) -> Self {
         Self {
             config,
-            port: Arc::new(Mutex::new(None)),
-            is_connected: Arc::new(Mutex::new(false)),
+            writer: Arc::new(Mutex::new(None)),
+            is_connected: Arc::new(AtomicBool::new(false)),
+            reader: None,
+            raw_tap: None,
+            encoding: TextEncoding::default(),
+        }
+    }
+
+    // 読み取りスレッドに停止を通知し、終了を待つ
+    async fn stop_reader(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.shutdown.store(true, Ordering::SeqCst);
+            if tokio::task::spawn_blocking(move || reader.handle.join()).await.is_err() {
+                warn!("Failed to join serial reader thread for port: {}", self.config.port);
+            }
         }
     }
 
@@ -97,7 +125,7 @@ impl SerialHandler {
             .stop_bits(self.config.stop_bits.clone().into())
             .parity(self.config.parity.clone().into())
             .flow_control(self.config.flow_control.clone().into())
-            .timeout(Duration::from_millis(1000));
+            .timeout(WRITE_TIMEOUT);
 
         match builder.open() {
             Ok(port) => {
@@ -133,20 +161,17 @@ impl ConnectionHandler for SerialHandler {
             return Err(ConnectionError::PortNotFound(self.config.port.clone()));
         }
 
+        // 既存の読み取りスレッドがあれば停止
+        self.stop_reader().await;
+
         // ポートを開く
         let port = self.create_port().await?;
         
-        // ポートを保存
-        {
-            let mut port_guard = self.port.lock().await;
-            *port_guard = Some(port);
-        }
+        // 書き込み用ハンドルとして保存
+        *self.writer.lock().unwrap_or_else(|e| e.into_inner()) = Some(port);
 
         // 接続状態を更新
-        {
-            let mut connected = self.is_connected.lock().await;
-            *connected = true;
-        }
+        self.is_connected.store(true, Ordering::SeqCst);
 
         info!("Successfully connected to serial port: {}", self.config.port);
         Ok(())
@@ -155,158 +180,119 @@ impl ConnectionHandler for SerialHandler {
     async fn disconnect(&mut self) -> ConnectionResult<()> {
         debug!("Disconnecting from serial port: {}", self.config.port);
 
+        // 接続状態を更新
+        self.is_connected.store(false, Ordering::SeqCst);
+
+        // 読み取りスレッドを停止
+        self.stop_reader().await;
+
         // ポートを閉じる
-        {
-            let mut port_guard = self.port.lock().await;
-            if let Some(mut port) = port_guard.take() {
+        let writer = self.writer.clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            let mut writer_guard = writer.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(mut port) = writer_guard.take() {
                 // ポートを明示的にフラッシュしてから閉じる
                 let _ = port.flush();
-                drop(port);
             }
-        }
-
-        // 接続状態を更新
-        {
-            let mut connected = self.is_connected.lock().await;
-            *connected = false;
-        }
+        })
+        .await;
 
         info!("Disconnected from serial port: {}", self.config.port);
         Ok(())
     }
 
     async fn send(&mut self, data: &[u8]) -> ConnectionResult<()> {
-        let mut port_guard = self.port.lock().await;
-        
-        if let Some(port) = port_guard.as_mut() {
-            match port.write_all(data) {
-                Ok(_) => {
-                    match port.flush() {
-                        Ok(_) => {
-                            debug!("Sent {} bytes to serial port", data.len());
-                            Ok(())
-                        }
-                        Err(e) => {
-                            error!("Failed to flush serial port: {}", e);
-                            Err(ConnectionError::SendFailed(e.to_string()))
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to write to serial port: {}", e);
-                    Err(ConnectionError::SendFailed(e.to_string()))
-                }
-            }
-        } else {
-            Err(ConnectionError::ConnectionClosed)
+        // 書き込みは読み取りスレッドとは別のハンドルで行うため、受信処理と競合しない
+        let writer = self.writer.clone();
+        let data = data.to_vec();
+
+        let result = tokio::task::spawn_blocking(move || {
+            let mut writer_guard = writer.lock().unwrap_or_else(|e| e.into_inner());
+            let port = writer_guard.as_mut().ok_or(ConnectionError::ConnectionClosed)?;
+
+            port.write_all(&data).map_err(|e| {
+                error!("Failed to write to serial port: {}", e);
+                ConnectionError::SendFailed(e.to_string())
+            })?;
+            port.flush().map_err(|e| {
+                error!("Failed to flush serial port: {}", e);
+                ConnectionError::SendFailed(e.to_string())
+            })?;
+
+            debug!("Sent {} bytes to serial port", data.len());
+            Ok(())
+        })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(e) => Err(ConnectionError::SendFailed(e.to_string())),
         }
     }
 
-    async fn start_receive_loop(&mut self, tx: mpsc::UnboundedSender<TerminalMessage>) -> ConnectionResult<()> {
-        let port_arc = self.port.clone();
-        let is_connected_arc = self.is_connected.clone();
-        let port_name = self.config.port.clone();
-
-        tokio::spawn(async move {
-            let mut buffer = [0u8; 1024];
-            
-            loop {
-                // 接続状態をチェック
-                {
-                    let connected = is_connected_arc.lock().await;
-                    if !*connected {
-                        debug!("Receive loop stopped: not connected");
-                        break;
-                    }
-                }
+    async fn start_receive_loop(&mut self, tx: MessageSender) -> ConnectionResult<()> {
+        // 以前の読み取りスレッドが残っていれば停止してから開始する
+        self.stop_reader().await;
 
-                // データを読み取り
-                let result = {
-                    let mut port_guard = port_arc.lock().await;
-                    if let Some(port) = port_guard.as_mut() {
-                        // タイムアウト付きで読み取り
-                        let read_result = timeout(
-                            Duration::from_millis(100),
-                            tokio::task::spawn_blocking({
-                                let mut port_clone = port.try_clone().unwrap();
-                                move || port_clone.read(&mut buffer)
-                            })
-                        ).await;
-
-                        match read_result {
-                            Ok(Ok(Ok(bytes_read))) => Some(Ok(bytes_read)),
-                            Ok(Ok(Err(e))) => Some(Err(e)),
-                            Ok(Err(_)) => {
-                                // join error
-                                Some(Err(std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    "Task join error"
-                                )))
-                            }
-                            Err(_) => {
-                                // timeout
-                                None
-                            }
-                        }
-                    } else {
-                        debug!("Receive loop stopped: port closed");
-                        break;
-                    }
-                };
-
-                match result {
-                    Some(Ok(bytes_read)) if bytes_read > 0 => {
-                        let data = &buffer[..bytes_read];
-                        let content = String::from_utf8_lossy(data).to_string();
-                        
-                        debug!("Received {} bytes from serial port: {:?}", bytes_read, content);
-                        
-                        let message = TerminalMessage::new_received(content, "UTF-8".to_string());
-                        
-                        if tx.send(message).is_err() {
-                            warn!("Failed to send received message to channel");
-                            break;
-                        }
-                    }
-                    Some(Ok(_)) => {
-                        // 0 bytes read, continue
-                    }
-                    Some(Err(e)) => {
-                        match e.kind() {
-                            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => {
-                                // タイムアウトやWouldBlockは正常、続行
-                            }
-                            _ => {
-                                error!("Serial receive error: {}", e);
-                                // エラーメッセージを送信
-                                let error_message = TerminalMessage::new_received(
-                                    format!("Error: {}", e),
-                                    "UTF-8".to_string()
-                                );
-                                let _ = tx.send(error_message);
-                                break;
-                            }
-                        }
-                    }
-                    None => {
-                        // タイムアウト、続行
-                    }
-                }
-
-                // 短時間スリープしてCPU使用率を下げる
-                tokio::time::sleep(Duration::from_millis(1)).await;
+        let mut reader_port = {
+            let writer_guard = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+            match writer_guard.as_ref() {
+                Some(port) => port.try_clone()?,
+                None => return Err(ConnectionError::ConnectionClosed),
             }
+        };
 
-            info!("Serial receive loop ended for port: {}", port_name);
-        });
-
+        // 読み取りハンドルは短いタイムアウトで停止シグナルを定期的に確認する
+        reader_port.set_timeout(READER_POLL_INTERVAL)?;
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::Builder::new()
+            .name(format!("serial-reader-{}", self.config.port))
+            .spawn({
+                let shutdown = shutdown.clone();
+                let is_connected = self.is_connected.clone();
+                let port_name = self.config.port.clone();
+                let raw_tap = self.raw_tap.clone();
+                let encoding = self.encoding;
+                move || run_reader(reader_port, tx, raw_tap, encoding, shutdown, is_connected, port_name)
+            })?;
+
+        self.reader = Some(ReaderThread { shutdown, handle });
         Ok(())
     }
 
+    fn set_raw_tap(&mut self, tap: broadcast::Sender<Vec<u8>>) {
+        self.raw_tap = Some(tap);
+    }
+
+    fn set_encoding(&mut self, encoding: TextEncoding) {
+        self.encoding = encoding;
+    }
+
+    async fn set_control_line(&mut self, line: ControlLine, level: bool) -> ConnectionResult<()> {
+        let writer = self.writer.clone();
+
+        let result = tokio::task::spawn_blocking(move || {
+            let mut writer_guard = writer.lock().unwrap_or_else(|e| e.into_inner());
+            let port = writer_guard.as_mut().ok_or(ConnectionError::ConnectionClosed)?;
+
+            match line {
+                ControlLine::Dtr => port.write_data_terminal_ready(level)?,
+                ControlLine::Rts => port.write_request_to_send(level)?,
+            }
+            debug!("Set {:?} to {}", line, level);
+            Ok(())
+        })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(e) => Err(ConnectionError::SendFailed(e.to_string())),
+        }
+    }
+
     fn is_connected(&self) -> bool {
-        // Note: この関数は同期的なので、Arcの値を直接チェックできない
-        // 実際の実装では、AtomicBoolを使用するか、別の方法を検討する必要がある
-        true // 暫定的な実装
+        self.is_connected.load(Ordering::SeqCst)
     }
 
     fn get_connection_info(&self) -> Option<String> {
@@ -336,6 +322,76 @@ impl ConnectionHandler for SerialHandler {
     }
 }
 
+impl Drop for SerialHandler {
+    fn drop(&mut self) {
+        // 切断されずに破棄された場合でも読み取りスレッドを終了させる
+        if let Some(reader) = &self.reader {
+            reader.shutdown.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+// 読み取りスレッド本体。停止シグナルを受けるか、致命的なエラーが起きるまで読み続ける
+fn run_reader(
+    mut port: Box<dyn SerialPort>,
+    tx: MessageSender,
+    raw_tap: Option<broadcast::Sender<Vec<u8>>>,
+    encoding: TextEncoding,
+    shutdown: Arc<AtomicBool>,
+    is_connected: Arc<AtomicBool>,
+    port_name: String,
+) {
+    let mut buffer = [0u8; 4096];
+    let mut decoder = encoding.decoder();
+
+    while !shutdown.load(Ordering::SeqCst) {
+        match port.read(&mut buffer) {
+            Ok(0) => {
+                // 0 bytes read, continue
+            }
+            Ok(bytes_read) => {
+                if let Some(tap) = &raw_tap {
+                    let _ = tap.send(buffer[..bytes_read].to_vec());
+                }
+                let content = decoder.decode(&buffer[..bytes_read]);
+
+                debug!("Received {} bytes from serial port: {:?}", bytes_read, content);
+                if content.is_empty() {
+                    // 文字の途中で区切られたため、次の受信とつなげて復号する
+                    continue;
+                }
+
+                let message = TerminalMessage::new_received(content, encoding.name().to_string());
+
+                if tx.send(message).is_err() {
+                    warn!("Failed to send received message to channel");
+                    break;
+                }
+            }
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::Interrupted => {
+                    // タイムアウトやWouldBlockは正常、続行
+                }
+                _ => {
+                    error!("Serial receive error: {}", e);
+                    // エラーメッセージを送信
+                    let error_message = TerminalMessage::new_received(
+                        format!("Error: {}", e),
+                        "UTF-8".to_string()
+                    );
+                    let _ = tx.send(error_message);
+                    is_connected.store(false, Ordering::SeqCst);
+                    break;
+                }
+            },
+        }
+    }
+
+    info!("Serial receive loop ended for port: {}", port_name);
+}
+
 #[derive(Debug, Clone)]
 pub struct SerialPortInfo {
     pub port_name: String,
@@ -350,6 +406,7 @@ pub struct SerialPortInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::communication::{message_pipeline, PipelineConfig};
     use crate::models::{DataBits, FlowControl, Parity, SerialConfig, StopBits};
 
     fn create_test_serial_config() -> SerialConfig {
@@ -430,7 +487,7 @@ mod tests {
         let config = create_test_serial_config();
         let handler = SerialHandler::new(config);
         
-        assert!(handler.is_connected());
+        assert!(!handler.is_connected());
     }
 
     #[tokio::test]
@@ -451,6 +508,18 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_start_receive_loop_without_connection() {
+        let config = create_test_serial_config();
+        let mut handler = SerialHandler::new(config);
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        
+        let result = handler.start_receive_loop(tx).await;
+        
+        assert!(matches!(result, Err(ConnectionError::ConnectionClosed)));
+        assert!(handler.reader.is_none());
+    }
+
     #[tokio::test]
     async fn test_disconnect_without_connection() {
         let config = create_test_serial_config();
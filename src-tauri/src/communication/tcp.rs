@@ -1,11 +1,11 @@
-use super::{ConnectionError, ConnectionHandler, ConnectionResult};
-use crate::models::{ConnectionConfig, TcpConfig, TerminalMessage};
+use super::{ConnectionError, ConnectionHandler, ConnectionResult, MessageSender, TextEncoding};
+use crate::models::{ConnectionConfig, ControlLine, TcpConfig, TerminalMessage};
 use async_trait::async_trait;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, Mutex};
 use tokio::time::{timeout, Duration};
 use tracing::{debug, error, info, warn};
 
@@ -13,6 +13,8 @@ pub struct TcpHandler {
     config: TcpConfig,
     stream: Arc<Mutex<Option<TcpStream>>>,
     is_connected: Arc<AtomicBool>,
+    raw_tap: Option<broadcast::Sender<Vec<u8>>>,
+    encoding: TextEncoding,
 }
 
 impl TcpHandler {
@@ -21,6 +23,8 @@ impl TcpHandler {
             config,
             stream: Arc::new(Mutex::new(None)),
             is_connected: Arc::new(AtomicBool::new(false)),
+            raw_tap: None,
+            encoding: TextEncoding::default(),
         }
     }
 
@@ -152,14 +156,17 @@ impl ConnectionHandler for TcpHandler {
         }
     }
 
-    async fn start_receive_loop(&mut self, tx: mpsc::UnboundedSender<TerminalMessage>) -> ConnectionResult<()> {
+    async fn start_receive_loop(&mut self, tx: MessageSender) -> ConnectionResult<()> {
         let stream_arc = self.stream.clone();
         let is_connected_arc = self.is_connected.clone();
         let host = self.config.host.clone();
         let port = self.config.port;
+        let raw_tap = self.raw_tap.clone();
+        let encoding = self.encoding;
 
         tokio::spawn(async move {
             let mut buffer = [0u8; 1024];
+            let mut decoder = encoding.decoder();
             
             loop {
                 // 接続状態をチェック
@@ -187,11 +194,18 @@ impl ConnectionHandler for TcpHandler {
                 match result {
                     Some(Ok(bytes_read)) if bytes_read > 0 => {
                         let data = &buffer[..bytes_read];
-                        let content = String::from_utf8_lossy(data).to_string();
+                        if let Some(tap) = &raw_tap {
+                            let _ = tap.send(data.to_vec());
+                        }
+                        let content = decoder.decode(data);
                         
                         debug!("Received {} bytes from TCP connection: {:?}", bytes_read, content);
+                        if content.is_empty() {
+                            // 文字の途中で区切られたため、次の受信とつなげて復号する
+                            continue;
+                        }
                         
-                        let message = TerminalMessage::new_received(content, "UTF-8".to_string());
+                        let message = TerminalMessage::new_received(content, encoding.name().to_string());
                         
                         if tx.send(message).is_err() {
                             warn!("Failed to send received message to channel");
@@ -260,10 +274,22 @@ impl ConnectionHandler for TcpHandler {
         Ok(())
     }
 
+    fn set_raw_tap(&mut self, tap: broadcast::Sender<Vec<u8>>) {
+        self.raw_tap = Some(tap);
+    }
+
+    fn set_encoding(&mut self, encoding: TextEncoding) {
+        self.encoding = encoding;
+    }
+
     fn is_connected(&self) -> bool {
         self.is_connected.load(Ordering::SeqCst)
     }
 
+    async fn set_control_line(&mut self, line: ControlLine, _level: bool) -> ConnectionResult<()> {
+        Err(ConnectionError::NotSupported(format!("{:?} is not available on TCP connections", line)))
+    }
+
     fn get_connection_info(&self) -> Option<String> {
         Some(format!(
             "TCP: {}:{} (timeout: {}ms, keep-alive: {})",
@@ -342,8 +368,8 @@ mod tests {
         let config = create_test_tcp_config();
         let handler = TcpHandler::new(config);
         
-        // 現在の実装では常にtrueを返すが、これは暫定的な実装
-        assert!(handler.is_connected());
+        // TcpHandler::new は is_connected を false で初期化するので、connect 前は未接続になる
+        assert!(!handler.is_connected());
     }
 
     #[tokio::test]
@@ -360,6 +386,11 @@ mod tests {
             tcp_config: Some(config),
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
         };
         
         let result = handler.connect(&connection_config).await;
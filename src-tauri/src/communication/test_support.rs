@@ -0,0 +1,34 @@
+use crate::models::{ConnectionConfig, TcpConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+// 受信したデータをそのまま返すローカルのエコーサーバーを起動する（tcp_echo_server.py と同じ動作）
+pub async fn spawn_echo_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        if let Ok((mut socket, _)) = listener.accept().await {
+            let mut buffer = [0u8; 1024];
+            while let Ok(n) = socket.read(&mut buffer).await {
+                if n == 0 || socket.write_all(&buffer[..n]).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    port
+}
+
+// spawn_echo_server で起動したエコーサーバーへの接続設定
+pub fn create_test_echo_config(port: u16) -> ConnectionConfig {
+    ConnectionConfig::new_tcp(
+        "Echo".to_string(),
+        TcpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..TcpConfig::default()
+        },
+    )
+}
\ No newline at end of file
@@ -1,10 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use crate::communication::{ConnectionManager, ConnectionError, ConnectionResult};
-    use crate::models::{ConnectionConfig, ConnectionType, TcpConfig};
+    use crate::communication::test_support::{create_test_echo_config, spawn_echo_server};
+    use crate::communication::{message_pipeline, run_trigger_responder, ConnectionManager, ConnectionError, ConnectionResult, PipelineConfig};
+    use crate::models::{ConnectionConfig, ConnectionType, ControlLine, Framing, LineEnding, MessageDirection, MessageStore, TcpConfig, TriggerMode, TriggerRule};
     use chrono::Utc;
+    use std::collections::HashMap;
+    use std::sync::Arc;
     use std::time::Duration;
-    use tokio::sync::mpsc;
+    use tokio::sync::Mutex;
 
     fn create_test_tcp_config() -> ConnectionConfig {
         ConnectionConfig {
@@ -20,6 +23,11 @@ mod tests {
             }),
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
         }
     }
 
@@ -34,7 +42,7 @@ mod tests {
     async fn test_connection_manager_connect_success() {
         let mut manager = ConnectionManager::new();
         let config = create_test_tcp_config();
-        let (tx, _rx) = mpsc::unbounded_channel();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
 
         // モックハンドラーは実際のTCP/Serial接続をモックできないため、
         // このテストでは基本的な構造のテストに留める
@@ -46,12 +54,183 @@ mod tests {
         // テスト環境では接続成功をアサートしない
     }
 
+    #[tokio::test]
+    async fn test_connection_manager_records_sent_and_received_messages() {
+        let port = spawn_echo_server().await;
+        let store = Arc::new(Mutex::new(MessageStore::default()));
+        let mut manager = ConnectionManager::with_message_store(store.clone());
+        let (tx, mut rx) = message_pipeline(PipelineConfig::default());
+
+        let config = create_test_echo_config(port);
+
+        manager.connect(config, tx).await.unwrap();
+        manager.send_message("ping".to_string()).await.unwrap();
+
+        // 送信メッセージとエコーされた受信メッセージがフロントエンド向けに流れる
+        let mut forwarded = Vec::new();
+        while forwarded.len() < 2 {
+            let batch = tokio::time::timeout(Duration::from_secs(2), rx.recv_batch())
+                .await
+                .expect("timed out waiting for messages")
+                .unwrap();
+            forwarded.extend(batch.messages);
+        }
+        assert_eq!(forwarded[0].direction, MessageDirection::Sent);
+        assert_eq!(forwarded[1].direction, MessageDirection::Received);
+        assert_eq!(forwarded[1].content, "ping");
+
+        manager.disconnect().await.unwrap();
+
+        // フロントエンドを経由せずにストアへ記録されている
+        let store = store.lock().await;
+        let directions: Vec<MessageDirection> = store.iter().map(|m| m.direction.clone()).collect();
+        assert_eq!(directions, vec![MessageDirection::Sent, MessageDirection::Received]);
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_subscribe_receives_only_received_data() {
+        let port = spawn_echo_server().await;
+        let mut manager = ConnectionManager::new();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        let mut subscriber = manager.subscribe();
+
+        let config = create_test_echo_config(port);
+
+        manager.connect(config, tx).await.unwrap();
+        manager.send_data(&[0x41, 0x42], "41 42".to_string(), "HEX").await.unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(2), subscriber.recv())
+            .await
+            .expect("timed out waiting for echo")
+            .unwrap();
+        assert_eq!(message.direction, MessageDirection::Received);
+        assert_eq!(message.content, "AB");
+
+        // TCP 接続には制御線がない
+        let result = manager.set_control_line(ControlLine::Dtr, true).await;
+        assert!(matches!(result, Err(ConnectionError::NotSupported(_))));
+
+        manager.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_frames_received_data_by_line() {
+        let port = spawn_echo_server().await;
+        let mut manager = ConnectionManager::new();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        let mut subscriber = manager.subscribe();
+
+        let mut config = create_test_echo_config(port);
+        config.terminal_overrides.framing = Some(Framing::Line);
+
+        manager.connect(config, tx).await.unwrap();
+        manager.send_message("boot".to_string()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        manager.send_message("ing\r\n".to_string()).await.unwrap();
+
+        // 別々に届いたデータが1行にまとまる
+        let message = tokio::time::timeout(Duration::from_secs(2), subscriber.recv())
+            .await
+            .expect("timed out waiting for echo")
+            .unwrap();
+        assert_eq!(message.content, "booting\r\n");
+
+        manager.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_applies_terminal_overrides() {
+        let port = spawn_echo_server().await;
+        let store = Arc::new(Mutex::new(MessageStore::default()));
+        let mut manager = ConnectionManager::with_message_store(store.clone());
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        let mut subscriber = manager.subscribe();
+        let mut raw = manager.subscribe_raw();
+
+        let mut config = create_test_echo_config(port);
+        config.terminal_overrides.encoding = Some("Shift_JIS".to_string());
+        config.terminal_overrides.line_ending = Some(LineEnding::Lf);
+        config.terminal_overrides.echo_input = Some(false);
+
+        manager.connect(config, tx).await.unwrap();
+        manager.send_line("温度").await.unwrap();
+
+        // プロファイルの文字コードと改行コードで送信し、受信データも同じ文字コードで復号する
+        let data = tokio::time::timeout(Duration::from_secs(2), raw.recv())
+            .await
+            .expect("timed out waiting for echo")
+            .unwrap();
+        assert_eq!(data, vec![0x89, 0xB7, 0x93, 0x78, b'\n']);
+        let message = tokio::time::timeout(Duration::from_secs(2), subscriber.recv())
+            .await
+            .expect("timed out waiting for echo")
+            .unwrap();
+        assert_eq!(message.content, "温度\n");
+        assert_eq!(message.encoding, "Shift_JIS");
+
+        manager.disconnect().await.unwrap();
+
+        // ローカルエコーが無効なため、送信メッセージは端末に記録しない
+        let store = store.lock().await;
+        let directions: Vec<MessageDirection> = store.iter().map(|m| m.direction.clone()).collect();
+        assert_eq!(directions, vec![MessageDirection::Received]);
+    }
+
+    #[tokio::test]
+    async fn test_connection_manager_trigger_sends_response() {
+        let port = spawn_echo_server().await;
+        let store = Arc::new(Mutex::new(MessageStore::default()));
+        let mut manager = ConnectionManager::with_message_store(store.clone());
+        let responses = manager.take_trigger_responses().unwrap();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+
+        let mut config = create_test_echo_config(port);
+        config.triggers.push(TriggerRule {
+            id: "t1".to_string(),
+            name: "answer".to_string(),
+            pattern: "ping".to_string(),
+            response: "{{secret:reply}}".to_string(),
+            line_ending: None,
+            delay_ms: 10,
+            mode: TriggerMode::OneShot,
+            cooldown_ms: 0,
+            enabled: true,
+        });
+
+        manager.connect(config, tx).await.unwrap();
+        manager.set_secrets(HashMap::from([("reply".to_string(), "pong".to_string())]));
+        let manager = Arc::new(Mutex::new(manager));
+        tokio::spawn(run_trigger_responder(manager.clone(), responses));
+        manager.lock().await.send_message("ping".to_string()).await.unwrap();
+
+        // ping の受信で発火し、注記・応答・応答のエコーの順に記録される。
+        // 資格情報は送信時に置き換え、記録にはプレースホルダーを残す
+        let expected = vec![
+            (MessageDirection::Sent, "ping".to_string()),
+            (MessageDirection::Received, "ping".to_string()),
+            (MessageDirection::System, "Trigger 'answer' matched /ping/, sending \"{{secret:reply}}\"".to_string()),
+            (MessageDirection::Sent, "{{secret:reply}}".to_string()),
+            (MessageDirection::Received, "pong".to_string()),
+        ];
+        let mut recorded = Vec::new();
+        for _ in 0..100 {
+            recorded = store.lock().await.iter().map(|m| (m.direction.clone(), m.content.clone())).collect();
+            if recorded.len() >= expected.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(recorded, expected);
+
+        manager.lock().await.disconnect().await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_connection_manager_connect_invalid_config() {
         let mut manager = ConnectionManager::new();
         let mut config = create_test_tcp_config();
         config.tcp_config = None; // 無効な設定
-        let (tx, _rx) = mpsc::unbounded_channel();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
 
         let result = manager.connect(config, tx).await;
         assert!(result.is_err());
@@ -0,0 +1,213 @@
+use super::ConnectionManager;
+use crate::services::macros::substitute_secrets;
+use crate::models::{TriggerMode, TriggerRule};
+use regex::Regex;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+// プロンプトが複数回に分かれて届いても一致できるよう保持する受信データの上限
+const MATCH_BUFFER_LIMIT: usize = 4096;
+
+// 発火したトリガーの応答。session が変わっていれば（再接続後）送信しない
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerResponse {
+    pub session: u64,
+    pub rule_name: String,
+    pub content: String, // {{secret:<id>}} は送信時に置き換える
+    pub delay: Duration,
+}
+
+struct TriggerState {
+    rule: TriggerRule,
+    regex: Regex,
+    scan_from: usize, // バッファ内の照合済みの位置
+    fired: bool,
+    last_fired: Option<Instant>,
+}
+
+// 受信データをトリガールールと照合する
+#[derive(Default)]
+pub struct TriggerEngine {
+    buffer: String,
+    states: Vec<TriggerState>,
+}
+
+impl TriggerEngine {
+    pub fn new(rules: &[TriggerRule]) -> Self {
+        let mut engine = Self::default();
+        engine.set_rules(rules);
+        engine
+    }
+
+    // ルールを差し替える。同じIDのルールは発火状態を引き継ぎ、受信済みのデータには反応しない
+    pub fn set_rules(&mut self, rules: &[TriggerRule]) {
+        let mut previous: Vec<TriggerState> = std::mem::take(&mut self.states);
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            if let Err(e) = rule.validate() {
+                warn!("Skipping trigger '{}': {}", rule.name, e);
+                continue;
+            }
+            let Ok(regex) = Regex::new(&rule.pattern) else {
+                continue;
+            };
+            let (fired, last_fired) = previous
+                .iter()
+                .position(|state| state.rule.id == rule.id)
+                .map(|index| previous.swap_remove(index))
+                .map(|state| (state.fired, state.last_fired))
+                .unwrap_or((false, None));
+
+            self.states.push(TriggerState {
+                rule: rule.clone(),
+                regex,
+                scan_from: self.buffer.len(),
+                fired,
+                last_fired,
+            });
+        }
+    }
+
+    // 受信データを追加し、発火したルールを返す
+    pub fn feed(&mut self, content: &str, now: Instant) -> Vec<TriggerRule> {
+        if self.states.is_empty() {
+            return Vec::new();
+        }
+
+        self.buffer.push_str(content);
+        if self.buffer.len() > MATCH_BUFFER_LIMIT {
+            let mut cut = self.buffer.len() - MATCH_BUFFER_LIMIT;
+            while !self.buffer.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.buffer.drain(..cut);
+            for state in &mut self.states {
+                state.scan_from = state.scan_from.saturating_sub(cut);
+            }
+        }
+
+        let mut fired = Vec::new();
+        for state in &mut self.states {
+            let Some(found) = state.regex.find_at(&self.buffer, state.scan_from) else {
+                continue;
+            };
+            // 無視した一致も消費し、クールダウン後に同じ出力で発火しないようにする
+            state.scan_from = found.end();
+
+            if state.rule.mode == TriggerMode::OneShot && state.fired {
+                continue;
+            }
+            let cooldown = Duration::from_millis(state.rule.cooldown_ms);
+            if state.last_fired.map(|last| now < last + cooldown).unwrap_or(false) {
+                debug!("Trigger '{}' matched during cooldown", state.rule.name);
+                continue;
+            }
+
+            state.fired = true;
+            state.last_fired = Some(now);
+            fired.push(state.rule.clone());
+        }
+        fired
+    }
+}
+
+// トリガーの応答を待ち時間の後に送信する。接続をまたいで1つだけ起動する
+pub async fn run_trigger_responder(
+    connection_manager: Arc<Mutex<ConnectionManager>>,
+    mut responses: UnboundedReceiver<TriggerResponse>,
+) {
+    while let Some(response) = responses.recv().await {
+        let connection_manager = connection_manager.clone();
+        tokio::spawn(async move {
+            if !response.delay.is_zero() {
+                tokio::time::sleep(response.delay).await;
+            }
+
+            let mut connection_manager = connection_manager.lock().await;
+            if connection_manager.session != response.session || !connection_manager.is_connected() {
+                debug!("Dropping response of trigger '{}' for a closed session", response.rule_name);
+                return;
+            }
+            // 表示・ログにはプレースホルダーのまま残す
+            let encoding = connection_manager.encoding();
+            let data = substitute_secrets(&response.content, connection_manager.secrets())
+                .and_then(|text| encoding.encode(&text).map_err(|e| e.to_string()));
+            let data = match data {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("Failed to send response of trigger '{}': {}", response.rule_name, e);
+                    return;
+                }
+            };
+            if let Err(e) = connection_manager
+                .send_data(&data, response.content, encoding.name())
+                .await
+            {
+                warn!("Failed to send response of trigger '{}': {}", response.rule_name, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_rule(id: &str, pattern: &str, mode: TriggerMode, cooldown_ms: u64) -> TriggerRule {
+        TriggerRule {
+            id: id.to_string(),
+            name: id.to_string(),
+            pattern: pattern.to_string(),
+            response: "\r".to_string(),
+            line_ending: None,
+            delay_ms: 0,
+            mode,
+            cooldown_ms,
+            enabled: true,
+        }
+    }
+
+    fn names(rules: Vec<TriggerRule>) -> Vec<String> {
+        rules.into_iter().map(|rule| rule.name).collect()
+    }
+
+    #[test]
+    fn test_match_split_across_chunks() {
+        let mut engine = TriggerEngine::new(&[create_rule("autoboot", "stop autoboot", TriggerMode::OneShot, 0)]);
+        let now = Instant::now();
+
+        assert!(engine.feed("Press any key to st", now).is_empty());
+        assert_eq!(names(engine.feed("op autoboot:  3", now)), vec!["autoboot"]);
+        // 1回限りのルールは再び一致しても発火しない
+        assert!(engine.feed("Press any key to stop autoboot", now).is_empty());
+    }
+
+    #[test]
+    fn test_repeat_with_cooldown() {
+        let mut engine = TriggerEngine::new(&[create_rule("more", "--More--", TriggerMode::Repeat, 1000)]);
+        let start = Instant::now();
+
+        assert_eq!(engine.feed("--More--", start).len(), 1);
+        assert!(engine.feed("--More--", start + Duration::from_millis(500)).is_empty());
+        // クールダウン中の一致は消費されている
+        assert!(engine.feed("", start + Duration::from_millis(1500)).is_empty());
+        assert_eq!(engine.feed("--More--", start + Duration::from_millis(1500)).len(), 1);
+    }
+
+    #[test]
+    fn test_disabled_and_replaced_rules() {
+        let mut disabled = create_rule("login", "login:", TriggerMode::OneShot, 0);
+        disabled.enabled = false;
+        let other = create_rule("other", "never", TriggerMode::Repeat, 0);
+        let mut engine = TriggerEngine::new(&[disabled, other.clone()]);
+        let now = Instant::now();
+        assert!(engine.feed("login:", now).is_empty());
+
+        // 有効化しても受信済みのデータには反応しない
+        engine.set_rules(&[create_rule("login", "login:", TriggerMode::OneShot, 0), other]);
+        assert!(engine.feed("", now).is_empty());
+        assert_eq!(names(engine.feed("\r\nlogin:", now)), vec!["login"]);
+    }
+}
\ No newline at end of file
@@ -1,53 +1,154 @@
+// Tauri のコマンド層。CLI（--no-default-features）のビルドには含めない
+#[cfg(feature = "gui")]
 mod commands;
-mod communication;
-mod models;
-mod services;
-mod utils;
+// CLI（src/bin）からも通信処理・モデル・サービスを使う
+pub mod communication;
+pub mod models;
+pub mod services;
+pub mod utils;
 
+#[cfg(feature = "gui")]
 use commands::{
-    AppState, TerminalState, SettingsState,
+    AppState, TerminalState, SettingsState, VaultState, MacroState, ScriptState, ScheduleState, ApiServerState, run_vault_auto_lock,
     // Connection commands
-    get_serial_ports, get_serial_ports_info, connect_device, disconnect_device,
-    send_message, get_connection_status, get_connection_info,
+    get_serial_ports, get_serial_ports_info, connect_device, connect_profile, disconnect_device,
+    send_message, get_connection_status, get_connection_info, get_connected_profile,
+    set_profile_triggers,
     // Terminal commands
-    get_terminal_config, update_terminal_config, get_terminal_messages,
+    get_terminal_config, update_terminal_config, get_effective_terminal_config, get_terminal_messages,
     add_terminal_message, clear_terminal_messages, get_command_history,
     add_command_to_history, search_command_history, export_terminal_messages,
+    get_session_log_files, get_session_log_dir,
     // Settings commands
     get_app_config, update_app_config, get_profiles, add_profile,
     update_profile, delete_profile, get_active_profile, set_active_profile,
     get_recent_profiles, duplicate_profile, export_profiles, import_profiles,
-    validate_profile,
+    preview_profile_merge, merge_profiles, import_external_sessions,
+    validate_profile, get_profile_groups, get_group_profiles, create_profile_group,
+    rename_profile_group, delete_profile_group, reorder_profile_groups,
+    move_profile_to_group, set_profile_tags, get_profile_tags, search_profiles,
+    // Vault commands
+    get_vault_status, create_vault, unlock_vault, lock_vault, list_secrets, store_secret,
+    reveal_secret, delete_secret,
+    // Macro commands
+    list_macros, save_macro, delete_macro, run_macro, abort_macro,
+    // Script commands
+    run_script, cancel_script,
+    // Scheduled send commands
+    add_scheduled_job, list_scheduled_jobs, pause_scheduled_job, resume_scheduled_job,
+    remove_scheduled_job,
+    // Test runner commands
+    run_test_suite, export_test_report,
+    // Local API commands
+    get_api_server_status, set_api_server_enabled, regenerate_api_token,
+    // PTY bridge commands
+    open_pty_bridge, close_pty_bridge, get_pty_bridge,
+    // TCP bridge commands
+    start_tcp_bridge, stop_tcp_bridge, get_tcp_bridge_status, set_bridge_client_role,
+    // Highlight commands
+    get_highlight_rules, save_highlight_rules, get_alert_counts,
 };
 
+#[cfg(feature = "gui")]
+use services::{DataMasker, SessionLogger, SettingsPersistence};
+#[cfg(feature = "gui")]
+use std::sync::Arc;
+#[cfg(feature = "gui")]
+use tauri::{Manager, RunEvent};
+#[cfg(feature = "gui")]
+use tokio::sync::Mutex;
+#[cfg(feature = "gui")]
+use tracing::warn;
+#[cfg(feature = "gui")]
 use tracing_subscriber;
 
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // ログ初期化
     tracing_subscriber::fmt::init();
 
-    // アプリケーション状態を初期化
-    let app_state = AppState::new();
-    let terminal_state = TerminalState::new();
-    let settings_state = SettingsState::new();
-
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(app_state)
-        .manage(terminal_state)
-        .manage(settings_state)
+        .setup(|app| {
+            // 設定・プロファイル・コマンド履歴はアプリの設定ディレクトリに保存する
+            let config_dir = app.path().app_config_dir()?;
+            let persistence = Arc::new(SettingsPersistence::new(config_dir.clone()));
+            let app_config = persistence.load_app_config();
+            let settings_state = SettingsState::new(
+                app_config.clone(),
+                persistence.load_profiles(),
+                persistence.clone(),
+            );
+
+            // セッションログはアプリのデータディレクトリ配下に保存する
+            let log_dir = app.path().app_data_dir()?.join("logs");
+            let session_logger = SessionLogger::new(log_dir, app_config.logging.clone());
+            if let Err(e) = session_logger.cleanup_expired() {
+                warn!("Failed to clean up expired session logs: {}", e);
+            }
+
+            let terminal_state = TerminalState::with_config(
+                app_config.terminal.clone(),
+                persistence.load_command_history(),
+            );
+            let app_state = AppState::new(
+                terminal_state.messages.clone(),
+                Arc::new(Mutex::new(session_logger)),
+                DataMasker::new_lossy(&app_config.logging),
+                &app_config.highlight_rules,
+                app_config.terminal.clone(),
+            );
+
+            // 資格情報ストア
+            let vault_state = VaultState::load(
+                config_dir.join("vault.json"),
+                app_config.security.auto_lock_timeout_minutes,
+            )?;
+            tauri::async_runtime::spawn(run_vault_auto_lock(
+                app.handle().clone(),
+                vault_state.vault.clone(),
+            ));
+
+            let schedule_state = ScheduleState::new(app_state.connection_manager.clone());
+
+            app.manage(settings_state);
+            app.manage(app_state);
+            app.manage(terminal_state);
+            app.manage(vault_state);
+            app.manage(MacroState::new());
+            app.manage(ScriptState::new());
+            app.manage(schedule_state);
+            app.manage(ApiServerState::new());
+
+            // ローカル API は有効にしている場合のみ起動する
+            if app_config.api_server.enabled {
+                let app_handle = app.handle().clone();
+                let config = app_config.api_server.clone();
+                tauri::async_runtime::spawn(async move {
+                    let api_state = app_handle.state::<ApiServerState>();
+                    if let Err(e) = api_state.apply(&config, &app_handle).await {
+                        warn!("{}", e);
+                    }
+                });
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             // Connection commands
             get_serial_ports,
             get_serial_ports_info,
             connect_device,
+            connect_profile,
             disconnect_device,
             send_message,
             get_connection_status,
             get_connection_info,
+            get_connected_profile,
+            set_profile_triggers,
             // Terminal commands
             get_terminal_config,
+            get_effective_terminal_config,
             update_terminal_config,
             get_terminal_messages,
             add_terminal_message,
@@ -56,6 +157,8 @@ pub fn run() {
             add_command_to_history,
             search_command_history,
             export_terminal_messages,
+            get_session_log_files,
+            get_session_log_dir,
             // Settings commands
             get_app_config,
             update_app_config,
@@ -69,8 +172,71 @@ pub fn run() {
             duplicate_profile,
             export_profiles,
             import_profiles,
+            preview_profile_merge,
+            merge_profiles,
+            import_external_sessions,
             validate_profile,
+            get_profile_groups,
+            get_group_profiles,
+            create_profile_group,
+            rename_profile_group,
+            delete_profile_group,
+            reorder_profile_groups,
+            move_profile_to_group,
+            set_profile_tags,
+            get_profile_tags,
+            search_profiles,
+            // Vault commands
+            get_vault_status,
+            create_vault,
+            unlock_vault,
+            lock_vault,
+            list_secrets,
+            store_secret,
+            reveal_secret,
+            delete_secret,
+            // Macro commands
+            list_macros,
+            save_macro,
+            delete_macro,
+            run_macro,
+            abort_macro,
+            // Script commands
+            run_script,
+            cancel_script,
+            // Scheduled send commands
+            add_scheduled_job,
+            list_scheduled_jobs,
+            pause_scheduled_job,
+            resume_scheduled_job,
+            remove_scheduled_job,
+            // Test runner commands
+            run_test_suite,
+            export_test_report,
+            // Local API commands
+            get_api_server_status,
+            set_api_server_enabled,
+            regenerate_api_token,
+            // PTY bridge commands
+            open_pty_bridge,
+            close_pty_bridge,
+            get_pty_bridge,
+            // TCP bridge commands
+            start_tcp_bridge,
+            stop_tcp_bridge,
+            get_tcp_bridge_status,
+            set_bridge_client_role,
+            // Highlight commands
+            get_highlight_rules,
+            save_highlight_rules,
+            get_alert_counts,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // 終了前に未保存の設定を書き込む
+            if let RunEvent::Exit = event {
+                app_handle.state::<SettingsState>().persistence.flush();
+            }
+        });
 }
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+// 接続中のシリアルポートを TCP で公開するブリッジの設定
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TcpBridgeConfig {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String, // LAN に公開する場合は "0.0.0.0"
+    pub port: u16,
+    #[serde(default)]
+    pub framing: BridgeFraming,
+    #[serde(default)]
+    pub default_role: ClientRole, // 新しく接続したクライアントの権限
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum BridgeFraming {
+    #[default]
+    Raw, // バイト列をそのまま流す
+    Telnet, // telnet クライアント向けに IAC をエスケープし、オプション交渉に応じる
+    Rfc2217, // Telnet に加えて COM-PORT-OPTION（RFC 2217）に応じる
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ClientRole {
+    #[default]
+    ReadOnly, // 受信データの閲覧のみ。送ったデータは捨てる
+    ReadWrite,
+}
+
+// ブリッジに接続中のクライアント
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BridgeClient {
+    pub id: String,
+    pub address: String,
+    pub role: ClientRole,
+    pub connected_at: DateTime<Utc>,
+    pub bytes_from_client: u64, // デバイスへ送信したバイト数
+    pub bytes_to_client: u64,
+}
+
+fn default_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+impl TcpBridgeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.bind_address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid bind address: {}", self.bind_address))?;
+        Ok(())
+    }
+}
+
+impl BridgeClient {
+    pub fn new(address: String, role: ClientRole) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            address,
+            role,
+            connected_at: Utc::now(),
+            bytes_from_client: 0,
+            bytes_to_client: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_defaults() {
+        let config: TcpBridgeConfig = serde_json::from_str(r#"{"port":2217}"#).unwrap();
+        assert_eq!(config.bind_address, "127.0.0.1");
+        assert_eq!(config.framing, BridgeFraming::Raw);
+        assert_eq!(config.default_role, ClientRole::ReadOnly);
+        assert!(config.validate().is_ok());
+
+        let config = TcpBridgeConfig { bind_address: "desk-pc".to_string(), ..config };
+        assert!(config.validate().is_err());
+    }
+}
\ No newline at end of file
@@ -2,7 +2,9 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use super::{TerminalOverrides, TriggerRule};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ConnectionConfig {
     pub id: String,
     pub name: String,
@@ -11,6 +13,32 @@ pub struct ConnectionConfig {
     pub tcp_config: Option<TcpConfig>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub secret_refs: Vec<SecretRef>, // 資格情報ストアに保存された秘密情報への参照
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub last_connected_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub terminal_overrides: TerminalOverrides, // プロファイル固有の端末設定
+    #[serde(default)]
+    pub triggers: Vec<TriggerRule>, // 受信データへの自動応答
+}
+
+// 資格情報ストアに保存する秘密情報の種類
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SecretKind {
+    LoginPassword,
+    ProxyPassword,
+    TlsKeyPassphrase,
+    Other,
+}
+
+// プロファイルから秘密情報を参照する。値そのものはプロファイルに保存しない
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SecretRef {
+    pub kind: SecretKind,
+    pub secret_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -19,7 +47,7 @@ pub enum ConnectionType {
     Tcp,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct SerialConfig {
     pub port: String,
     pub baud_rate: u32,
@@ -29,7 +57,7 @@ pub struct SerialConfig {
     pub flow_control: FlowControl,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TcpConfig {
     pub host: String,
     pub port: u16,
@@ -69,6 +97,13 @@ pub enum FlowControl {
     Hardware,
 }
 
+// シリアルポートの制御線
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ControlLine {
+    Dtr,
+    Rts,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ConnectionStatus {
     Disconnected,
@@ -123,7 +158,6 @@ impl Default for TcpConfig {
 }
 
 impl ConnectionConfig {
-    #[allow(dead_code)]
     pub fn new_serial(name: String, serial_config: SerialConfig) -> Self {
         let now = Utc::now();
         Self {
@@ -134,10 +168,14 @@ impl ConnectionConfig {
             tcp_config: None,
             created_at: now,
             updated_at: now,
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn new_tcp(name: String, tcp_config: TcpConfig) -> Self {
         let now = Utc::now();
         Self {
@@ -148,6 +186,27 @@ impl ConnectionConfig {
             tcp_config: Some(tcp_config),
             created_at: now,
             updated_at: now,
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
+        }
+    }
+
+    // 同じ接続先（シリアルポート、またはホストとポート）か
+    pub fn same_target(&self, other: &ConnectionConfig) -> bool {
+        if self.connection_type != other.connection_type {
+            return false;
+        }
+        match self.connection_type {
+            ConnectionType::Serial => {
+                self.serial_config.as_ref().map(|c| &c.port) == other.serial_config.as_ref().map(|c| &c.port)
+            }
+            ConnectionType::Tcp => {
+                self.tcp_config.as_ref().map(|c| (c.host.to_lowercase(), c.port))
+                    == other.tcp_config.as_ref().map(|c| (c.host.to_lowercase(), c.port))
+            }
         }
     }
 }
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// 受信データ中の一致箇所を強調表示するルール。重要度によっては警告も出す
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HighlightRule {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub color: Option<String>, // CSS の色（例: "#ff5555"）。None の場合は重要度ごとの色
+    #[serde(default)]
+    pub notify: bool, // 一致したときに音や通知で知らせる
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+    Critical, // 一致すると alert-raised で通知する
+}
+
+// メッセージ内の一致範囲。位置は UTF-16 単位（JavaScript の文字列の添字と同じ）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HighlightSpan {
+    pub rule_id: String,
+    pub start: usize,
+    pub end: usize,
+    pub severity: Severity,
+    pub color: Option<String>,
+}
+
+// 重要度が Critical、または notify を指定したルールに一致したときの通知
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Alert {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub severity: Severity,
+    pub notify: bool,
+    pub message_id: String,
+    pub matched: String, // メッセージ内で最初に一致した文字列
+    pub timestamp: DateTime<Utc>,
+}
+
+// 接続中のセッションでルールに一致した回数
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AlertCount {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub severity: Severity,
+    pub count: u64,
+}
+
+impl HighlightRule {
+    pub fn new(name: String, pattern: String, severity: Severity) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            pattern,
+            severity,
+            color: None,
+            notify: false,
+            enabled: true,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Highlight rule name is required".to_string());
+        }
+        let regex = Regex::new(&self.pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+        // 空文字列に一致するパターンはすべてのメッセージに一致してしまう
+        if regex.is_match("") {
+            return Err("Pattern must not match empty text".to_string());
+        }
+        if self.color.as_deref().is_some_and(|color| color.trim().is_empty()) {
+            return Err("Color must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    // alert-raised で通知するか
+    pub fn raises_alert(&self) -> bool {
+        self.severity == Severity::Critical || self.notify
+    }
+}
+
+// 組み込みのルール。初回起動時の設定に使う
+pub fn default_highlight_rules() -> Vec<HighlightRule> {
+    vec![
+        HighlightRule::new("Panic".to_string(), r"(?i)\bpanic".to_string(), Severity::Critical),
+        HighlightRule::new("HardFault".to_string(), r"HardFault".to_string(), Severity::Critical),
+        HighlightRule::new("Assert".to_string(), r"\bASSERT".to_string(), Severity::Error),
+        // ESP-IDF のエラーログ（例: "E (12345) wifi: ..."）
+        HighlightRule::new("ESP-IDF error".to_string(), r"\bE \(\d+\)".to_string(), Severity::Error),
+        HighlightRule::new("ESP-IDF warning".to_string(), r"\bW \(\d+\)".to_string(), Severity::Warning),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        for rule in default_highlight_rules() {
+            assert!(rule.validate().is_ok(), "{}", rule.name);
+        }
+
+        let mut rule = HighlightRule::new("error".to_string(), "(".to_string(), Severity::Error);
+        assert!(rule.validate().is_err());
+        rule.pattern = "x*".to_string();
+        assert!(rule.validate().is_err());
+        rule.pattern = "ERR".to_string();
+        rule.color = Some(" ".to_string());
+        assert!(rule.validate().is_err());
+    }
+
+    #[test]
+    fn test_raises_alert() {
+        let mut rule = HighlightRule::new("warn".to_string(), "WARN".to_string(), Severity::Warning);
+        assert!(!rule.raises_alert());
+        rule.notify = true;
+        assert!(rule.raises_alert());
+        rule.notify = false;
+        rule.severity = Severity::Critical;
+        assert!(rule.raises_alert());
+        assert!(Severity::Critical > Severity::Warning);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::ControlLine;
+
+// 名前付きのコマンド列（マクロ）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Macro {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<MacroStep>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum MacroStep {
+    // テキストを送信する。{{secret:<id>}} は接続中プロファイルの資格情報に置き換える
+    SendText {
+        text: String,
+        #[serde(default)]
+        append_line_ending: bool,
+    },
+    // 16進数で指定したバイト列を送信する（例: "0D 0A"）
+    SendHex { hex: String },
+    Wait { ms: u64 },
+    // 受信データが正規表現に一致するまで待つ
+    WaitFor {
+        pattern: String,
+        timeout_ms: u64,
+        #[serde(default = "StepTarget::next")]
+        on_match: StepTarget,
+        #[serde(default = "StepTarget::fail")]
+        on_timeout: StepTarget,
+    },
+    SetControlLine { line: ControlLine, level: bool },
+}
+
+// WaitFor の結果に応じた次の動作
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum StepTarget {
+    Next,
+    Goto { step: usize }, // 0 始まりのステップ番号
+    Stop, // 成功として終了
+    Fail,
+}
+
+impl StepTarget {
+    fn next() -> Self {
+        StepTarget::Next
+    }
+
+    fn fail() -> Self {
+        StepTarget::Fail
+    }
+}
+
+impl Macro {
+    pub fn new(name: String, description: String, steps: Vec<MacroStep>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            description,
+            steps,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    // 実行前に検出できる誤りを確認する
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Macro name is required".to_string());
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            match step {
+                MacroStep::SendHex { hex } => {
+                    parse_hex(hex).map_err(|e| format!("Step {}: {}", index + 1, e))?;
+                }
+                MacroStep::WaitFor { pattern, on_match, on_timeout, .. } => {
+                    Regex::new(pattern).map_err(|e| format!("Step {}: invalid pattern: {}", index + 1, e))?;
+                    for target in [on_match, on_timeout] {
+                        if let StepTarget::Goto { step } = target {
+                            if *step >= self.steps.len() {
+                                return Err(format!("Step {}: goto target {} does not exist", index + 1, step + 1));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// 空白・カンマ区切りまたは連続した16進数をバイト列に変換する
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in '{}'", text));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex value in '{}'", text))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("0D 0a").unwrap(), vec![0x0d, 0x0a]);
+        assert_eq!(parse_hex("0x01,0x02").unwrap(), vec![0x01, 0x02]);
+        assert_eq!(parse_hex("DEADbeef").unwrap(), vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(parse_hex("ABC").is_err());
+        assert!(parse_hex("ZZ").is_err());
+    }
+
+    #[test]
+    fn test_step_deserialize_defaults() {
+        let step: MacroStep = serde_json::from_str(r#"{"type":"WaitFor","pattern":"login:","timeout_ms":1000}"#).unwrap();
+        assert_eq!(
+            step,
+            MacroStep::WaitFor {
+                pattern: "login:".to_string(),
+                timeout_ms: 1000,
+                on_match: StepTarget::Next,
+                on_timeout: StepTarget::Fail,
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_steps() {
+        let bad_goto = Macro::new(
+            "boot".to_string(),
+            String::new(),
+            vec![MacroStep::WaitFor {
+                pattern: "ok".to_string(),
+                timeout_ms: 100,
+                on_match: StepTarget::Next,
+                on_timeout: StepTarget::Goto { step: 3 },
+            }],
+        );
+        assert!(bad_goto.validate().is_err());
+
+        let bad_pattern = Macro::new(
+            "boot".to_string(),
+            String::new(),
+            vec![MacroStep::WaitFor {
+                pattern: "(".to_string(),
+                timeout_ms: 100,
+                on_match: StepTarget::Next,
+                on_timeout: StepTarget::Fail,
+            }],
+        );
+        assert!(bad_pattern.validate().is_err());
+
+        let valid = Macro::new(
+            "boot".to_string(),
+            String::new(),
+            vec![MacroStep::SendHex { hex: "0d0a".to_string() }, MacroStep::Wait { ms: 10 }],
+        );
+        assert!(valid.validate().is_ok());
+    }
+}
\ No newline at end of file
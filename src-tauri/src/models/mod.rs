@@ -1,7 +1,19 @@
+pub mod bridge;
 pub mod connection;
+pub mod highlight;
+pub mod macros;
+pub mod schedule;
 pub mod settings;
 pub mod terminal;
+pub mod test_suite;
+pub mod triggers;
 
+pub use bridge::*;
 pub use connection::*;
+pub use highlight::*;
+pub use macros::*;
+pub use schedule::*;
 pub use settings::*;
-pub use terminal::*;
\ No newline at end of file
+pub use terminal::*;
+pub use test_suite::*;
+pub use triggers::*;
\ No newline at end of file
@@ -0,0 +1,148 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::parse_hex;
+
+// 短すぎる間隔で送信し続けないための下限
+const MIN_INTERVAL_MS: u64 = 10;
+
+// 定期送信ジョブの設定
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SendJobSpec {
+    pub name: String,
+    pub payload: String,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    pub schedule: JobSchedule,
+    #[serde(default)]
+    pub max_count: Option<u64>, // None の場合は停止するまで送信し続ける
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum PayloadFormat {
+    #[default]
+    Text,
+    Hex, // "0D 0A" のような16進数表記
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum JobSchedule {
+    // interval_ms ごと。jitter_ms を指定すると 0〜jitter_ms のランダムな遅延を加える
+    Interval {
+        interval_ms: u64,
+        #[serde(default)]
+        jitter_ms: u64,
+    },
+    // 毎日指定した時刻（ローカル時刻）
+    Daily { times: Vec<NaiveTime> },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Paused,
+    Completed, // 送信回数の上限に達した
+}
+
+// 定期送信ジョブと実行状況
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SendJob {
+    pub id: String,
+    #[serde(flatten)]
+    pub spec: SendJobSpec,
+    pub state: JobState,
+    pub sent_count: u64,
+    pub last_sent_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>, // 未接続などで送信できなかった場合
+    pub created_at: DateTime<Utc>,
+}
+
+impl SendJobSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Job name is required".to_string());
+        }
+        if self.payload.is_empty() {
+            return Err("Payload is required".to_string());
+        }
+        if self.format == PayloadFormat::Hex {
+            parse_hex(&self.payload)?;
+        }
+        if self.max_count == Some(0) {
+            return Err("Count limit must be at least 1".to_string());
+        }
+        match &self.schedule {
+            JobSchedule::Interval { interval_ms, .. } if *interval_ms < MIN_INTERVAL_MS => {
+                Err(format!("Interval must be at least {} ms", MIN_INTERVAL_MS))
+            }
+            JobSchedule::Daily { times } if times.is_empty() => Err("At least one time is required".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // 送信するバイト列と記録用の表示内容、エンコーディング
+    pub fn encode(&self) -> Result<(Vec<u8>, String, &'static str), String> {
+        match self.format {
+            PayloadFormat::Text => Ok((self.payload.as_bytes().to_vec(), self.payload.clone(), "UTF-8")),
+            PayloadFormat::Hex => Ok((parse_hex(&self.payload)?, self.payload.clone(), "HEX")),
+        }
+    }
+}
+
+impl SendJob {
+    pub fn new(spec: SendJobSpec) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            spec,
+            state: JobState::Running,
+            sent_count: 0,
+            last_sent_at: None,
+            last_error: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_spec(schedule: JobSchedule) -> SendJobSpec {
+        SendJobSpec {
+            name: "heartbeat".to_string(),
+            payload: "AT\r".to_string(),
+            format: PayloadFormat::Text,
+            schedule,
+            max_count: None,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(create_spec(JobSchedule::Interval { interval_ms: 1000, jitter_ms: 0 }).validate().is_ok());
+        assert!(create_spec(JobSchedule::Interval { interval_ms: 1, jitter_ms: 0 }).validate().is_err());
+        assert!(create_spec(JobSchedule::Daily { times: Vec::new() }).validate().is_err());
+
+        let mut hex = create_spec(JobSchedule::Interval { interval_ms: 1000, jitter_ms: 0 });
+        hex.format = PayloadFormat::Hex;
+        assert!(hex.validate().is_err());
+        hex.payload = "01 03 00 00".to_string();
+        assert!(hex.validate().is_ok());
+        assert_eq!(hex.encode().unwrap().0, vec![0x01, 0x03, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_deserialize_daily_schedule() {
+        let schedule: JobSchedule = serde_json::from_str(r#"{"type":"Daily","times":["08:30:00","17:00:00"]}"#).unwrap();
+        assert_eq!(
+            schedule,
+            JobSchedule::Daily {
+                times: vec![
+                    NaiveTime::from_hms_opt(8, 30, 0).unwrap(),
+                    NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+                ],
+            }
+        );
+    }
+}
\ No newline at end of file
@@ -1,8 +1,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use super::{ConnectionConfig, TerminalConfig};
+use super::{default_highlight_rules, ConnectionConfig, HighlightRule, Macro, TerminalConfig};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
@@ -11,6 +11,10 @@ pub struct AppConfig {
     pub window: WindowConfig,
     pub logging: LoggingConfig,
     pub security: SecurityConfig,
+    #[serde(default)]
+    pub api_server: ApiServerConfig,
+    #[serde(default = "default_highlight_rules")]
+    pub highlight_rules: Vec<HighlightRule>,
     pub last_updated: DateTime<Utc>,
 }
 
@@ -40,6 +44,22 @@ pub struct LoggingConfig {
     pub retention_days: u32,
     pub log_level: LogLevel,
     pub mask_sensitive_data: bool,
+    #[serde(default)]
+    pub mask_live_view: bool, // ターミナル表示にもマスキングを適用する
+    #[serde(default)]
+    pub masking_rules: Vec<MaskingRule>,
+    #[serde(default)]
+    pub exclude_sensitive_from_history: bool, // マスキングルールに一致するコマンドを履歴に残さない
+}
+
+// ユーザー定義のマスキングルール
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaskingRule {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,     // 正規表現
+    pub replacement: String, // 置換テンプレート（$1 などでキャプチャを参照可能）
+    pub enabled: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -53,11 +73,18 @@ pub enum LogLevel {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SecurityConfig {
-    pub encrypt_passwords: bool,
     pub require_confirmation_for_destructive_actions: bool,
     pub auto_lock_timeout_minutes: Option<u32>,
 }
 
+// 外部ツールから操作するためのローカル API（127.0.0.1 のみで待ち受ける）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ApiServerConfig {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: String, // 接続後に authenticate で送る。初回有効化時に生成する
+}
+
 // プロファイル管理
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProfileManager {
@@ -65,6 +92,10 @@ pub struct ProfileManager {
     pub active_profile_id: Option<String>,
     pub last_used_profiles: Vec<String>, // 最近使用したプロファイルID
     pub groups: HashMap<String, ProfileGroup>,
+    #[serde(default)]
+    pub group_order: Vec<String>, // グループの表示順
+    #[serde(default)]
+    pub macros: Vec<Macro>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -76,6 +107,47 @@ pub struct ProfileGroup {
     pub color: Option<String>,
 }
 
+// プロファイルのマージ取り込み
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MergeStatus {
+    Added,
+    Updated,
+    Unchanged,
+    Conflict,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MergeMatch {
+    Id,
+    Name,
+}
+
+// 競合したプロファイルの解決方法
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ConflictStrategy {
+    KeepMine,
+    TakeTheirs,
+    KeepBoth,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MergeEntry {
+    pub incoming_id: String,
+    pub name: String,
+    pub status: MergeStatus,
+    pub existing_id: Option<String>, // 対応する既存プロファイル
+    pub matched_by: Option<MergeMatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub kept_mine: usize,
+    pub kept_both: usize,
+}
+
 // キーボードショートカット
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KeyboardShortcuts {
@@ -98,6 +170,8 @@ impl Default for AppConfig {
             window: WindowConfig::default(),
             logging: LoggingConfig::default(),
             security: SecurityConfig::default(),
+            api_server: ApiServerConfig::default(),
+            highlight_rules: default_highlight_rules(),
             last_updated: Utc::now(),
         }
     }
@@ -126,6 +200,19 @@ impl Default for LoggingConfig {
             retention_days: 30,
             log_level: LogLevel::Info,
             mask_sensitive_data: true,
+            mask_live_view: false,
+            masking_rules: Vec::new(),
+            exclude_sensitive_from_history: true,
+        }
+    }
+}
+
+impl Default for ApiServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7878,
+            token: String::new(),
         }
     }
 }
@@ -133,7 +220,6 @@ impl Default for LoggingConfig {
 impl Default for SecurityConfig {
     fn default() -> Self {
         Self {
-            encrypt_passwords: true,
             require_confirmation_for_destructive_actions: true,
             auto_lock_timeout_minutes: None,
         }
@@ -147,6 +233,8 @@ impl Default for ProfileManager {
             active_profile_id: None,
             last_used_profiles: Vec::new(),
             groups: HashMap::new(),
+            group_order: Vec::new(),
+            macros: Vec::new(),
         }
     }
 }
@@ -184,6 +272,11 @@ impl ProfileManager {
         // 最近使用したリストからも削除
         self.last_used_profiles.retain(|id| id != profile_id);
         
+        // 所属しているグループからも削除
+        for group in self.groups.values_mut() {
+            group.profile_ids.retain(|id| id != profile_id);
+        }
+        
         self.profiles.len() < original_len
     }
 
@@ -208,13 +301,22 @@ impl ProfileManager {
         }
     }
 
+    // プロファイルで接続したことを記録する（アクティブ化・最近使用した一覧・最終接続日時）
+    pub fn record_connection(&mut self, profile_id: &str) -> bool {
+        let Some(profile) = self.get_profile_mut(profile_id) else {
+            return false;
+        };
+        profile.last_connected_at = Some(Utc::now());
+        self.set_active_profile(profile_id.to_string());
+        true
+    }
+
     pub fn get_active_profile(&self) -> Option<&ConnectionConfig> {
         self.active_profile_id
             .as_ref()
             .and_then(|id| self.get_profile(id))
     }
 
-    #[allow(dead_code)]
     pub fn get_profiles_by_group(&self, group_id: &str) -> Vec<&ConnectionConfig> {
         if let Some(group) = self.groups.get(group_id) {
             group
@@ -227,13 +329,578 @@ impl ProfileManager {
         }
     }
 
-    #[allow(dead_code)]
     pub fn add_group(&mut self, group: ProfileGroup) {
+        if !self.group_order.contains(&group.id) {
+            self.group_order.push(group.id.clone());
+        }
         self.groups.insert(group.id.clone(), group);
     }
 
-    #[allow(dead_code)]
     pub fn remove_group(&mut self, group_id: &str) -> bool {
+        self.group_order.retain(|id| id != group_id);
         self.groups.remove(group_id).is_some()
     }
+
+    pub fn rename_group(&mut self, group_id: &str, name: String) -> bool {
+        match self.groups.get_mut(group_id) {
+            Some(group) => {
+                group.name = name;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 表示順に並べたグループ一覧
+    pub fn ordered_groups(&self) -> Vec<&ProfileGroup> {
+        self.group_order
+            .iter()
+            .filter_map(|id| self.groups.get(id))
+            .collect()
+    }
+
+    // すべてのグループIDを新しい順序で指定する
+    pub fn reorder_groups(&mut self, group_ids: Vec<String>) -> Result<(), String> {
+        let mut sorted_new = group_ids.clone();
+        sorted_new.sort();
+        sorted_new.dedup();
+        let mut sorted_current: Vec<String> = self.groups.keys().cloned().collect();
+        sorted_current.sort();
+
+        if sorted_new.len() != group_ids.len() || sorted_new != sorted_current {
+            return Err("Group order must contain every group exactly once".to_string());
+        }
+
+        self.group_order = group_ids;
+        Ok(())
+    }
+
+    // プロファイルを指定したグループへ移動する。None の場合はどのグループにも属さない
+    pub fn move_profile_to_group(
+        &mut self,
+        profile_id: &str,
+        group_id: Option<&str>,
+        position: Option<usize>,
+    ) -> Result<(), String> {
+        if self.get_profile(profile_id).is_none() {
+            return Err(format!("Profile not found: {}", profile_id));
+        }
+        if let Some(group_id) = group_id {
+            if !self.groups.contains_key(group_id) {
+                return Err(format!("Group not found: {}", group_id));
+            }
+        }
+
+        for group in self.groups.values_mut() {
+            group.profile_ids.retain(|id| id != profile_id);
+        }
+
+        if let Some(group) = group_id.and_then(|id| self.groups.get_mut(id)) {
+            let index = position.unwrap_or(group.profile_ids.len()).min(group.profile_ids.len());
+            group.profile_ids.insert(index, profile_id.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn group_of_profile(&self, profile_id: &str) -> Option<&ProfileGroup> {
+        self.ordered_groups()
+            .into_iter()
+            .find(|group| group.profile_ids.iter().any(|id| id == profile_id))
+    }
+
+    // 前後の空白を除き、重複を取り除いたタグを設定する
+    pub fn set_profile_tags(&mut self, profile_id: &str, tags: Vec<String>) -> bool {
+        let Some(profile) = self.get_profile_mut(profile_id) else {
+            return false;
+        };
+
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim().to_string();
+            if !tag.is_empty() && !normalized.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                normalized.push(tag);
+            }
+        }
+        profile.tags = normalized;
+        true
+    }
+
+    // 使用されているすべてのタグ（昇順）
+    pub fn all_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .profiles
+            .iter()
+            .flat_map(|profile| profile.tags.iter().cloned())
+            .collect();
+        tags.sort_by_key(|tag| tag.to_lowercase());
+        tags.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+        tags
+    }
+
+    // 名前・タグ・グループで検索する。
+    // 「tag:xxx」「group:xxx」の形式で条件を指定でき、複数の条件はすべて満たすものを返す
+    pub fn search_profiles(&self, query: &str) -> Vec<&ConnectionConfig> {
+        let terms: Vec<String> = query.split_whitespace().map(|term| term.to_lowercase()).collect();
+
+        self.profiles
+            .iter()
+            .filter(|profile| {
+                let group_name = self
+                    .group_of_profile(&profile.id)
+                    .map(|group| group.name.to_lowercase());
+                terms.iter().all(|term| {
+                    if let Some(tag) = term.strip_prefix("tag:") {
+                        profile.tags.iter().any(|t| t.to_lowercase() == tag)
+                    } else if let Some(group) = term.strip_prefix("group:") {
+                        group_name.as_deref().is_some_and(|name| name.contains(group))
+                    } else {
+                        profile.name.to_lowercase().contains(term.as_str())
+                            || profile.tags.iter().any(|t| t.to_lowercase() == *term)
+                            || group_name.as_deref().is_some_and(|name| name.contains(term.as_str()))
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // 取り込むプロファイルを既存のものと照合する（変更は行わない）。
+    // ID が一致し取り込む側の方が新しければ更新、既存側の方が新しい場合や
+    // 名前だけが一致して内容が異なる場合は競合とする
+    pub fn preview_merge(&self, incoming: &[ConnectionConfig]) -> Vec<MergeEntry> {
+        incoming
+            .iter()
+            .map(|profile| {
+                let (existing, matched_by) = match self.get_profile(&profile.id) {
+                    Some(existing) => (Some(existing), Some(MergeMatch::Id)),
+                    None => match self.find_profile_by_name(&profile.name) {
+                        Some(existing) => (Some(existing), Some(MergeMatch::Name)),
+                        None => (None, None),
+                    },
+                };
+
+                let status = match existing {
+                    None => MergeStatus::Added,
+                    Some(existing) if same_settings(existing, profile) => MergeStatus::Unchanged,
+                    Some(existing)
+                        if matched_by == Some(MergeMatch::Id) && profile.updated_at > existing.updated_at =>
+                    {
+                        MergeStatus::Updated
+                    }
+                    Some(_) => MergeStatus::Conflict,
+                };
+
+                MergeEntry {
+                    incoming_id: profile.id.clone(),
+                    name: profile.name.clone(),
+                    status,
+                    existing_id: existing.map(|p| p.id.clone()),
+                    matched_by,
+                }
+            })
+            .collect()
+    }
+
+    // プレビューと同じ照合結果で取り込む。競合にはすべて解決方法の指定が必要
+    pub fn apply_merge(
+        &mut self,
+        incoming: Vec<ConnectionConfig>,
+        resolutions: &HashMap<String, ConflictStrategy>,
+    ) -> Result<MergeSummary, String> {
+        let entries = self.preview_merge(&incoming);
+
+        let unresolved: Vec<&str> = entries
+            .iter()
+            .filter(|entry| entry.status == MergeStatus::Conflict && !resolutions.contains_key(&entry.incoming_id))
+            .map(|entry| entry.name.as_str())
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(format!("No resolution chosen for: {}", unresolved.join(", ")));
+        }
+
+        let mut summary = MergeSummary::default();
+        for (entry, profile) in entries.into_iter().zip(incoming) {
+            let strategy = match entry.status {
+                MergeStatus::Added => {
+                    // 同じファイル内で ID が重複している場合は新しい ID を振る
+                    let mut profile = profile;
+                    if self.get_profile(&profile.id).is_some() {
+                        profile.id = uuid::Uuid::new_v4().to_string();
+                    }
+                    self.add_profile(profile);
+                    summary.added += 1;
+                    continue;
+                }
+                MergeStatus::Unchanged => {
+                    summary.unchanged += 1;
+                    continue;
+                }
+                MergeStatus::Updated => ConflictStrategy::TakeTheirs,
+                MergeStatus::Conflict => resolutions[&entry.incoming_id],
+            };
+            let existing_id = entry.existing_id.unwrap_or_default();
+
+            match strategy {
+                ConflictStrategy::KeepMine => summary.kept_mine += 1,
+                ConflictStrategy::TakeTheirs => {
+                    // グループ所属や履歴を保つため、既存の ID と作成日時は残す。
+                    // 秘密情報の参照と接続日時はこの端末のものなので上書きしない
+                    if let Some(existing) = self.get_profile_mut(&existing_id) {
+                        let id = std::mem::take(&mut existing.id);
+                        let secret_refs = std::mem::take(&mut existing.secret_refs);
+                        let created_at = existing.created_at;
+                        let last_connected_at = existing.last_connected_at;
+                        *existing = profile;
+                        existing.id = id;
+                        existing.secret_refs = secret_refs;
+                        existing.created_at = created_at;
+                        existing.last_connected_at = last_connected_at;
+                    }
+                    summary.updated += 1;
+                }
+                ConflictStrategy::KeepBoth => {
+                    let mut profile = profile;
+                    profile.id = uuid::Uuid::new_v4().to_string();
+                    profile.name = self.unique_profile_name(&profile.name);
+                    self.add_profile(profile);
+                    summary.kept_both += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    // 同じ名前で同じ接続先のプロファイル。外部ファイルを再度取り込んだときの重複判定に使う
+    pub fn find_same_profile(&self, profile: &ConnectionConfig) -> Option<&ConnectionConfig> {
+        self.find_profile_by_name(&profile.name)
+            .filter(|existing| existing.same_target(profile))
+    }
+
+    fn find_profile_by_name(&self, name: &str) -> Option<&ConnectionConfig> {
+        let name = name.trim();
+        self.profiles.iter().find(|p| p.name.trim().eq_ignore_ascii_case(name))
+    }
+
+    // 「名前 (imported)」「名前 (imported 2)」... のうち未使用の名前
+    fn unique_profile_name(&self, name: &str) -> String {
+        let mut candidate = format!("{} (imported)", name);
+        let mut counter = 2;
+        while self.find_profile_by_name(&candidate).is_some() {
+            candidate = format!("{} (imported {})", name, counter);
+            counter += 1;
+        }
+        candidate
+    }
+
+    // 読み込んだデータの不整合（存在しないプロファイルの参照や表示順の欠落）を修正する
+    pub fn repair_groups(&mut self) {
+        let profile_ids: Vec<String> = self.profiles.iter().map(|p| p.id.clone()).collect();
+        let mut assigned: Vec<String> = Vec::new();
+
+        let mut seen = HashSet::new();
+        let mut order: Vec<String> = self
+            .group_order
+            .iter()
+            .filter(|id| self.groups.contains_key(*id) && seen.insert(*id))
+            .cloned()
+            .collect();
+        let mut missing: Vec<String> = self
+            .groups
+            .keys()
+            .filter(|id| !order.contains(id))
+            .cloned()
+            .collect();
+        missing.sort();
+        order.extend(missing);
+
+        // 1つのプロファイルは1つのグループにのみ所属する
+        for group_id in &order {
+            if let Some(group) = self.groups.get_mut(group_id) {
+                group.profile_ids.retain(|id| {
+                    if profile_ids.contains(id) && !assigned.contains(id) {
+                        assigned.push(id.clone());
+                        true
+                    } else {
+                        false
+                    }
+                });
+            }
+        }
+        self.group_order = order;
+    }
+
+    pub fn get_macro(&self, macro_id: &str) -> Option<&Macro> {
+        self.macros.iter().find(|m| m.id == macro_id)
+    }
+
+    // 同じIDのマクロがあれば置き換え、なければ追加する
+    pub fn save_macro(&mut self, mut macro_def: Macro) {
+        match self.macros.iter_mut().find(|m| m.id == macro_def.id) {
+            Some(existing) => {
+                macro_def.created_at = existing.created_at;
+                *existing = macro_def;
+            }
+            None => self.macros.push(macro_def),
+        }
+    }
+
+    pub fn remove_macro(&mut self, macro_id: &str) -> bool {
+        let original_len = self.macros.len();
+        self.macros.retain(|m| m.id != macro_id);
+        self.macros.len() < original_len
+    }
+}
+
+// ID・日時・秘密情報の参照を除いた設定内容が同じか
+fn same_settings(a: &ConnectionConfig, b: &ConnectionConfig) -> bool {
+    let mut normalized = b.clone();
+    normalized.id = a.id.clone();
+    normalized.created_at = a.created_at;
+    normalized.updated_at = a.updated_at;
+    normalized.last_connected_at = a.last_connected_at;
+    normalized.secret_refs = a.secret_refs.clone();
+    *a == normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{SecretKind, SecretRef};
+
+    fn create_test_profile(id: &str, name: &str, tags: &[&str]) -> ConnectionConfig {
+        let mut profile = ConnectionConfig::new_tcp(name.to_string(), Default::default());
+        profile.id = id.to_string();
+        profile.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        profile
+    }
+
+    fn create_test_group(id: &str, name: &str) -> ProfileGroup {
+        ProfileGroup {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: None,
+            profile_ids: Vec::new(),
+            color: None,
+        }
+    }
+
+    fn create_test_manager() -> ProfileManager {
+        let mut manager = ProfileManager::default();
+        manager.add_profile(create_test_profile("p1", "Gateway A", &["lab", "gateway"]));
+        manager.add_profile(create_test_profile("p2", "Sensor B", &["field"]));
+        manager.add_profile(create_test_profile("p3", "Gateway C", &["field", "gateway"]));
+        manager.add_group(create_test_group("g1", "Building 1"));
+        manager.add_group(create_test_group("g2", "Building 2"));
+        manager
+    }
+
+    #[test]
+    fn test_add_and_remove_group_keeps_order() {
+        let mut manager = create_test_manager();
+        assert_eq!(manager.group_order, vec!["g1", "g2"]);
+
+        assert!(manager.remove_group("g1"));
+        assert_eq!(manager.group_order, vec!["g2"]);
+        assert!(!manager.remove_group("g1"));
+    }
+
+    #[test]
+    fn test_reorder_groups() {
+        let mut manager = create_test_manager();
+
+        manager.reorder_groups(vec!["g2".to_string(), "g1".to_string()]).unwrap();
+        let names: Vec<&str> = manager.ordered_groups().iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["Building 2", "Building 1"]);
+
+        assert!(manager.reorder_groups(vec!["g1".to_string()]).is_err());
+        assert!(manager.reorder_groups(vec!["g1".to_string(), "g1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_move_profile_between_groups() {
+        let mut manager = create_test_manager();
+
+        manager.move_profile_to_group("p1", Some("g1"), None).unwrap();
+        manager.move_profile_to_group("p2", Some("g1"), Some(0)).unwrap();
+        assert_eq!(manager.groups["g1"].profile_ids, vec!["p2", "p1"]);
+
+        manager.move_profile_to_group("p1", Some("g2"), None).unwrap();
+        assert_eq!(manager.groups["g1"].profile_ids, vec!["p2"]);
+        assert_eq!(manager.groups["g2"].profile_ids, vec!["p1"]);
+
+        manager.move_profile_to_group("p1", None, None).unwrap();
+        assert!(manager.group_of_profile("p1").is_none());
+
+        assert!(manager.move_profile_to_group("missing", Some("g1"), None).is_err());
+        assert!(manager.move_profile_to_group("p1", Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn test_remove_profile_cleans_up_groups() {
+        let mut manager = create_test_manager();
+        manager.move_profile_to_group("p1", Some("g1"), None).unwrap();
+
+        assert!(manager.remove_profile("p1"));
+
+        assert!(manager.groups["g1"].profile_ids.is_empty());
+    }
+
+    #[test]
+    fn test_set_profile_tags_normalizes() {
+        let mut manager = create_test_manager();
+
+        assert!(manager.set_profile_tags("p2", vec![" field ".to_string(), "Field".to_string(), "".to_string(), "rack-3".to_string()]));
+
+        assert_eq!(manager.get_profile("p2").unwrap().tags, vec!["field", "rack-3"]);
+        assert!(!manager.set_profile_tags("missing", Vec::new()));
+    }
+
+    #[test]
+    fn test_all_tags() {
+        let manager = create_test_manager();
+        assert_eq!(manager.all_tags(), vec!["field", "gateway", "lab"]);
+    }
+
+    #[test]
+    fn test_search_profiles() {
+        let mut manager = create_test_manager();
+        manager.move_profile_to_group("p3", Some("g2"), None).unwrap();
+
+        let ids = |results: Vec<&ConnectionConfig>| -> Vec<String> {
+            results.iter().map(|p| p.id.clone()).collect()
+        };
+
+        assert_eq!(ids(manager.search_profiles("gateway")), vec!["p1", "p3"]);
+        assert_eq!(ids(manager.search_profiles("tag:field")), vec!["p2", "p3"]);
+        assert_eq!(ids(manager.search_profiles("tag:field gateway")), vec!["p3"]);
+        assert_eq!(ids(manager.search_profiles("group:building")), vec!["p3"]);
+        assert_eq!(ids(manager.search_profiles("")).len(), 3);
+    }
+
+    #[test]
+    fn test_repair_groups() {
+        let mut manager = create_test_manager();
+        manager.groups.get_mut("g1").unwrap().profile_ids = vec!["p1".to_string(), "gone".to_string()];
+        manager.groups.get_mut("g2").unwrap().profile_ids = vec!["p1".to_string(), "p2".to_string()];
+        manager.group_order = vec!["g1".to_string(), "deleted".to_string(), "g1".to_string()];
+
+        manager.repair_groups();
+
+        assert_eq!(manager.group_order, vec!["g1", "g2"]);
+        assert_eq!(manager.groups["g1"].profile_ids, vec!["p1"]);
+        assert_eq!(manager.groups["g2"].profile_ids, vec!["p2"]);
+    }
+
+    #[test]
+    fn test_record_connection() {
+        let mut manager = create_test_manager();
+        manager.set_active_profile("p1".to_string());
+
+        assert!(manager.record_connection("p2"));
+
+        assert_eq!(manager.active_profile_id.as_deref(), Some("p2"));
+        assert_eq!(manager.last_used_profiles, vec!["p2", "p1"]);
+        assert!(manager.get_profile("p2").unwrap().last_connected_at.is_some());
+        assert!(!manager.record_connection("missing"));
+    }
+
+    #[test]
+    fn test_preview_merge() {
+        let manager = create_test_manager();
+        let mut renamed = manager.get_profile("p1").unwrap().clone();
+        renamed.tags.push("rack-1".to_string());
+        renamed.updated_at += chrono::Duration::seconds(10);
+        let same = manager.get_profile("p2").unwrap().clone();
+        let by_name = create_test_profile("other-id", "gateway c", &["imported"]);
+        let new_profile = create_test_profile("p9", "New Device", &[]);
+
+        let entries = manager.preview_merge(&[renamed, same, by_name, new_profile]);
+        let statuses: Vec<MergeStatus> = entries.iter().map(|e| e.status).collect();
+
+        assert_eq!(
+            statuses,
+            vec![MergeStatus::Updated, MergeStatus::Unchanged, MergeStatus::Conflict, MergeStatus::Added]
+        );
+        assert_eq!(entries[2].existing_id.as_deref(), Some("p3"));
+        assert_eq!(entries[2].matched_by, Some(MergeMatch::Name));
+        assert_eq!(entries[3].existing_id, None);
+    }
+
+    #[test]
+    fn test_preview_merge_older_incoming_is_conflict() {
+        let manager = create_test_manager();
+        let mut stale = manager.get_profile("p1").unwrap().clone();
+        stale.name = "Old Gateway".to_string();
+        stale.updated_at -= chrono::Duration::seconds(10);
+
+        let entries = manager.preview_merge(&[stale]);
+
+        assert_eq!(entries[0].status, MergeStatus::Conflict);
+        assert_eq!(entries[0].matched_by, Some(MergeMatch::Id));
+    }
+
+    #[test]
+    fn test_apply_merge_requires_resolutions() {
+        let mut manager = create_test_manager();
+        let incoming = vec![create_test_profile("x1", "Sensor B", &["changed"])];
+
+        assert!(manager.apply_merge(incoming, &HashMap::new()).is_err());
+        assert_eq!(manager.profiles.len(), 3);
+    }
+
+    #[test]
+    fn test_apply_merge_strategies() {
+        let mut manager = create_test_manager();
+        manager.move_profile_to_group("p2", Some("g1"), None).unwrap();
+        manager.record_connection("p2");
+        manager.get_profile_mut("p2").unwrap().secret_refs =
+            vec![SecretRef { kind: SecretKind::LoginPassword, secret_id: "s1".to_string() }];
+        let incoming = vec![
+            create_test_profile("x1", "Gateway A", &["theirs"]),
+            create_test_profile("x2", "Sensor B", &["theirs"]),
+            create_test_profile("x3", "Gateway C", &["theirs"]),
+            create_test_profile("x4", "New Device", &[]),
+        ];
+        let resolutions = HashMap::from([
+            ("x1".to_string(), ConflictStrategy::KeepMine),
+            ("x2".to_string(), ConflictStrategy::TakeTheirs),
+            ("x3".to_string(), ConflictStrategy::KeepBoth),
+        ]);
+
+        let summary = manager.apply_merge(incoming, &resolutions).unwrap();
+
+        assert_eq!(
+            summary,
+            MergeSummary { added: 1, updated: 1, unchanged: 0, kept_mine: 1, kept_both: 1 }
+        );
+        assert_eq!(manager.get_profile("p1").unwrap().tags, vec!["lab", "gateway"]);
+        // 上書きしても既存の ID とグループ所属は保たれる
+        let updated = manager.get_profile("p2").unwrap();
+        assert_eq!(updated.tags, vec!["theirs"]);
+        assert_eq!(manager.groups["g1"].profile_ids, vec!["p2"]);
+        // この端末の秘密情報の参照と接続日時も保たれる
+        assert_eq!(updated.secret_refs[0].secret_id, "s1");
+        assert!(updated.last_connected_at.is_some());
+        assert!(manager.profiles.iter().any(|p| p.name == "Gateway C (imported)"));
+        assert!(manager.get_profile("x4").is_some());
+        assert_eq!(manager.profiles.len(), 5);
+
+        // 同じファイルを再度取り込んでも重複しない
+        let again = vec![manager.get_profile("x4").unwrap().clone()];
+        let summary = manager.apply_merge(again, &HashMap::new()).unwrap();
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(manager.profiles.len(), 5);
+    }
+
+    #[test]
+    fn test_find_same_profile() {
+        let manager = create_test_manager();
+
+        // 取り込み直したセッションは ID が異なっても同じプロファイルとみなす
+        let reimported = create_test_profile("new-id", "gateway a", &[]);
+        assert_eq!(manager.find_same_profile(&reimported).unwrap().id, "p1");
+
+        let mut moved = reimported.clone();
+        moved.tcp_config.as_mut().unwrap().port += 1;
+        assert!(manager.find_same_profile(&moved).is_none());
+    }
 }
\ No newline at end of file
@@ -1,5 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeBounds;
+
+use super::HighlightSpan;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TerminalMessage {
@@ -8,12 +12,15 @@ pub struct TerminalMessage {
     pub direction: MessageDirection,
     pub content: String,
     pub encoding: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<HighlightSpan>, // 受信時に強調表示ルールと照合した結果
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum MessageDirection {
     Sent,
     Received,
+    System, // トリガーの発火など、アプリが記録する注記
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,6 +34,27 @@ pub struct TerminalConfig {
     pub theme: TerminalTheme,
     pub max_history_size: usize,
     pub auto_scroll: bool,
+    #[serde(default)]
+    pub framing: Framing,
+}
+
+// 受信データを表示用のメッセージに区切る方法
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum Framing {
+    #[default]
+    Raw, // 受信したまとまりごと
+    Line, // 改行ごと
+    IdleGap { idle_ms: u64 }, // 一定時間受信がなければ区切る
+}
+
+// プロファイルごとに上書きする端末設定。None の項目は全体の設定を使う
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct TerminalOverrides {
+    pub encoding: Option<String>,
+    pub line_ending: Option<LineEnding>,
+    pub echo_input: Option<bool>,
+    pub show_timestamp: Option<bool>,
+    pub framing: Option<Framing>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -59,6 +87,7 @@ impl Default for TerminalConfig {
             theme: TerminalTheme::default(),
             max_history_size: 1000,
             auto_scroll: true,
+            framing: Framing::default(),
         }
     }
 }
@@ -85,6 +114,7 @@ impl TerminalMessage {
             direction: MessageDirection::Sent,
             content,
             encoding,
+            highlights: Vec::new(),
         }
     }
 
@@ -95,12 +125,46 @@ impl TerminalMessage {
             direction: MessageDirection::Received,
             content,
             encoding,
+            highlights: Vec::new(),
+        }
+    }
+
+    pub fn new_system(content: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            direction: MessageDirection::System,
+            content,
+            encoding: "UTF-8".to_string(),
+            highlights: Vec::new(),
         }
     }
 }
 
+impl TerminalConfig {
+    // 全体の設定にプロファイルの上書きを適用した設定
+    pub fn with_overrides(&self, overrides: &TerminalOverrides) -> TerminalConfig {
+        let mut config = self.clone();
+        if let Some(encoding) = &overrides.encoding {
+            config.encoding = encoding.clone();
+        }
+        if let Some(line_ending) = &overrides.line_ending {
+            config.line_ending = line_ending.clone();
+        }
+        if let Some(echo_input) = overrides.echo_input {
+            config.echo_input = echo_input;
+        }
+        if let Some(show_timestamp) = overrides.show_timestamp {
+            config.show_timestamp = show_timestamp;
+        }
+        if let Some(framing) = &overrides.framing {
+            config.framing = framing.clone();
+        }
+        config
+    }
+}
+
 impl LineEnding {
-    #[allow(dead_code)]
     pub fn to_bytes(&self) -> &'static [u8] {
         match self {
             LineEnding::Cr => b"\r",
@@ -109,7 +173,6 @@ impl LineEnding {
         }
     }
 
-    #[allow(dead_code)]
     pub fn to_string(&self) -> &'static str {
         match self {
             LineEnding::Cr => "\r",
@@ -119,57 +182,218 @@ impl LineEnding {
     }
 }
 
+// バックエンドで保持する送受信メッセージ
+#[derive(Debug, Clone)]
+pub struct MessageStore {
+    messages: VecDeque<TerminalMessage>,
+    max_size: usize,
+    // メッセージIDから追加順の通し番号を引く。位置は先頭のメッセージの通し番号との差で求める
+    sequences: HashMap<String, u64>,
+    front_sequence: u64,
+}
+
+impl Default for MessageStore {
+    fn default() -> Self {
+        Self::new(TerminalConfig::default().max_history_size)
+    }
+}
+
+impl MessageStore {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            max_size,
+            sequences: HashMap::new(),
+            front_sequence: 0,
+        }
+    }
+
+    pub fn push(&mut self, message: TerminalMessage) {
+        let sequence = self.front_sequence + self.messages.len() as u64;
+        self.sequences.insert(message.id.clone(), sequence);
+        self.messages.push_back(message);
+        self.enforce_max_size();
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.enforce_max_size();
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.sequences.clear();
+        self.front_sequence = 0;
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &TerminalMessage> {
+        self.messages.iter()
+    }
+
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> impl DoubleEndedIterator<Item = &TerminalMessage> {
+        self.messages.range(range)
+    }
+
+    // メッセージIDからストア内の位置を取得する
+    pub fn position(&self, message_id: &str) -> Option<usize> {
+        self.sequences
+            .get(message_id)
+            .map(|sequence| (sequence - self.front_sequence) as usize)
+    }
+
+    // 最大履歴サイズを超えた場合は古いものを削除
+    fn enforce_max_size(&mut self) {
+        while self.messages.len() > self.max_size {
+            if let Some(removed) = self.messages.pop_front() {
+                // 同じIDで追加し直したメッセージの番号は残す
+                if self.sequences.get(&removed.id) == Some(&self.front_sequence) {
+                    self.sequences.remove(&removed.id);
+                }
+                self.front_sequence += 1;
+            }
+        }
+    }
+}
+
+// 検索結果の最大件数
+const MAX_SEARCH_RESULTS: usize = 50;
+// 検索順位で使用頻度・最終使用日時をどの程度重視するか
+const FRECENCY_WEIGHT: f64 = 3.0;
+
+// コマンド履歴の1項目。profile_id が None のものは保存済みプロファイルを使わない接続での履歴
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub profile_id: Option<String>,
+    pub last_used_at: DateTime<Utc>,
+    pub use_count: u32,
+}
+
 // コマンド履歴管理
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommandHistory {
-    pub commands: Vec<String>,
-    pub max_size: usize,
+    #[serde(default)]
+    pub entries: Vec<HistoryEntry>, // 最後に使用した順（古いものが先頭）
+    pub max_size: usize, // プロファイルごとの上限
     pub current_index: Option<usize>,
+    // 以前の形式（コマンド文字列のみ）。読み込み後に migrate_legacy で entries へ移す
+    #[serde(default, skip_serializing)]
+    commands: Vec<String>,
 }
 
 impl Default for CommandHistory {
     fn default() -> Self {
         Self {
-            commands: Vec::new(),
-            max_size: 100,
+            entries: Vec::new(),
+            max_size: TerminalConfig::default().max_history_size,
             current_index: None,
+            commands: Vec::new(),
         }
     }
 }
 
 impl CommandHistory {
-    pub fn add_command(&mut self, command: String) {
-        if !command.trim().is_empty() {
-            // 同じコマンドが最後にある場合は追加しない
-            if self.commands.last() != Some(&command) {
-                self.commands.push(command);
-                
-                // 最大サイズを超えた場合は古いものを削除
-                if self.commands.len() > self.max_size {
-                    self.commands.remove(0);
-                }
+    pub fn add_command(&mut self, command: String, profile_id: Option<&str>) {
+        self.add_command_at(command, profile_id, Utc::now());
+    }
+
+    fn add_command_at(&mut self, command: String, profile_id: Option<&str>, now: DateTime<Utc>) {
+        self.current_index = None;
+        if command.trim().is_empty() {
+            return;
+        }
+
+        // 同じプロファイルで同じコマンドを使った場合は回数を増やして末尾へ移動する
+        let existing = self
+            .entries
+            .iter()
+            .position(|entry| entry.command == command && entry.profile_id.as_deref() == profile_id);
+        let entry = match existing {
+            Some(index) => {
+                let mut entry = self.entries.remove(index);
+                entry.use_count += 1;
+                entry.last_used_at = now;
+                entry
             }
+            None => HistoryEntry {
+                command,
+                profile_id: profile_id.map(str::to_string),
+                last_used_at: now,
+                use_count: 1,
+            },
+        };
+        self.entries.push(entry);
+        self.enforce_max_size();
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.enforce_max_size();
+    }
+
+    // 以前の形式で保存された履歴を取り込む
+    pub fn migrate_legacy(&mut self) {
+        let now = Utc::now();
+        for command in std::mem::take(&mut self.commands) {
+            self.add_command_at(command, None, now);
         }
+    }
+
+    // 条件に一致する履歴を削除し、削除した件数を返す
+    pub fn remove_matching(&mut self, predicate: impl Fn(&str) -> bool) -> usize {
+        let original_len = self.entries.len();
+        self.entries.retain(|entry| !predicate(&entry.command));
         self.current_index = None;
+        original_len - self.entries.len()
+    }
+
+    // プロファイルの履歴（新しい順）。None の場合は全プロファイルをまとめた履歴を返す
+    pub fn entries_for(&self, profile_id: Option<&str>) -> Vec<HistoryEntry> {
+        let mut entries: Vec<HistoryEntry> = match profile_id {
+            Some(profile_id) => self
+                .entries
+                .iter()
+                .filter(|entry| entry.profile_id.as_deref() == Some(profile_id))
+                .cloned()
+                .collect(),
+            None => {
+                let mut merged: Vec<HistoryEntry> = Vec::new();
+                for entry in &self.entries {
+                    match merged.iter_mut().find(|m| m.command == entry.command) {
+                        Some(m) => {
+                            m.use_count += entry.use_count;
+                            m.last_used_at = m.last_used_at.max(entry.last_used_at);
+                        }
+                        None => merged.push(HistoryEntry {
+                            profile_id: None,
+                            ..entry.clone()
+                        }),
+                    }
+                }
+                merged
+            }
+        };
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_used_at));
+        entries
     }
 
     #[allow(dead_code)]
     pub fn get_previous(&mut self) -> Option<&String> {
-        if self.commands.is_empty() {
+        if self.entries.is_empty() {
             return None;
         }
 
         match self.current_index {
             None => {
-                self.current_index = Some(self.commands.len() - 1);
-                self.commands.last()
+                self.current_index = Some(self.entries.len() - 1);
+                self.entries.last().map(|entry| &entry.command)
             }
             Some(index) => {
                 if index > 0 {
                     self.current_index = Some(index - 1);
-                    self.commands.get(index - 1)
+                    self.entries.get(index - 1).map(|entry| &entry.command)
                 } else {
-                    self.commands.get(index)
+                    self.entries.get(index).map(|entry| &entry.command)
                 }
             }
         }
@@ -180,9 +404,9 @@ impl CommandHistory {
         match self.current_index {
             None => None,
             Some(index) => {
-                if index < self.commands.len() - 1 {
+                if index < self.entries.len() - 1 {
                     self.current_index = Some(index + 1);
-                    self.commands.get(index + 1)
+                    self.entries.get(index + 1).map(|entry| &entry.command)
                 } else {
                     self.current_index = None;
                     None
@@ -191,10 +415,272 @@ impl CommandHistory {
         }
     }
 
-    pub fn search(&self, query: &str) -> Vec<&String> {
-        self.commands
-            .iter()
-            .filter(|cmd| cmd.to_lowercase().contains(&query.to_lowercase()))
-            .collect()
+    // あいまい検索。一致の度合いと使用頻度・最終使用日時を合わせて順位付けする
+    pub fn search(&self, query: &str, profile_id: Option<&str>) -> Vec<HistoryEntry> {
+        self.search_at(query, profile_id, Utc::now())
+    }
+
+    fn search_at(&self, query: &str, profile_id: Option<&str>, now: DateTime<Utc>) -> Vec<HistoryEntry> {
+        let mut scored: Vec<(f64, HistoryEntry)> = self
+            .entries_for(profile_id)
+            .into_iter()
+            .filter_map(|entry| {
+                let match_score = fuzzy_score(query, &entry.command)?;
+                let rank = match_score as f64 + FRECENCY_WEIGHT * frecency(&entry, now).ln_1p();
+                Some((rank, entry))
+            })
+            .collect();
+
+        // 同じ順位の場合は新しいものを先にする（entries_for の順序を保つ）
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(MAX_SEARCH_RESULTS);
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    // プロファイルごとに新しいものから max_size 件を残す
+    fn enforce_max_size(&mut self) {
+        let mut counts: HashMap<Option<String>, usize> = HashMap::new();
+        let mut keep = vec![false; self.entries.len()];
+        for (index, entry) in self.entries.iter().enumerate().rev() {
+            let count = counts.entry(entry.profile_id.clone()).or_insert(0);
+            *count += 1;
+            keep[index] = *count <= self.max_size;
+        }
+
+        let mut keep = keep.into_iter();
+        self.entries.retain(|_| keep.next().unwrap_or(false));
+    }
+}
+
+// クエリの文字が順番どおりに含まれていれば一致とみなす。
+// 連続した一致・単語の先頭での一致・前方一致を高く評価し、間が空くほど低くする
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous: Option<usize> = None;
+    for query_char in &query {
+        let index = start + candidate[start..].iter().position(|c| c == query_char)?;
+        score += 1;
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        match previous {
+            Some(previous) if index == previous + 1 => score += 5,
+            Some(previous) => score -= (index - previous - 1).min(5) as i64,
+            None => score -= index.min(5) as i64,
+        }
+        previous = Some(index);
+        start = index + 1;
+    }
+    if candidate.starts_with(&query) {
+        score += 10;
+    }
+    Some(score)
+}
+
+// 使用回数を最終使用日時からの経過時間で重み付けした値
+fn frecency(entry: &HistoryEntry, now: DateTime<Utc>) -> f64 {
+    let age = now - entry.last_used_at;
+    let weight = if age < chrono::Duration::hours(1) {
+        4.0
+    } else if age < chrono::Duration::days(1) {
+        2.0
+    } else if age < chrono::Duration::weeks(1) {
+        1.0
+    } else {
+        0.5
+    };
+    entry.use_count as f64 * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_message(content: &str) -> TerminalMessage {
+        TerminalMessage::new_received(content.to_string(), "UTF-8".to_string())
+    }
+
+    #[test]
+    fn test_message_store_push() {
+        let mut store = MessageStore::new(10);
+        store.push(create_test_message("first"));
+        store.push(create_test_message("second"));
+
+        assert_eq!(store.iter().count(), 2);
+        let contents: Vec<&str> = store.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_message_store_enforces_max_size() {
+        let mut store = MessageStore::new(2);
+        store.push(create_test_message("a"));
+        store.push(create_test_message("b"));
+        store.push(create_test_message("c"));
+
+        let contents: Vec<&str> = store.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_message_store_set_max_size_truncates() {
+        let mut store = MessageStore::new(5);
+        for content in ["a", "b", "c", "d"] {
+            store.push(create_test_message(content));
+        }
+
+        store.set_max_size(1);
+
+        assert_eq!(store.iter().count(), 1);
+        assert_eq!(store.iter().next().unwrap().content, "d");
+    }
+
+    #[test]
+    fn test_message_store_position_and_range() {
+        let mut store = MessageStore::new(10);
+        let messages: Vec<TerminalMessage> = ["a", "b", "c"].iter().map(|c| create_test_message(c)).collect();
+        for message in &messages {
+            store.push(message.clone());
+        }
+
+        assert_eq!(store.position(&messages[1].id), Some(1));
+        assert_eq!(store.position("missing"), None);
+
+        let after: Vec<&str> = store.range(2..).map(|m| m.content.as_str()).collect();
+        assert_eq!(after, vec!["c"]);
+
+        // 古いメッセージが削除されると位置も詰まる
+        store.set_max_size(2);
+        assert_eq!(store.position(&messages[0].id), None);
+        assert_eq!(store.position(&messages[1].id), Some(0));
+        store.push(create_test_message("d"));
+        store.clear();
+        store.push(messages[2].clone());
+        assert_eq!(store.position(&messages[2].id), Some(0));
+    }
+
+    #[test]
+    fn test_message_store_clear() {
+        let mut store = MessageStore::default();
+        store.push(create_test_message("a"));
+        store.clear();
+
+        assert_eq!(store.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_terminal_config_with_overrides() {
+        let global = TerminalConfig::default();
+        let overrides = TerminalOverrides {
+            encoding: Some("Shift_JIS".to_string()),
+            line_ending: Some(LineEnding::Cr),
+            framing: Some(Framing::IdleGap { idle_ms: 50 }),
+            ..TerminalOverrides::default()
+        };
+
+        let effective = global.with_overrides(&overrides);
+
+        assert_eq!(effective.encoding, "Shift_JIS");
+        assert_eq!(effective.line_ending, LineEnding::Cr);
+        assert_eq!(effective.framing, Framing::IdleGap { idle_ms: 50 });
+        // 上書きしていない項目は全体の設定のまま
+        assert_eq!(effective.echo_input, global.echo_input);
+        assert_eq!(effective.font_size, global.font_size);
+        assert_eq!(global.with_overrides(&TerminalOverrides::default()).encoding, "UTF-8");
+    }
+
+    #[test]
+    fn test_command_history_counts_uses_per_profile() {
+        let mut history = CommandHistory::default();
+        history.add_command("status".to_string(), Some("p1"));
+        history.add_command("reboot".to_string(), Some("p1"));
+        history.add_command("status".to_string(), Some("p1"));
+        history.add_command("status".to_string(), Some("p2"));
+        history.add_command("  ".to_string(), Some("p1"));
+
+        let p1 = history.entries_for(Some("p1"));
+        let commands: Vec<(&str, u32)> = p1.iter().map(|e| (e.command.as_str(), e.use_count)).collect();
+        assert_eq!(commands, vec![("status", 2), ("reboot", 1)]);
+
+        // 全体の履歴ではプロファイルをまたいで回数を合計する
+        let global = history.entries_for(None);
+        assert_eq!(global.len(), 2);
+        assert_eq!(global.iter().find(|e| e.command == "status").unwrap().use_count, 3);
+    }
+
+    #[test]
+    fn test_command_history_max_size_per_profile() {
+        let mut history = CommandHistory::default();
+        history.set_max_size(2);
+        for command in ["a", "b", "c"] {
+            history.add_command(command.to_string(), Some("p1"));
+        }
+        history.add_command("x".to_string(), None);
+
+        let p1: Vec<String> = history.entries_for(Some("p1")).into_iter().map(|e| e.command).collect();
+        assert_eq!(p1, vec!["c", "b"]);
+        assert_eq!(history.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_command_history_fuzzy_search_ranking() {
+        let now = Utc::now();
+        let mut history = CommandHistory::default();
+        let old = now - chrono::Duration::weeks(2);
+        history.add_command_at("show interfaces".to_string(), None, old);
+        history.add_command_at("show ip route".to_string(), None, old);
+        for _ in 0..5 {
+            history.add_command_at("show ip route".to_string(), None, now);
+        }
+        history.add_command_at("ping 10.0.0.1".to_string(), None, now);
+
+        let results: Vec<String> = history
+            .search_at("shiprt", None, now)
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(results, vec!["show ip route"]);
+
+        let results: Vec<String> = history.search_at("sh", None, now).into_iter().map(|e| e.command).collect();
+        assert_eq!(results, vec!["show ip route", "show interfaces"]);
+
+        assert_eq!(history.search_at("", None, now).len(), 3);
+    }
+
+    #[test]
+    fn test_fuzzy_score_prefers_contiguous_matches() {
+        assert!(fuzzy_score("ver", "version") > fuzzy_score("ver", "view error"));
+        assert_eq!(fuzzy_score("xyz", "version"), None);
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn test_command_history_migrates_legacy_format() {
+        let mut history: CommandHistory =
+            serde_json::from_str(r#"{"commands": ["ls", "pwd", "ls"], "max_size": 100, "current_index": null}"#).unwrap();
+
+        history.migrate_legacy();
+
+        let commands: Vec<(String, u32)> = history.entries_for(None).into_iter().map(|e| (e.command, e.use_count)).collect();
+        assert_eq!(commands.len(), 2);
+        assert!(commands.contains(&("ls".to_string(), 2)));
+        assert!(!serde_json::to_string(&history).unwrap().contains("\"commands\""));
+    }
+
+    #[test]
+    fn test_command_history_remove_matching() {
+        let mut history = CommandHistory::default();
+        history.add_command("login admin".to_string(), None);
+        history.add_command("password=secret".to_string(), None);
+
+        assert_eq!(history.remove_matching(|command| command.contains("password")), 1);
+        assert_eq!(history.entries.len(), 1);
     }
 }
\ No newline at end of file
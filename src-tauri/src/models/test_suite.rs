@@ -0,0 +1,177 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// expect の timeout_ms を省略した場合の待ち時間
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+// 量産検査などで繰り返し実行するコマンド/応答の確認手順。YAML（JSON も可）で記述する
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TestSuite {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "default_timeout_ms")]
+    pub default_timeout_ms: u64,
+    pub steps: Vec<TestStep>,
+}
+
+// 手書きしやすいよう type は小文字で書く（例: `- type: expect`）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TestStep {
+    // ${name} はそれまでにキャプチャした値に置き換える
+    Send {
+        #[serde(default)]
+        name: Option<String>,
+        text: String,
+        #[serde(default = "default_true")]
+        append_line_ending: bool,
+    },
+    // 名前付きグループ (?P<name>...) に一致した値をキャプチャする
+    Expect {
+        #[serde(default)]
+        name: Option<String>,
+        pattern: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    // キャプチャした値を数値として範囲チェックする
+    Assert {
+        #[serde(default)]
+        name: Option<String>,
+        value: String,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    Wait {
+        #[serde(default)]
+        name: Option<String>,
+        ms: u64,
+    },
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl TestSuite {
+    // YAML は JSON の上位互換なので、どちらの形式でも読み込める
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let suite: TestSuite = serde_yaml::from_str(source).map_err(|e| format!("Invalid test suite: {}", e))?;
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Test suite name is required".to_string());
+        }
+        if self.steps.is_empty() {
+            return Err("Test suite has no steps".to_string());
+        }
+
+        // assert が参照する値は、それより前の expect でキャプチャされている必要がある
+        let mut captured: HashSet<String> = HashSet::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            match step {
+                TestStep::Expect { pattern, .. } => {
+                    let regex = Regex::new(pattern).map_err(|e| format!("Step {}: invalid pattern: {}", index + 1, e))?;
+                    captured.extend(regex.capture_names().flatten().map(str::to_string));
+                }
+                TestStep::Assert { value, min, max, .. } => {
+                    if min.is_none() && max.is_none() {
+                        return Err(format!("Step {}: min or max is required", index + 1));
+                    }
+                    if !captured.contains(value) {
+                        return Err(format!("Step {}: '{}' is not captured by a previous step", index + 1, value));
+                    }
+                }
+                TestStep::Send { .. } | TestStep::Wait { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TestStep {
+    // レポートに表示する名前。省略した場合は内容から作る
+    pub fn display_name(&self) -> String {
+        match self {
+            TestStep::Send { name: Some(name), .. }
+            | TestStep::Expect { name: Some(name), .. }
+            | TestStep::Assert { name: Some(name), .. }
+            | TestStep::Wait { name: Some(name), .. } => name.clone(),
+            TestStep::Send { text, .. } => format!("send {}", text),
+            TestStep::Expect { pattern, .. } => format!("expect /{}/", pattern),
+            TestStep::Assert { value, min, max, .. } => match (min, max) {
+                (Some(min), Some(max)) => format!("{} in {}..{}", value, min, max),
+                (Some(min), None) => format!("{} >= {}", value, min),
+                (None, Some(max)) => format!("{} <= {}", value, max),
+                (None, None) => value.clone(),
+            },
+            TestStep::Wait { ms, .. } => format!("wait {} ms", ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUITE: &str = r#"
+name: board check
+steps:
+  - type: send
+    text: VER?
+  - type: expect
+    pattern: 'FW (?P<version>\d+\.\d+)'
+    timeout_ms: 1000
+  - type: send
+    text: VOLT?
+  - type: expect
+    pattern: 'V=(?P<voltage>[0-9.]+)'
+  - type: assert
+    name: supply voltage
+    value: voltage
+    min: 3.2
+    max: 3.4
+"#;
+
+    #[test]
+    fn test_parse_yaml_suite() {
+        let suite = TestSuite::parse(SUITE).unwrap();
+        assert_eq!(suite.name, "board check");
+        assert_eq!(suite.default_timeout_ms, DEFAULT_TIMEOUT_MS);
+        assert_eq!(suite.steps.len(), 5);
+        assert_eq!(
+            suite.steps[0],
+            TestStep::Send { name: None, text: "VER?".to_string(), append_line_ending: true }
+        );
+        assert_eq!(suite.steps[1].display_name(), r"expect /FW (?P<version>\d+\.\d+)/");
+        assert_eq!(suite.steps[4].display_name(), "supply voltage");
+    }
+
+    #[test]
+    fn test_parse_json_suite() {
+        let json = r#"{"name":"ping","steps":[{"type":"send","text":"ping"},{"type":"expect","pattern":"pong"}]}"#;
+        let suite = TestSuite::parse(json).unwrap();
+        assert_eq!(suite.steps.len(), 2);
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_capture() {
+        let source = SUITE.replace("value: voltage", "value: current");
+        let error = TestSuite::parse(&source).unwrap_err();
+        assert!(error.contains("'current' is not captured"), "{}", error);
+
+        let source = SUITE.replace("'V=(?P<voltage>[0-9.]+)'", "'V=('");
+        assert!(TestSuite::parse(&source).is_err());
+    }
+}
\ No newline at end of file
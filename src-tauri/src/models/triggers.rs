@@ -0,0 +1,83 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::LineEnding;
+
+// 受信データが正規表現に一致したときに自動で送信する応答
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TriggerRule {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,
+    pub response: String,
+    #[serde(default)]
+    pub line_ending: Option<LineEnding>, // 指定した場合は応答の末尾に付加する
+    #[serde(default)]
+    pub delay_ms: u64, // 一致してから送信するまでの待ち時間
+    #[serde(default)]
+    pub mode: TriggerMode,
+    #[serde(default)]
+    pub cooldown_ms: u64, // 発火後、この時間内の一致は無視する
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum TriggerMode {
+    #[default]
+    OneShot, // 接続ごとに1回だけ
+    Repeat,
+}
+
+impl TriggerRule {
+    // 送信するバイト列
+    pub fn response_bytes(&self) -> Vec<u8> {
+        let mut data = self.response.as_bytes().to_vec();
+        if let Some(line_ending) = &self.line_ending {
+            data.extend_from_slice(line_ending.to_bytes());
+        }
+        data
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Trigger name is required".to_string());
+        }
+        let regex = Regex::new(&self.pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+        // 空文字列に一致するパターンは受信のたびに発火してしまう
+        if regex.is_match("") {
+            return Err("Pattern must not match empty text".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_rule(pattern: &str) -> TriggerRule {
+        TriggerRule {
+            id: "t1".to_string(),
+            name: "login".to_string(),
+            pattern: pattern.to_string(),
+            response: "root".to_string(),
+            line_ending: Some(LineEnding::Cr),
+            delay_ms: 0,
+            mode: TriggerMode::OneShot,
+            cooldown_ms: 0,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_response_bytes_appends_line_ending() {
+        assert_eq!(create_rule("login:").response_bytes(), b"root\r".to_vec());
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        assert!(create_rule("login:").validate().is_ok());
+        assert!(create_rule("(").validate().is_err());
+        assert!(create_rule(".*").validate().is_err());
+    }
+}
\ No newline at end of file
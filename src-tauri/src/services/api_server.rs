@@ -0,0 +1,482 @@
+use crate::models::TerminalMessage;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+// 1行が長すぎるリクエストは受け付けない
+const MAX_REQUEST_LEN: usize = 1024 * 1024;
+
+// クライアントへ書き出す前に溜めておく行数。読み取りが遅いクライアントの通知はこれを超えると破棄する
+const OUTGOING_CAPACITY: usize = 256;
+
+// JSON-RPC 2.0 のエラー
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn parse_error(message: impl ToString) -> Self {
+        Self { code: -32700, message: message.to_string() }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self { code: -32601, message: format!("Method not found: {}", method) }
+    }
+
+    pub fn invalid_params(message: impl ToString) -> Self {
+        Self { code: -32602, message: message.to_string() }
+    }
+
+    // コマンドの実行に失敗した（ApiResponse::error に相当）
+    pub fn failed(message: impl ToString) -> Self {
+        Self { code: -32000, message: message.to_string() }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self { code: -32001, message: "Not authenticated".to_string() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Option<Value>, // 省略した場合は通知として扱い、応答を返さない
+}
+
+// API のメソッドを実行する。Tauri に依存しないよう、実装はコマンド層が提供する
+#[async_trait]
+pub trait ApiHandler: Send + Sync {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError>;
+    // "message" 通知として配信する受信メッセージ
+    async fn subscribe(&self) -> broadcast::Receiver<TerminalMessage>;
+}
+
+// 改行区切りの JSON-RPC 2.0 を 127.0.0.1 で受け付ける。
+// クライアントは最初に authenticate でトークンを送る必要がある
+pub struct ApiServer {
+    address: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    token: watch::Sender<Arc<String>>,
+    listener: JoinHandle<()>,
+}
+
+impl ApiServer {
+    // port に 0 を指定すると空いているポートを使う
+    pub async fn start(port: u16, token: String, handler: Arc<dyn ApiHandler>) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let address = listener.local_addr()?;
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let (token, token_rx) = watch::channel(Arc::new(token));
+
+        let listener = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        debug!("API client connected: {}", peer);
+                        tokio::spawn(handle_client(stream, token_rx.clone(), handler.clone(), shutdown_rx.clone()));
+                    }
+                    Err(e) => warn!("Failed to accept API client: {}", e),
+                }
+            }
+        });
+
+        info!("Local API server listening on {}", address);
+        Ok(Self {
+            address,
+            shutdown,
+            token,
+            listener,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // 待ち受けを続けたままトークンを差し替える。古いトークンで認証したクライアントは切断する
+    pub fn set_token(&self, token: String) {
+        self.token.send_replace(Arc::new(token));
+    }
+
+    // 待ち受けを止め、接続中のクライアントも切断する
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.listener.abort();
+        let _ = self.shutdown.send(true);
+        info!("Local API server on {} stopped", self.address);
+    }
+}
+
+// クライアントごとの状態
+struct ClientSession {
+    token: watch::Receiver<Arc<String>>,
+    handler: Arc<dyn ApiHandler>,
+    authenticated: bool,
+    subscription: Option<JoinHandle<()>>,
+    outgoing: mpsc::Sender<String>,
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    token: watch::Receiver<Arc<String>>,
+    handler: Arc<dyn ApiHandler>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // 接続した時点のトークンを基準に、以降の差し替えを検出する
+    let mut token_changes = token.clone();
+    token_changes.borrow_and_update();
+
+    let (reader, mut writer) = stream.into_split();
+    let (outgoing, mut outgoing_rx) = mpsc::channel::<String>(OUTGOING_CAPACITY);
+
+    // 応答と購読の通知を同じ接続へ順に書き出す
+    let writer_task = tokio::spawn(async move {
+        while let Some(mut line) = outgoing_rx.recv().await {
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = ClientSession {
+        token,
+        handler,
+        authenticated: false,
+        subscription: None,
+        outgoing,
+    };
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let mut limited = (&mut reader).take(MAX_REQUEST_LEN as u64 + 1);
+        let read = tokio::select! {
+            _ = shutdown.changed() => break,
+            _ = token_changes.changed() => break,
+            read = limited.read_line(&mut line) => read,
+        };
+        match read {
+            Ok(0) | Err(_) => break,
+            Ok(_) if line.len() > MAX_REQUEST_LEN => {
+                warn!("API request exceeded {} bytes, closing connection", MAX_REQUEST_LEN);
+                break;
+            }
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<RpcRequest>(&line) {
+            Ok(request) => {
+                let result = session.dispatch(&request.method, request.params).await;
+                request.id.map(|id| rpc_response(id, result))
+            }
+            Err(e) => Some(rpc_response(Value::Null, Err(RpcError::parse_error(e)))),
+        };
+        if let Some(response) = response {
+            if session.outgoing.send(response.to_string()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    if let Some(subscription) = session.subscription.take() {
+        subscription.abort();
+    }
+    drop(session);
+    let _ = writer_task.await;
+    debug!("API client disconnected");
+}
+
+impl ClientSession {
+    async fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        if method == "authenticate" {
+            let token = params
+                .get("token")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::invalid_params("token is required"))?;
+            if !constant_time_eq(token.as_bytes(), self.token.borrow().as_bytes()) {
+                warn!("API client sent an invalid token");
+                return Err(RpcError::failed("Invalid token"));
+            }
+            self.authenticated = true;
+            return Ok(Value::Bool(true));
+        }
+        if !self.authenticated {
+            return Err(RpcError::unauthorized());
+        }
+
+        match method {
+            "subscribe" => {
+                if self.subscription.as_ref().map(|task| !task.is_finished()).unwrap_or(false) {
+                    return Ok(Value::Bool(false));
+                }
+                let received = self.handler.subscribe().await;
+                self.subscription = Some(tokio::spawn(forward_messages(received, self.outgoing.clone())));
+                Ok(Value::Bool(true))
+            }
+            "unsubscribe" => match self.subscription.take() {
+                Some(task) => {
+                    task.abort();
+                    Ok(Value::Bool(true))
+                }
+                None => Ok(Value::Bool(false)),
+            },
+            _ => self.handler.call(method, params).await,
+        }
+    }
+}
+
+// 受信メッセージを "message" 通知として送る。取りこぼした場合は "lagged" で件数を知らせる。
+// クライアントの読み取りが追いつかない間は通知を破棄し、書き出せるようになってから件数を知らせる
+async fn forward_messages(mut received: broadcast::Receiver<TerminalMessage>, outgoing: mpsc::Sender<String>) {
+    let mut skipped: u64 = 0;
+    loop {
+        let message = match received.recv().await {
+            Ok(message) => Some(message),
+            Err(RecvError::Lagged(count)) => {
+                skipped += count;
+                None
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if skipped > 0 {
+            let lagged = rpc_notification("lagged", json!({ "skipped": skipped }));
+            match outgoing.try_send(lagged.to_string()) {
+                Ok(()) => skipped = 0,
+                Err(TrySendError::Full(_)) => {
+                    skipped += u64::from(message.is_some());
+                    continue;
+                }
+                Err(TrySendError::Closed(_)) => return,
+            }
+        }
+        if let Some(message) = message {
+            match outgoing.try_send(rpc_notification("message", json!(message)).to_string()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => skipped += 1,
+                Err(TrySendError::Closed(_)) => return,
+            }
+        }
+    }
+}
+
+fn rpc_response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn rpc_notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// トークンの照合時間から一致した長さを推測されないようにする
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::tcp::OwnedReadHalf;
+    use tokio::time::{timeout, Duration};
+
+    struct EchoHandler {
+        received_tx: broadcast::Sender<TerminalMessage>,
+    }
+
+    #[async_trait]
+    impl ApiHandler for EchoHandler {
+        async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+            match method {
+                "send" => {
+                    let message = params["message"].as_str().ok_or_else(|| RpcError::invalid_params("message is required"))?;
+                    let _ = self
+                        .received_tx
+                        .send(TerminalMessage::new_received(message.to_string(), "UTF-8".to_string()));
+                    Ok(json!("Message sent"))
+                }
+                _ => Err(RpcError::method_not_found(method)),
+            }
+        }
+
+        async fn subscribe(&self) -> broadcast::Receiver<TerminalMessage> {
+            self.received_tx.subscribe()
+        }
+    }
+
+    async fn start_server() -> ApiServer {
+        let handler = Arc::new(EchoHandler {
+            received_tx: broadcast::channel(16).0,
+        });
+        ApiServer::start(0, "secret-token".to_string(), handler).await.unwrap()
+    }
+
+    async fn request(
+        writer: &mut tokio::net::tcp::OwnedWriteHalf,
+        lines: &mut tokio::io::Lines<BufReader<OwnedReadHalf>>,
+        body: Value,
+    ) -> Value {
+        writer.write_all(format!("{}\n", body).as_bytes()).await.unwrap();
+        read_line(lines).await
+    }
+
+    async fn read_line(lines: &mut tokio::io::Lines<BufReader<OwnedReadHalf>>) -> Value {
+        let line = timeout(Duration::from_secs(2), lines.next_line())
+            .await
+            .expect("timed out waiting for the server")
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requires_authentication() {
+        let server = start_server().await;
+        let stream = TcpStream::connect(server.address()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let response = request(&mut writer, &mut lines, json!({"jsonrpc": "2.0", "id": 1, "method": "status"})).await;
+        assert_eq!(response["error"]["code"], -32001);
+
+        let response = request(
+            &mut writer,
+            &mut lines,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "authenticate", "params": {"token": "wrong"}}),
+        )
+        .await;
+        assert_eq!(response["error"]["message"], "Invalid token");
+
+        let response = request(&mut writer, &mut lines, json!({"id": 3, "method": "authenticate", "params": {"token": "secret-token"}})).await;
+        assert_eq!(response["result"], true);
+
+        let response = request(&mut writer, &mut lines, json!({"id": 4, "method": "status"})).await;
+        assert_eq!(response["error"]["code"], -32601);
+
+        let response = request(&mut writer, &mut lines, json!("not a request")).await;
+        assert_eq!(response["error"]["code"], -32700);
+        server.stop();
+    }
+
+    #[tokio::test]
+    async fn test_subscription_streams_messages() {
+        let server = start_server().await;
+        let stream = TcpStream::connect(server.address()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        request(&mut writer, &mut lines, json!({"id": 1, "method": "authenticate", "params": {"token": "secret-token"}})).await;
+        let response = request(&mut writer, &mut lines, json!({"id": 2, "method": "subscribe"})).await;
+        assert_eq!(response["result"], true);
+
+        let response = request(&mut writer, &mut lines, json!({"id": 3, "method": "send", "params": {"message": "OK"}})).await;
+        let notification = if response.get("id").is_some() {
+            read_line(&mut lines).await
+        } else {
+            // 通知が応答より先に届くこともある
+            let notification = response;
+            assert_eq!(read_line(&mut lines).await["id"], 3);
+            notification
+        };
+        assert_eq!(notification["method"], "message");
+        assert_eq!(notification["params"]["content"], "OK");
+        assert_eq!(notification["params"]["direction"], "Received");
+
+        // 停止するとクライアントも切断される
+        server.stop();
+        let closed = timeout(Duration::from_secs(2), lines.next_line()).await.unwrap().unwrap();
+        assert_eq!(closed, None);
+    }
+
+    #[tokio::test]
+    async fn test_set_token_disconnects_clients_using_the_old_token() {
+        let server = start_server().await;
+        let stream = TcpStream::connect(server.address()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let response = request(&mut writer, &mut lines, json!({"id": 1, "method": "authenticate", "params": {"token": "secret-token"}})).await;
+        assert_eq!(response["result"], true);
+
+        server.set_token("new-token".to_string());
+        let closed = timeout(Duration::from_secs(2), lines.next_line()).await.unwrap().unwrap();
+        assert_eq!(closed, None);
+
+        // 同じアドレスで待ち受けを続け、新しいトークンだけを受け付ける
+        let stream = TcpStream::connect(server.address()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let response = request(&mut writer, &mut lines, json!({"id": 1, "method": "authenticate", "params": {"token": "secret-token"}})).await;
+        assert_eq!(response["error"]["message"], "Invalid token");
+        let response = request(&mut writer, &mut lines, json!({"id": 2, "method": "authenticate", "params": {"token": "new-token"}})).await;
+        assert_eq!(response["result"], true);
+        server.stop();
+    }
+
+    async fn next_notification(outgoing_rx: &mut mpsc::Receiver<String>) -> Value {
+        serde_json::from_str(&outgoing_rx.recv().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_gets_lagged_notification() {
+        let (received_tx, received) = broadcast::channel(16);
+        let (outgoing, mut outgoing_rx) = mpsc::channel(2);
+        tokio::spawn(forward_messages(received, outgoing));
+
+        // 書き出し待ちが上限に達した後の通知は破棄する
+        for content in ["a", "b", "c"] {
+            received_tx
+                .send(TerminalMessage::new_received(content.to_string(), "UTF-8".to_string()))
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(next_notification(&mut outgoing_rx).await["params"]["content"], "a");
+        assert_eq!(next_notification(&mut outgoing_rx).await["params"]["content"], "b");
+
+        // 空きができたら破棄した件数を知らせてから再開する
+        received_tx
+            .send(TerminalMessage::new_received("d".to_string(), "UTF-8".to_string()))
+            .unwrap();
+        let lagged = timeout(Duration::from_secs(5), next_notification(&mut outgoing_rx)).await.unwrap();
+        assert_eq!(lagged["method"], "lagged");
+        assert_eq!(lagged["params"]["skipped"], 1);
+        assert_eq!(next_notification(&mut outgoing_rx).await["params"]["content"], "d");
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokex"));
+        assert!(!constant_time_eq(b"token", b"tok"));
+    }
+}
\ No newline at end of file
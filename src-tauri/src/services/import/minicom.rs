@@ -0,0 +1,132 @@
+use super::{data_bits_from_number, parity_from_str, stop_bits_from_str, ImportReport, ImportSource, ImportedProfile, UnmappedField};
+use crate::models::{ConnectionConfig, FlowControl, SerialConfig};
+
+// minicom の設定ファイル (minirc.*) を読み込む
+pub fn parse(file_name: &str, content: &str) -> ImportReport {
+    let mut report = ImportReport {
+        source: ImportSource::Minicom,
+        profiles: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    let entries: Vec<(&str, &str)> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("pu "))
+        .map(|rest| {
+            let rest = rest.trim_start();
+            match rest.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, value.trim()),
+                None => (rest, ""),
+            }
+        })
+        .collect();
+    if entries.is_empty() {
+        return report;
+    }
+
+    let name = file_name
+        .strip_prefix("minirc.")
+        .filter(|name| !name.is_empty())
+        .unwrap_or(file_name)
+        .to_string();
+    let mut unmapped = Vec::new();
+    let mut serial_config = SerialConfig::default();
+    let mut port = None;
+    let mut rtscts = false;
+    let mut xonxoff = false;
+
+    for (key, value) in entries {
+        match key {
+            "port" => port = Some(value.to_string()),
+            "baudrate" => match value.parse() {
+                Ok(baud_rate) => serial_config.baud_rate = baud_rate,
+                Err(_) => unmapped.push(UnmappedField::new(key, value, "Invalid baud rate")),
+            },
+            "bits" => match value.parse().ok().and_then(data_bits_from_number) {
+                Some(data_bits) => serial_config.data_bits = data_bits,
+                None => unmapped.push(UnmappedField::new(key, value, "Unsupported data bits")),
+            },
+            "parity" => match parity_from_str(value) {
+                Some(parity) => serial_config.parity = parity,
+                None => unmapped.push(UnmappedField::new(key, value, "Unknown parity")),
+            },
+            "stopbits" => match stop_bits_from_str(value) {
+                Some(stop_bits) => serial_config.stop_bits = stop_bits,
+                None => unmapped.push(UnmappedField::new(key, value, "Unsupported stop bits")),
+            },
+            "rtscts" => rtscts = value.eq_ignore_ascii_case("yes"),
+            "xonxoff" => xonxoff = value.eq_ignore_ascii_case("yes"),
+            _ => unmapped.push(UnmappedField::new(key, value, "No matching setting")),
+        }
+    }
+
+    serial_config.flow_control = match (rtscts, xonxoff) {
+        (true, _) => FlowControl::Hardware,
+        (false, true) => FlowControl::Software,
+        (false, false) => FlowControl::None,
+    };
+    if rtscts && xonxoff {
+        unmapped.push(UnmappedField::new(
+            "xonxoff",
+            "Yes",
+            "Only one flow control can be used; hardware flow control was kept",
+        ));
+    }
+
+    match port {
+        Some(port) if !port.is_empty() => {
+            serial_config.port = port;
+            report.profiles.push(ImportedProfile {
+                profile: ConnectionConfig::new_serial(name.clone(), serial_config),
+                source_name: name,
+                unmapped_fields: unmapped,
+            });
+        }
+        _ => report.skipped.push(format!("{}: no serial port configured", name)),
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DataBits, Parity, StopBits};
+
+    #[test]
+    fn test_parse_minirc() {
+        let content = "\
+# Machine-generated file - use setup menu in minicom to change parameters.
+pu port             /dev/ttyUSB0
+pu baudrate         57600
+pu bits             7
+pu parity           E
+pu stopbits         2
+pu rtscts           No
+pu xonxoff          Yes
+pu histlines        5000
+";
+        let report = parse("minirc.board", content);
+
+        assert_eq!(report.profiles.len(), 1);
+        let imported = &report.profiles[0];
+        assert_eq!(imported.profile.name, "board");
+        let serial = imported.profile.serial_config.as_ref().unwrap();
+        assert_eq!(serial.port, "/dev/ttyUSB0");
+        assert_eq!(serial.baud_rate, 57600);
+        assert_eq!(serial.data_bits, DataBits::Seven);
+        assert_eq!(serial.parity, Parity::Even);
+        assert_eq!(serial.stop_bits, StopBits::Two);
+        assert_eq!(serial.flow_control, FlowControl::Software);
+
+        assert_eq!(imported.unmapped_fields.len(), 1);
+        assert_eq!(imported.unmapped_fields[0].field, "histlines");
+    }
+
+    #[test]
+    fn test_parse_without_port_is_skipped() {
+        let report = parse("minirc.dfl", "pu baudrate 9600\n");
+
+        assert!(report.profiles.is_empty());
+        assert_eq!(report.skipped, vec!["dfl: no serial port configured".to_string()]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,189 @@
+// 他のターミナルソフトの接続設定を ConnectionConfig に変換する
+pub mod minicom;
+pub mod putty;
+pub mod teraterm;
+
+use crate::models::{ConnectionConfig, DataBits, Parity, StopBits};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ImportSource {
+    Putty,
+    TeraTerm,
+    Minicom,
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Could not detect the file format")]
+    UnknownFormat,
+
+    #[error("No sessions found in the file")]
+    NoSessions,
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// 変換できなかった設定項目
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UnmappedField {
+    pub field: String,
+    pub value: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportedProfile {
+    pub profile: ConnectionConfig,
+    pub source_name: String, // 元のソフトでのセッション名
+    pub unmapped_fields: Vec<UnmappedField>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportReport {
+    pub source: ImportSource,
+    pub profiles: Vec<ImportedProfile>,
+    pub skipped: Vec<String>, // 取り込めなかったセッションとその理由
+}
+
+impl UnmappedField {
+    pub fn new(field: impl Into<String>, value: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            value: value.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+pub fn import_file(path: &Path, source: Option<ImportSource>) -> Result<ImportReport, ImportError> {
+    let content = decode_text(&std::fs::read(path)?);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let source = match source {
+        Some(source) => source,
+        None => detect_source(&file_name, &content).ok_or(ImportError::UnknownFormat)?,
+    };
+    import_content(source, &file_name, &content)
+}
+
+pub fn import_content(source: ImportSource, file_name: &str, content: &str) -> Result<ImportReport, ImportError> {
+    let report = match source {
+        ImportSource::Putty => putty::parse(content),
+        ImportSource::TeraTerm => teraterm::parse(file_name, content),
+        ImportSource::Minicom => minicom::parse(file_name, content),
+    };
+
+    if report.profiles.is_empty() && report.skipped.is_empty() {
+        return Err(ImportError::NoSessions);
+    }
+    Ok(report)
+}
+
+// ファイル名と内容から形式を推定する
+pub fn detect_source(file_name: &str, content: &str) -> Option<ImportSource> {
+    let lower_name = file_name.to_lowercase();
+    if lower_name.ends_with(".reg") || content.contains("\\SimonTatham\\PuTTY\\Sessions\\") {
+        Some(ImportSource::Putty)
+    } else if lower_name.starts_with("minirc") || content.lines().any(|line| line.trim_start().starts_with("pu ")) {
+        Some(ImportSource::Minicom)
+    } else if lower_name.ends_with(".ini") || lower_name.ends_with(".ttl") || content.contains("[Tera Term]") {
+        Some(ImportSource::TeraTerm)
+    } else {
+        None
+    }
+}
+
+// regedit が出力する UTF-16 のファイルにも対応する
+pub fn decode_text(bytes: &[u8]) -> String {
+    let decode_utf16 = |bytes: &[u8], little_endian: bool| -> String {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                if little_endian {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, true),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, false),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+pub(crate) fn data_bits_from_number(bits: u32) -> Option<DataBits> {
+    match bits {
+        5 => Some(DataBits::Five),
+        6 => Some(DataBits::Six),
+        7 => Some(DataBits::Seven),
+        8 => Some(DataBits::Eight),
+        _ => None,
+    }
+}
+
+pub(crate) fn stop_bits_from_str(value: &str) -> Option<StopBits> {
+    match value.trim() {
+        "1" => Some(StopBits::One),
+        "1.5" => Some(StopBits::OnePointFive),
+        "2" => Some(StopBits::Two),
+        _ => None,
+    }
+}
+
+pub(crate) fn parity_from_str(value: &str) -> Option<Parity> {
+    match value.trim().to_lowercase().as_str() {
+        "n" | "none" => Some(Parity::None),
+        "o" | "odd" => Some(Parity::Odd),
+        "e" | "even" => Some(Parity::Even),
+        "m" | "mark" => Some(Parity::Mark),
+        "s" | "space" => Some(Parity::Space),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_source() {
+        assert_eq!(detect_source("sessions.reg", ""), Some(ImportSource::Putty));
+        assert_eq!(detect_source("minirc.dfl", ""), Some(ImportSource::Minicom));
+        assert_eq!(detect_source("TERATERM.INI", ""), Some(ImportSource::TeraTerm));
+        assert_eq!(detect_source("login.ttl", ""), Some(ImportSource::TeraTerm));
+        assert_eq!(detect_source("config", "pu port /dev/ttyS0"), Some(ImportSource::Minicom));
+        assert_eq!(detect_source("notes.txt", "hello"), None);
+    }
+
+    #[test]
+    fn test_decode_text_utf16() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "PuTTY".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(decode_text(&bytes), "PuTTY");
+        assert_eq!(decode_text(&[0xEF, 0xBB, 0xBF, b'a']), "a");
+        assert_eq!(decode_text(b"plain"), "plain");
+    }
+
+    #[test]
+    fn test_import_content_without_sessions() {
+        assert!(matches!(
+            import_content(ImportSource::Minicom, "minirc.dfl", "# empty"),
+            Err(ImportError::NoSessions)
+        ));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,313 @@
+use super::{data_bits_from_number, ImportReport, ImportSource, ImportedProfile, UnmappedField};
+use crate::models::{ConnectionConfig, FlowControl, Parity, SerialConfig, StopBits, TcpConfig};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const SESSIONS_KEY: &str = "\\Software\\SimonTatham\\PuTTY\\Sessions\\";
+
+// 変換先がないが、利用者に知らせるべき設定項目
+const NOTABLE_FIELDS: &[(&str, &str)] = &[
+    ("UserName", "Login user names are not stored in profiles"),
+    ("ProxyHost", "Proxy connections are not supported"),
+    ("PublicKeyFile", "SSH keys are not supported"),
+    ("RemoteCommand", "Remote commands are not supported"),
+    ("TerminalType", "Terminal type negotiation is not supported"),
+    ("LogFileName", "Use the session log settings instead"),
+];
+
+// PuTTY のレジストリエクスポート (.reg) を読み込む
+pub fn parse(content: &str) -> ImportReport {
+    let mut report = ImportReport {
+        source: ImportSource::Putty,
+        profiles: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for (name, values) in parse_sessions(content) {
+        if name == "Default Settings" {
+            continue;
+        }
+        match convert_session(&name, &values) {
+            Ok(imported) => report.profiles.push(imported),
+            Err(reason) => report.skipped.push(format!("{}: {}", name, reason)),
+        }
+    }
+    report
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum RegValue {
+    String(String),
+    Dword(u32),
+}
+
+impl RegValue {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            RegValue::String(value) => Some(value),
+            RegValue::Dword(_) => None,
+        }
+    }
+
+    fn as_u32(&self) -> Option<u32> {
+        match self {
+            RegValue::Dword(value) => Some(*value),
+            RegValue::String(value) => value.parse().ok(),
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            RegValue::String(value) => value.clone(),
+            RegValue::Dword(value) => value.to_string(),
+        }
+    }
+}
+
+// セッション名とその値の一覧（ファイル内の順序を保つ）
+fn parse_sessions(content: &str) -> Vec<(String, HashMap<String, RegValue>)> {
+    let mut sessions: Vec<(String, HashMap<String, RegValue>)> = Vec::new();
+    let mut in_session = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            let key = &line[1..line.len() - 1];
+            in_session = match key.find(SESSIONS_KEY) {
+                Some(index) => {
+                    let encoded = &key[index + SESSIONS_KEY.len()..];
+                    sessions.push((percent_decode(encoded), HashMap::new()));
+                    true
+                }
+                None => false,
+            };
+            continue;
+        }
+
+        if !in_session {
+            continue;
+        }
+        if let Some((name, value)) = parse_value_line(line) {
+            if let Some((_, values)) = sessions.last_mut() {
+                values.insert(name, value);
+            }
+        }
+    }
+    sessions
+}
+
+// "Name"="value" または "Name"=dword:0000000a
+fn parse_value_line(line: &str) -> Option<(String, RegValue)> {
+    let rest = line.strip_prefix('"')?;
+    let (name, value) = rest.split_once("\"=")?;
+
+    let value = if let Some(hex) = value.strip_prefix("dword:") {
+        RegValue::Dword(u32::from_str_radix(hex.trim(), 16).ok()?)
+    } else {
+        let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+        RegValue::String(inner.replace("\\\\", "\\").replace("\\\"", "\""))
+    };
+    Some((name.to_string(), value))
+}
+
+// PuTTY はセッション名の空白などを %XX で保存する
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn convert_session(name: &str, values: &HashMap<String, RegValue>) -> Result<ImportedProfile, String> {
+    let mut unmapped = Vec::new();
+    let get_str = |key: &str| values.get(key).and_then(RegValue::as_str).unwrap_or("");
+    let get_u32 = |key: &str| values.get(key).and_then(RegValue::as_u32);
+
+    let protocol = get_str("Protocol");
+    let profile = match protocol {
+        "serial" => {
+            let port = get_str("SerialLine");
+            if port.is_empty() {
+                return Err("serial session without a serial line".to_string());
+            }
+
+            let mut serial_config = SerialConfig {
+                port: port.to_string(),
+                ..SerialConfig::default()
+            };
+            if let Some(speed) = get_u32("SerialSpeed") {
+                serial_config.baud_rate = speed;
+            }
+            if let Some(bits) = get_u32("SerialDataBits") {
+                match data_bits_from_number(bits) {
+                    Some(data_bits) => serial_config.data_bits = data_bits,
+                    None => unmapped.push(UnmappedField::new("SerialDataBits", bits.to_string(), "Unsupported data bits")),
+                }
+            }
+            if let Some(half_bits) = get_u32("SerialStopHalfbits") {
+                match half_bits {
+                    2 => serial_config.stop_bits = StopBits::One,
+                    3 => serial_config.stop_bits = StopBits::OnePointFive,
+                    4 => serial_config.stop_bits = StopBits::Two,
+                    other => unmapped.push(UnmappedField::new("SerialStopHalfbits", other.to_string(), "Unsupported stop bits")),
+                }
+            }
+            if let Some(parity) = get_u32("SerialParity") {
+                match parity {
+                    0 => serial_config.parity = Parity::None,
+                    1 => serial_config.parity = Parity::Odd,
+                    2 => serial_config.parity = Parity::Even,
+                    3 => serial_config.parity = Parity::Mark,
+                    4 => serial_config.parity = Parity::Space,
+                    other => unmapped.push(UnmappedField::new("SerialParity", other.to_string(), "Unknown parity")),
+                }
+            }
+            if let Some(flow) = get_u32("SerialFlowControl") {
+                match flow {
+                    0 => serial_config.flow_control = FlowControl::None,
+                    1 => serial_config.flow_control = FlowControl::Software,
+                    2 => serial_config.flow_control = FlowControl::Hardware,
+                    other => unmapped.push(UnmappedField::new(
+                        "SerialFlowControl",
+                        other.to_string(),
+                        "DSR/DTR flow control is not supported",
+                    )),
+                }
+            }
+
+            ConnectionConfig::new_serial(name.to_string(), serial_config)
+        }
+        "raw" | "telnet" | "rlogin" => {
+            let host = get_str("HostName");
+            if host.is_empty() {
+                return Err("network session without a host name".to_string());
+            }
+            if protocol != "raw" {
+                unmapped.push(UnmappedField::new(
+                    "Protocol",
+                    protocol,
+                    "Imported as a raw TCP connection; protocol negotiation is not supported",
+                ));
+            }
+
+            let tcp_config = TcpConfig {
+                host: host.to_string(),
+                port: get_u32("PortNumber")
+                    .and_then(|port| u16::try_from(port).ok())
+                    .unwrap_or(23),
+                timeout: Duration::from_secs(5),
+                keep_alive: get_u32("TCPKeepalives").map(|value| value != 0).unwrap_or(false),
+            };
+            ConnectionConfig::new_tcp(name.to_string(), tcp_config)
+        }
+        "" => return Err("session has no protocol".to_string()),
+        other => return Err(format!("protocol '{}' is not supported", other)),
+    };
+
+    for (field, reason) in NOTABLE_FIELDS {
+        if let Some(value) = values.get(*field) {
+            let text = value.display();
+            if !text.is_empty() {
+                unmapped.push(UnmappedField::new(*field, text, *reason));
+            }
+        }
+    }
+
+    Ok(ImportedProfile {
+        profile,
+        source_name: name.to_string(),
+        unmapped_fields: unmapped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ConnectionType, DataBits};
+
+    const SAMPLE: &str = r#"Windows Registry Editor Version 5.00
+
+[HKEY_CURRENT_USER\Software\SimonTatham\PuTTY\Sessions]
+
+[HKEY_CURRENT_USER\Software\SimonTatham\PuTTY\Sessions\Default%20Settings]
+"Protocol"="ssh"
+
+[HKEY_CURRENT_USER\Software\SimonTatham\PuTTY\Sessions\Lab%20Console]
+"Protocol"="serial"
+"SerialLine"="COM3"
+"SerialSpeed"=dword:0001c200
+"SerialDataBits"=dword:00000007
+"SerialStopHalfbits"=dword:00000004
+"SerialParity"=dword:00000002
+"SerialFlowControl"=dword:00000003
+"UserName"=""
+
+[HKEY_CURRENT_USER\Software\SimonTatham\PuTTY\Sessions\Router]
+"Protocol"="telnet"
+"HostName"="192.168.1.1"
+"PortNumber"=dword:00000017
+"UserName"="admin"
+
+[HKEY_CURRENT_USER\Software\SimonTatham\PuTTY\Sessions\Server]
+"Protocol"="ssh"
+"HostName"="example.com"
+"#;
+
+    #[test]
+    fn test_parse_serial_session() {
+        let report = parse(SAMPLE);
+        let console = &report.profiles[0];
+
+        assert_eq!(console.source_name, "Lab Console");
+        assert_eq!(console.profile.connection_type, ConnectionType::Serial);
+        let serial = console.profile.serial_config.as_ref().unwrap();
+        assert_eq!(serial.port, "COM3");
+        assert_eq!(serial.baud_rate, 115200);
+        assert_eq!(serial.data_bits, DataBits::Seven);
+        assert_eq!(serial.stop_bits, StopBits::Two);
+        assert_eq!(serial.parity, Parity::Even);
+        assert_eq!(console.unmapped_fields.len(), 1);
+        assert_eq!(console.unmapped_fields[0].field, "SerialFlowControl");
+    }
+
+    #[test]
+    fn test_parse_network_session() {
+        let report = parse(SAMPLE);
+        let router = &report.profiles[1];
+
+        assert_eq!(router.profile.name, "Router");
+        let tcp = router.profile.tcp_config.as_ref().unwrap();
+        assert_eq!(tcp.host, "192.168.1.1");
+        assert_eq!(tcp.port, 23);
+
+        let fields: Vec<&str> = router.unmapped_fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, vec!["Protocol", "UserName"]);
+    }
+
+    #[test]
+    fn test_unsupported_protocol_is_skipped() {
+        let report = parse(SAMPLE);
+
+        assert_eq!(report.profiles.len(), 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].starts_with("Server:"));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("My%20Device%2FA"), "My Device/A");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,324 @@
+use super::{data_bits_from_number, parity_from_str, stop_bits_from_str, ImportReport, ImportSource, ImportedProfile, UnmappedField};
+use crate::models::{ConnectionConfig, FlowControl, SerialConfig, TcpConfig};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const DEFAULT_TELNET_PORT: u16 = 23;
+
+// Tera Term の設定ファイル (TERATERM.INI) またはマクロ (.ttl) の connect 行を読み込む
+pub fn parse(file_name: &str, content: &str) -> ImportReport {
+    let mut report = ImportReport {
+        source: ImportSource::TeraTerm,
+        profiles: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    if file_name.to_lowercase().ends_with(".ttl") {
+        parse_macro(content, &mut report);
+    } else {
+        parse_ini(file_name, content, &mut report);
+    }
+    report
+}
+
+fn parse_ini(file_name: &str, content: &str, report: &mut ImportReport) {
+    let sections = parse_sections(content);
+    let Some(settings) = sections.get("tera term") else {
+        return;
+    };
+    let get = |key: &str| settings.get(&key.to_lowercase()).map(String::as_str);
+    let base_name = file_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file_name);
+
+    // シリアル設定
+    if let Some(com_port) = get("ComPort").filter(|port| !port.is_empty()) {
+        let mut unmapped = Vec::new();
+        let mut serial_config = SerialConfig {
+            port: format!("COM{}", com_port),
+            ..SerialConfig::default()
+        };
+        if let Some(baud_rate) = get("BaudRate").and_then(|value| value.parse().ok()) {
+            serial_config.baud_rate = baud_rate;
+        }
+        if let Some(value) = get("DataBit") {
+            match value.parse().ok().and_then(data_bits_from_number) {
+                Some(data_bits) => serial_config.data_bits = data_bits,
+                None => unmapped.push(UnmappedField::new("DataBit", value, "Unsupported data bits")),
+            }
+        }
+        if let Some(value) = get("Parity") {
+            match parity_from_str(value) {
+                Some(parity) => serial_config.parity = parity,
+                None => unmapped.push(UnmappedField::new("Parity", value, "Unknown parity")),
+            }
+        }
+        if let Some(value) = get("StopBit") {
+            match stop_bits_from_str(value) {
+                Some(stop_bits) => serial_config.stop_bits = stop_bits,
+                None => unmapped.push(UnmappedField::new("StopBit", value, "Unsupported stop bits")),
+            }
+        }
+        if let Some(value) = get("FlowCtrl") {
+            match value.to_lowercase().as_str() {
+                "none" => serial_config.flow_control = FlowControl::None,
+                "x" | "xon" => serial_config.flow_control = FlowControl::Software,
+                "hard" | "rts" => serial_config.flow_control = FlowControl::Hardware,
+                _ => unmapped.push(UnmappedField::new("FlowCtrl", value, "Unsupported flow control")),
+            }
+        }
+        if let Some(delay) = get("DelayPerChar").filter(|value| *value != "0") {
+            unmapped.push(UnmappedField::new("DelayPerChar", delay, "Per-character transmit delay is not supported"));
+        }
+
+        let name = format!("{} (COM{})", base_name, com_port);
+        report.profiles.push(ImportedProfile {
+            profile: ConnectionConfig::new_serial(name.clone(), serial_config),
+            source_name: name,
+            unmapped_fields: unmapped,
+        });
+    }
+
+    // [Hosts] に登録された接続先
+    let default_port = get("TCPPort")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_TELNET_PORT);
+    let telnet = get("Telnet").map(|value| value.eq_ignore_ascii_case("on")).unwrap_or(false);
+    if let Some(hosts) = sections.get("hosts") {
+        let mut entries: Vec<(&String, &String)> = hosts.iter().collect();
+        entries.sort_by_key(|(key, _)| host_index(key));
+
+        for (_, entry) in entries {
+            match parse_connect_target(entry, default_port, telnet) {
+                Ok(imported) => report.profiles.push(imported),
+                Err(reason) => report.skipped.push(format!("{}: {}", entry, reason)),
+            }
+        }
+    }
+}
+
+// Host1, Host2 ... の番号順に並べる
+fn host_index(key: &str) -> u32 {
+    key.trim_start_matches(|c: char| c.is_alphabetic()).parse().unwrap_or(u32::MAX)
+}
+
+fn parse_macro(content: &str, report: &mut ImportReport) {
+    for line in content.lines() {
+        let line = line.trim();
+        let Some(rest) = line.strip_prefix("connect") else {
+            continue;
+        };
+        let Some(argument) = extract_quoted(rest) else {
+            continue;
+        };
+
+        match parse_connect_target(argument, DEFAULT_TELNET_PORT, true) {
+            Ok(imported) => report.profiles.push(imported),
+            Err(reason) => report.skipped.push(format!("{}: {}", argument, reason)),
+        }
+    }
+}
+
+fn extract_quoted(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let inner = &text[1..];
+    inner.find(quote).map(|end| &inner[..end])
+}
+
+// 「host[:port] /オプション」または「/C=3 /BAUD=115200」の形式を解析する
+fn parse_connect_target(target: &str, default_port: u16, default_telnet: bool) -> Result<ImportedProfile, String> {
+    let mut unmapped = Vec::new();
+    let mut host: Option<String> = None;
+    let mut options: HashMap<String, String> = HashMap::new();
+
+    for token in target.split_whitespace() {
+        if let Some(option) = token.strip_prefix('/') {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            options.insert(key.to_lowercase(), value.to_string());
+        } else if host.is_none() {
+            host = Some(token.to_string());
+        }
+    }
+
+    if options.contains_key("ssh") || options.contains_key("ssh1") || options.contains_key("ssh2") {
+        return Err("SSH connections are not supported".to_string());
+    }
+
+    for (key, reason) in [
+        ("user", "Login user names are not stored in profiles"),
+        ("passwd", "Store passwords in the credential vault instead"),
+        ("auth", "SSH authentication is not supported"),
+        ("f", "Referenced setting files are not imported"),
+    ] {
+        if let Some(value) = options.get(key) {
+            let shown = if key == "passwd" { "********" } else { value.as_str() };
+            unmapped.push(UnmappedField::new(format!("/{}", key), shown, reason));
+        }
+    }
+
+    // シリアル接続
+    if let Some(com_port) = options.get("c") {
+        let mut serial_config = SerialConfig {
+            port: format!("COM{}", com_port),
+            ..SerialConfig::default()
+        };
+        if let Some(baud_rate) = options
+            .get("baud")
+            .or_else(|| options.get("speed"))
+            .and_then(|value| value.parse().ok())
+        {
+            serial_config.baud_rate = baud_rate;
+        }
+
+        let name = format!("COM{}", com_port);
+        return Ok(ImportedProfile {
+            profile: ConnectionConfig::new_serial(name.clone(), serial_config),
+            source_name: name,
+            unmapped_fields: unmapped,
+        });
+    }
+
+    let host = host.ok_or_else(|| "no host or COM port specified".to_string())?;
+    let (host_name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') => (
+            name.to_string(),
+            port.parse().map_err(|_| format!("invalid port '{}'", port))?,
+        ),
+        _ => (host.clone(), default_port),
+    };
+    let port = options
+        .get("p")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(port);
+
+    let telnet = match options.get("t").map(String::as_str) {
+        Some("0") => false,
+        Some(_) => true,
+        None => default_telnet && !options.contains_key("nossh") || options.contains_key("telnet"),
+    };
+    if telnet {
+        unmapped.push(UnmappedField::new(
+            "Telnet",
+            "on",
+            "Imported as a raw TCP connection; telnet negotiation is not supported",
+        ));
+    }
+
+    let tcp_config = TcpConfig {
+        host: host_name.clone(),
+        port,
+        timeout: Duration::from_secs(5),
+        keep_alive: true,
+    };
+    Ok(ImportedProfile {
+        profile: ConnectionConfig::new_tcp(host.clone(), tcp_config),
+        source_name: host,
+        unmapped_fields: unmapped,
+    })
+}
+
+// セクション名とキーは小文字にそろえる
+fn parse_sections(content: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_lowercase();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+            continue;
+        }
+        if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ConnectionType, DataBits, Parity, StopBits};
+
+    const SAMPLE_INI: &str = "\
+[Tera Term]
+; serial settings
+ComPort=4
+BaudRate=38400
+DataBit=7
+Parity=even
+StopBit=2
+FlowCtrl=hard
+DelayPerChar=5
+TCPPort=2323
+Telnet=off
+
+[Hosts]
+Host2=device.local:8000
+Host1=192.168.0.10
+";
+
+    #[test]
+    fn test_parse_ini_serial() {
+        let report = parse("TERATERM.INI", SAMPLE_INI);
+        let serial_profile = &report.profiles[0];
+
+        assert_eq!(serial_profile.profile.name, "TERATERM (COM4)");
+        let serial = serial_profile.profile.serial_config.as_ref().unwrap();
+        assert_eq!(serial.port, "COM4");
+        assert_eq!(serial.baud_rate, 38400);
+        assert_eq!(serial.data_bits, DataBits::Seven);
+        assert_eq!(serial.parity, Parity::Even);
+        assert_eq!(serial.stop_bits, StopBits::Two);
+        assert_eq!(serial.flow_control, FlowControl::Hardware);
+        assert_eq!(serial_profile.unmapped_fields[0].field, "DelayPerChar");
+    }
+
+    #[test]
+    fn test_parse_ini_hosts() {
+        let report = parse("TERATERM.INI", SAMPLE_INI);
+
+        assert_eq!(report.profiles.len(), 3);
+        let first = report.profiles[1].profile.tcp_config.as_ref().unwrap();
+        assert_eq!(first.host, "192.168.0.10");
+        assert_eq!(first.port, 2323);
+        assert!(report.profiles[1].unmapped_fields.is_empty());
+
+        let second = report.profiles[2].profile.tcp_config.as_ref().unwrap();
+        assert_eq!(second.host, "device.local");
+        assert_eq!(second.port, 8000);
+    }
+
+    #[test]
+    fn test_parse_macro_connect_lines() {
+        let content = "\
+; login macro
+connect '/C=3 /BAUD=115200'
+connect '10.0.0.5:2000 /nossh /T=0 /user=admin /passwd=secret'
+connect 'server /ssh /2'
+wait 'login:'
+";
+        let report = parse("login.ttl", content);
+
+        assert_eq!(report.profiles.len(), 2);
+        assert_eq!(report.profiles[0].profile.connection_type, ConnectionType::Serial);
+        assert_eq!(report.profiles[0].profile.serial_config.as_ref().unwrap().baud_rate, 115200);
+
+        let tcp_profile = &report.profiles[1];
+        let tcp = tcp_profile.profile.tcp_config.as_ref().unwrap();
+        assert_eq!((tcp.host.as_str(), tcp.port), ("10.0.0.5", 2000));
+        let fields: Vec<&str> = tcp_profile.unmapped_fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, vec!["/user", "/passwd"]);
+        // パスワードはレポートにも残さない
+        assert_eq!(tcp_profile.unmapped_fields[1].value, "********");
+
+        assert_eq!(report.skipped.len(), 1);
+    }
+}
\ No newline at end of file
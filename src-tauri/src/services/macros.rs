@@ -0,0 +1,396 @@
+use crate::communication::ConnectionManager;
+use crate::models::{parse_hex, ControlLine, LineEnding, Macro, MacroStep, MessageDirection, StepTarget, TerminalMessage};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+// WaitFor の照合対象として保持する受信データの上限
+const RECEIVE_BUFFER_LIMIT: usize = 64 * 1024;
+
+// Goto による無限ループを防ぐための実行ステップ数の上限
+const MAX_EXECUTED_STEPS: usize = 10_000;
+
+// マクロ・スクリプト・定期送信の送信先（テストではモックに差し替える）
+#[async_trait]
+pub trait MacroTarget: Send {
+    async fn send(&mut self, data: &[u8], content: String, encoding: &str) -> Result<(), String>;
+    async fn set_control_line(&mut self, line: ControlLine, level: bool) -> Result<(), String>;
+}
+
+#[async_trait]
+impl MacroTarget for Arc<Mutex<ConnectionManager>> {
+    async fn send(&mut self, data: &[u8], content: String, encoding: &str) -> Result<(), String> {
+        self.lock()
+            .await
+            .send_data(data, content, encoding)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn set_control_line(&mut self, line: ControlLine, level: bool) -> Result<(), String> {
+        self.lock()
+            .await
+            .set_control_line(line, level)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+// WaitFor・スクリプトの expect で照合する受信データ
+#[derive(Debug, Default)]
+pub struct ReceiveBuffer {
+    data: String,
+}
+
+impl ReceiveBuffer {
+    // 受信メッセージのみを追加し、上限を超えた分は古い方から捨てる
+    pub fn push(&mut self, message: &TerminalMessage) {
+        if message.direction != MessageDirection::Received {
+            return;
+        }
+        self.data.push_str(&message.content);
+        if self.data.len() > RECEIVE_BUFFER_LIMIT {
+            let mut cut = self.data.len() - RECEIVE_BUFFER_LIMIT;
+            while !self.data.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.data.drain(..cut);
+        }
+    }
+
+    // 一致した位置までを消費し、キャプチャ（0 番目は一致全体）を返す
+    pub fn take_match(&mut self, regex: &Regex) -> Option<Vec<String>> {
+        let captures = regex.captures(&self.data)?;
+        let end = captures.get(0)?.end();
+        let groups = captures
+            .iter()
+            .map(|group| group.map(|m| m.as_str().to_string()).unwrap_or_default())
+            .collect();
+        self.data.drain(..end);
+        Some(groups)
+    }
+}
+
+// 実行時に接続先から決まる値
+#[derive(Debug, Clone)]
+pub struct MacroContext {
+    pub line_ending: LineEnding,
+    pub secrets: HashMap<String, String>, // secret_id -> 値
+}
+
+// 進捗イベント。step は 0 始まり
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum MacroEvent {
+    StepStarted { step: usize },
+    StepCompleted { step: usize },
+    PatternMatched { step: usize, text: String },
+    PatternTimedOut { step: usize },
+    Finished { outcome: MacroOutcome },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "status")]
+pub enum MacroOutcome {
+    Completed,
+    Stopped { step: usize },
+    Failed { step: Option<usize>, reason: String },
+    Aborted,
+}
+
+// マクロを最後まで実行する。received は実行前に購読しておいた受信メッセージ
+pub async fn run_macro<T: MacroTarget>(
+    macro_def: &Macro,
+    target: &mut T,
+    mut received: broadcast::Receiver<TerminalMessage>,
+    context: &MacroContext,
+    mut on_event: impl FnMut(MacroEvent),
+) -> MacroOutcome {
+    let mut buffer = ReceiveBuffer::default();
+    let mut index = 0;
+    let mut executed = 0;
+
+    let outcome = loop {
+        let Some(step) = macro_def.steps.get(index) else {
+            break MacroOutcome::Completed;
+        };
+        executed += 1;
+        if executed > MAX_EXECUTED_STEPS {
+            break MacroOutcome::Failed {
+                step: Some(index),
+                reason: format!("Exceeded {} executed steps", MAX_EXECUTED_STEPS),
+            };
+        }
+
+        on_event(MacroEvent::StepStarted { step: index });
+        debug!("Macro '{}' step {}: {:?}", macro_def.name, index, step);
+
+        let next = match execute_step(step, index, target, &mut received, &mut buffer, context, &mut on_event).await {
+            Ok(next) => next,
+            Err(reason) => break MacroOutcome::Failed { step: Some(index), reason },
+        };
+        on_event(MacroEvent::StepCompleted { step: index });
+
+        match next {
+            StepTarget::Next => index += 1,
+            StepTarget::Goto { step } => index = step,
+            StepTarget::Stop => break MacroOutcome::Stopped { step: index },
+            StepTarget::Fail => {
+                break MacroOutcome::Failed {
+                    step: Some(index),
+                    reason: "Expected pattern was not received".to_string(),
+                }
+            }
+        }
+    };
+
+    on_event(MacroEvent::Finished { outcome: outcome.clone() });
+    outcome
+}
+
+async fn execute_step<T: MacroTarget>(
+    step: &MacroStep,
+    index: usize,
+    target: &mut T,
+    received: &mut broadcast::Receiver<TerminalMessage>,
+    buffer: &mut ReceiveBuffer,
+    context: &MacroContext,
+    on_event: &mut impl FnMut(MacroEvent),
+) -> Result<StepTarget, String> {
+    match step {
+        MacroStep::SendText { text, append_line_ending } => {
+            let mut data = substitute_secrets(text, &context.secrets)?.into_bytes();
+            let mut content = text.clone();
+            if *append_line_ending {
+                data.extend_from_slice(context.line_ending.to_bytes());
+                content.push_str(context.line_ending.to_string());
+            }
+            // 表示・ログにはプレースホルダーのまま残す
+            target.send(&data, content, "UTF-8").await?;
+            Ok(StepTarget::Next)
+        }
+        MacroStep::SendHex { hex } => {
+            let data = parse_hex(hex)?;
+            let content = data.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+            target.send(&data, content, "HEX").await?;
+            Ok(StepTarget::Next)
+        }
+        MacroStep::Wait { ms } => {
+            tokio::time::sleep(Duration::from_millis(*ms)).await;
+            Ok(StepTarget::Next)
+        }
+        MacroStep::WaitFor { pattern, timeout_ms, on_match, on_timeout } => {
+            let regex = Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+            let deadline = Instant::now() + Duration::from_millis(*timeout_ms);
+
+            loop {
+                // 一致した位置までを消費し、次の WaitFor は続きから照合する
+                if let Some(mut groups) = buffer.take_match(&regex) {
+                    let text = groups.swap_remove(0);
+                    on_event(MacroEvent::PatternMatched { step: index, text });
+                    return Ok(on_match.clone());
+                }
+
+                match tokio::time::timeout_at(deadline, received.recv()).await {
+                    Err(_) => {
+                        on_event(MacroEvent::PatternTimedOut { step: index });
+                        return Ok(on_timeout.clone());
+                    }
+                    Ok(Ok(message)) => buffer.push(&message),
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        warn!("Macro receive buffer lagged, {} messages skipped", skipped);
+                    }
+                    Ok(Err(RecvError::Closed)) => return Err("Connection closed".to_string()),
+                }
+            }
+        }
+        MacroStep::SetControlLine { line, level } => {
+            target.set_control_line(*line, *level).await?;
+            Ok(StepTarget::Next)
+        }
+    }
+}
+
+// {{secret:<id>}} を資格情報の値に置き換える
+pub fn substitute_secrets(text: &str, secrets: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{secret:") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + "{{secret:".len()..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "Unterminated secret placeholder".to_string())?;
+        let secret_id = after[..end].trim();
+        let value = secrets
+            .get(secret_id)
+            .ok_or_else(|| format!("Secret '{}' is not available for the connected profile", secret_id))?;
+        result.push_str(value);
+        rest = &after[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// テスト用の送信先。送信内容と制御線の操作を記録し、切断状態に切り替えられる
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::MacroTarget;
+    use crate::models::ControlLine;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // 送信したデータと表示用の内容の組
+    type SentData = (Vec<u8>, String);
+
+    #[derive(Clone)]
+    pub struct RecordingTarget {
+        sent: Arc<Mutex<Vec<SentData>>>,
+        control_lines: Arc<Mutex<Vec<(ControlLine, bool)>>>,
+        connected: Arc<AtomicBool>,
+    }
+
+    impl Default for RecordingTarget {
+        fn default() -> Self {
+            Self {
+                sent: Arc::default(),
+                control_lines: Arc::default(),
+                connected: Arc::new(AtomicBool::new(true)),
+            }
+        }
+    }
+
+    impl RecordingTarget {
+        // 送信したデータと表示用の内容
+        pub fn sent(&self) -> Vec<SentData> {
+            self.sent.lock().unwrap().clone()
+        }
+
+        // 送信したデータを連結したもの
+        pub fn sent_bytes(&self) -> Vec<u8> {
+            self.sent.lock().unwrap().iter().flat_map(|(data, _)| data.clone()).collect()
+        }
+
+        pub fn control_lines(&self) -> Vec<(ControlLine, bool)> {
+            self.control_lines.lock().unwrap().clone()
+        }
+
+        // 未接続にすると送信が "Connection closed" で失敗する
+        pub fn set_connected(&self, connected: bool) {
+            self.connected.store(connected, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl MacroTarget for RecordingTarget {
+        async fn send(&mut self, data: &[u8], content: String, _encoding: &str) -> Result<(), String> {
+            if !self.connected.load(Ordering::SeqCst) {
+                return Err("Connection closed".to_string());
+            }
+            self.sent.lock().unwrap().push((data.to_vec(), content));
+            Ok(())
+        }
+
+        async fn set_control_line(&mut self, line: ControlLine, level: bool) -> Result<(), String> {
+            self.control_lines.lock().unwrap().push((line, level));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::RecordingTarget;
+    use super::*;
+
+    fn context() -> MacroContext {
+        MacroContext {
+            line_ending: LineEnding::CrLf,
+            secrets: HashMap::from([("pw".to_string(), "hunter2".to_string())]),
+        }
+    }
+
+    fn wait_for(pattern: &str, on_match: StepTarget, on_timeout: StepTarget) -> MacroStep {
+        MacroStep::WaitFor {
+            pattern: pattern.to_string(),
+            timeout_ms: 200,
+            on_match,
+            on_timeout,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_sends_and_matches() {
+        let macro_def = Macro::new(
+            "login".to_string(),
+            String::new(),
+            vec![
+                MacroStep::SetControlLine { line: ControlLine::Dtr, level: true },
+                wait_for("login:", StepTarget::Next, StepTarget::Fail),
+                MacroStep::SendText { text: "{{secret:pw}}".to_string(), append_line_ending: true },
+                MacroStep::SendHex { hex: "1b 5b".to_string() },
+            ],
+        );
+        let (tx, rx) = broadcast::channel(16);
+        tx.send(TerminalMessage::new_received("board login: ".to_string(), "UTF-8".to_string())).unwrap();
+
+        let mut target = RecordingTarget::default();
+        let mut events = Vec::new();
+        let outcome = run_macro(&macro_def, &mut target, rx, &context(), |event| events.push(event)).await;
+
+        assert_eq!(outcome, MacroOutcome::Completed);
+        assert_eq!(target.control_lines(), vec![(ControlLine::Dtr, true)]);
+        let sent = target.sent();
+        assert_eq!(sent[0], (b"hunter2\r\n".to_vec(), "{{secret:pw}}\r\n".to_string()));
+        assert_eq!(sent[1], (vec![0x1b, 0x5b], "1B 5B".to_string()));
+        assert!(events.contains(&MacroEvent::PatternMatched { step: 1, text: "login:".to_string() }));
+        assert_eq!(events.last(), Some(&MacroEvent::Finished { outcome: MacroOutcome::Completed }));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_branches() {
+        let macro_def = Macro::new(
+            "retry".to_string(),
+            String::new(),
+            vec![
+                wait_for("never", StepTarget::Next, StepTarget::Goto { step: 2 }),
+                MacroStep::SendText { text: "skipped".to_string(), append_line_ending: false },
+                MacroStep::SendText { text: "fallback".to_string(), append_line_ending: false },
+                wait_for("never", StepTarget::Next, StepTarget::Stop),
+            ],
+        );
+        let (_tx, rx) = broadcast::channel(16);
+
+        let mut target = RecordingTarget::default();
+        let outcome = run_macro(&macro_def, &mut target, rx, &context(), |_| {}).await;
+
+        assert_eq!(outcome, MacroOutcome::Stopped { step: 3 });
+        let sent = target.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1, "fallback");
+    }
+
+    #[tokio::test]
+    async fn test_missing_secret_fails() {
+        let macro_def = Macro::new(
+            "login".to_string(),
+            String::new(),
+            vec![MacroStep::SendText { text: "{{secret:other}}".to_string(), append_line_ending: false }],
+        );
+        let (_tx, rx) = broadcast::channel(16);
+
+        let mut target = RecordingTarget::default();
+        let outcome = run_macro(&macro_def, &mut target, rx, &context(), |_| {}).await;
+
+        assert!(matches!(outcome, MacroOutcome::Failed { step: Some(0), .. }));
+        assert!(target.sent().is_empty());
+    }
+}
\ No newline at end of file
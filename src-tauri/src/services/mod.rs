@@ -1,2 +1,20 @@
-// 将来のサービス層実装用
-// 現在は commands で直接実装しているが、将来的にはビジネスロジックをここに移動する予定
\ No newline at end of file
+// ビジネスロジックを提供するサービス層
+pub mod api_server;
+pub mod import;
+pub mod macros;
+pub mod masking;
+pub mod persistence;
+pub mod scheduler;
+pub mod scripting;
+pub mod session;
+pub mod session_logger;
+pub mod tcp_bridge;
+pub mod test_runner;
+pub mod validation;
+pub mod vault;
+
+pub use masking::DataMasker;
+pub use persistence::{SettingsFile, SettingsPersistence};
+pub use session::ResolvedProfile;
+pub use session_logger::{LogFileInfo, SessionLogger};
+pub use vault::{CredentialVault, KeyDerivation, SecretInfo, VaultError, VaultStatus};
\ No newline at end of file
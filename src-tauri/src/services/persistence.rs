@@ -0,0 +1,472 @@
+use crate::models::{AppConfig, CommandHistory, ProfileManager};
+use crate::utils::fs::{backup_file, write_atomic};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// 保存対象のファイル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SettingsFile {
+    AppConfig,
+    Profiles,
+    CommandHistory,
+}
+
+impl SettingsFile {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            SettingsFile::AppConfig => "settings.json",
+            SettingsFile::Profiles => "profiles.json",
+            SettingsFile::CommandHistory => "command_history.json",
+        }
+    }
+}
+
+enum WriterCommand {
+    Write(PathBuf, Vec<u8>),
+    Flush(mpsc::Sender<()>),
+    Shutdown,
+}
+
+// 設定・プロファイル・コマンド履歴をアプリの設定ディレクトリに保存する
+pub struct SettingsPersistence {
+    dir: PathBuf,
+    sender: Mutex<mpsc::Sender<WriterCommand>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SettingsPersistence {
+    pub fn new(dir: PathBuf) -> Self {
+        Self::with_debounce(dir, DEFAULT_DEBOUNCE)
+    }
+
+    pub fn with_debounce(dir: PathBuf, debounce: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("settings-writer".to_string())
+            .spawn(move || run_writer(receiver, debounce))
+            .expect("failed to spawn settings writer thread");
+
+        Self {
+            dir,
+            sender: Mutex::new(sender),
+            writer: Mutex::new(Some(writer)),
+        }
+    }
+
+    pub fn load_app_config(&self) -> AppConfig {
+        let path = self.path(SettingsFile::AppConfig);
+        let Some(value) = self.read_value(&path) else {
+            return AppConfig::default();
+        };
+
+        let (value, migrated_from) = migrate_app_config(value);
+        match from_value_with_defaults::<AppConfig>(value) {
+            Ok(config) => {
+                if let Some(old_version) = migrated_from {
+                    info!("Migrated settings from version {} to {}", old_version, config.version);
+                    save_migrated(&path, &old_version, &config);
+                }
+                config
+            }
+            Err(e) => {
+                backup_corrupt(&path, &e);
+                AppConfig::default()
+            }
+        }
+    }
+
+    pub fn load_profiles(&self) -> ProfileManager {
+        let mut profile_manager: ProfileManager = self.load(SettingsFile::Profiles);
+        profile_manager.repair_groups();
+        profile_manager
+    }
+
+    pub fn load_command_history(&self) -> CommandHistory {
+        let mut history: CommandHistory = self.load(SettingsFile::CommandHistory);
+        history.current_index = None;
+        history.migrate_legacy();
+        history
+    }
+
+    // 保存を予約する。短時間に連続した変更はまとめて書き込まれる
+    pub fn schedule_save<T: Serialize>(&self, file: SettingsFile, value: &T) {
+        let data = match serde_json::to_vec_pretty(value) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize {}: {}", file.file_name(), e);
+                return;
+            }
+        };
+
+        if self.send(WriterCommand::Write(self.path(file), data)).is_err() {
+            error!("Settings writer is not running; {} was not saved", file.file_name());
+        }
+    }
+
+    // 予約済みの保存をすべて書き込むまで待つ
+    pub fn flush(&self) {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        if self.send(WriterCommand::Flush(ack_tx)).is_ok() {
+            let _ = ack_rx.recv();
+        }
+    }
+
+    fn send(&self, command: WriterCommand) -> Result<(), mpsc::SendError<WriterCommand>> {
+        self.sender
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send(command)
+    }
+
+    fn path(&self, file: SettingsFile) -> PathBuf {
+        self.dir.join(file.file_name())
+    }
+
+    fn load<T: Serialize + DeserializeOwned + Default>(&self, file: SettingsFile) -> T {
+        let path = self.path(file);
+        let Some(value) = self.read_value(&path) else {
+            return T::default();
+        };
+
+        match from_value_with_defaults(value) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                backup_corrupt(&path, &e);
+                T::default()
+            }
+        }
+    }
+
+    // ファイルが存在しない、または読み込めない場合は None
+    fn read_value(&self, path: &Path) -> Option<Value> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("Settings file not found, using defaults: {:?}", path);
+                return None;
+            }
+            Err(e) => {
+                error!("Failed to read {:?}: {}", path, e);
+                return None;
+            }
+        };
+
+        match serde_json::from_str(&text) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                backup_corrupt(path, &e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for SettingsPersistence {
+    fn drop(&mut self) {
+        let _ = self.send(WriterCommand::Shutdown);
+        if let Some(writer) = self.writer.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+// 壊れたファイルは上書きせずに退避する。呼び出し側は既定値で起動する
+fn backup_corrupt(path: &Path, e: &serde_json::Error) {
+    error!("Settings file {:?} is corrupt: {}", path, e);
+    match backup_file(path, "corrupt") {
+        Ok(backup) => warn!("Backed up corrupt settings file to {:?}", backup),
+        Err(e) => error!("Failed to back up corrupt settings file {:?}: {}", path, e),
+    }
+}
+
+// 移行前のファイルを残したうえで、移行後の内容をすぐに書き込む
+fn save_migrated<T: Serialize>(path: &Path, old_version: &str, value: &T) {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup_path = path.with_file_name(format!("{}.v{}.bak", file_name, old_version));
+    if let Err(e) = fs::copy(path, &backup_path) {
+        warn!("Failed to back up settings before migration: {}", e);
+    }
+
+    let result = serde_json::to_vec_pretty(value)
+        .map_err(io::Error::other)
+        .and_then(|data| write_atomic(path, &data));
+    if let Err(e) = result {
+        error!("Failed to save migrated settings {:?}: {}", path, e);
+    }
+}
+
+fn run_writer(receiver: mpsc::Receiver<WriterCommand>, debounce: Duration) {
+    let mut pending: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let command = match deadline {
+            Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match command {
+            Ok(WriterCommand::Write(path, data)) => {
+                pending.insert(path, data);
+                // 最初の変更から一定時間後に書き込む
+                deadline.get_or_insert_with(|| Instant::now() + debounce);
+            }
+            Ok(WriterCommand::Flush(ack)) => {
+                write_pending(&mut pending);
+                deadline = None;
+                let _ = ack.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                write_pending(&mut pending);
+                deadline = None;
+            }
+            Ok(WriterCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                write_pending(&mut pending);
+                break;
+            }
+        }
+    }
+}
+
+fn write_pending(pending: &mut HashMap<PathBuf, Vec<u8>>) {
+    for (path, data) in pending.drain() {
+        match write_atomic(&path, &data) {
+            Ok(_) => debug!("Saved settings file: {:?}", path),
+            Err(e) => error!("Failed to save settings file {:?}: {}", path, e),
+        }
+    }
+}
+
+// 既定値をもとに不足しているフィールドを補ってから読み込む
+fn from_value_with_defaults<T: Serialize + DeserializeOwned + Default>(
+    value: Value,
+) -> Result<T, serde_json::Error> {
+    let mut merged = serde_json::to_value(T::default())?;
+    merge_json(&mut merged, value);
+    serde_json::from_value(merged)
+}
+
+fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// 古いバージョンの設定ファイルを現在の形式に移行する。移行した場合は元のバージョンを返す
+fn migrate_app_config(mut value: Value) -> (Value, Option<String>) {
+    let current = env!("CARGO_PKG_VERSION");
+    let file_version = value
+        .get("version")
+        .and_then(Value::as_str)
+        .unwrap_or("0.0.0")
+        .to_string();
+
+    if parse_version(&file_version) >= parse_version(current) {
+        return (value, None);
+    }
+
+    if let Value::Object(object) = &mut value {
+        // 不足しているフィールドは読み込み時に既定値で補われる
+        object.insert("version".to_string(), Value::String(current.to_string()));
+    }
+    (value, Some(file_version))
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .split(['.', '-', '+'])
+        .take(3)
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ConnectionConfig, ConnectionType, TcpConfig};
+    use chrono::Utc;
+
+    fn create_test_persistence(dir: &Path) -> SettingsPersistence {
+        SettingsPersistence::with_debounce(dir.to_path_buf(), Duration::from_millis(20))
+    }
+
+    fn create_test_profile(id: &str) -> ConnectionConfig {
+        ConnectionConfig {
+            id: id.to_string(),
+            name: format!("Profile {}", id),
+            connection_type: ConnectionType::Tcp,
+            serial_config: None,
+            tcp_config: Some(TcpConfig {
+                host: "localhost".to_string(),
+                port: 8080,
+                timeout: Duration::from_secs(5),
+                keep_alive: true,
+            }),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            secret_refs: Vec::new(),
+            tags: Vec::new(),
+            last_connected_at: None,
+            terminal_overrides: Default::default(),
+            triggers: Vec::new(),
+        }
+    }
+
+    fn backups(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with(prefix))
+            .collect()
+    }
+
+    #[test]
+    fn test_load_missing_files_returns_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = create_test_persistence(dir.path());
+
+        assert_eq!(persistence.load_app_config().version, env!("CARGO_PKG_VERSION"));
+        assert!(persistence.load_profiles().profiles.is_empty());
+        assert!(persistence.load_command_history().entries.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = create_test_persistence(dir.path());
+
+        let mut manager = ProfileManager::default();
+        manager.add_profile(create_test_profile("a"));
+        manager.set_active_profile("a".to_string());
+        persistence.schedule_save(SettingsFile::Profiles, &manager);
+        persistence.flush();
+
+        let loaded = create_test_persistence(dir.path()).load_profiles();
+        assert_eq!(loaded.profiles.len(), 1);
+        assert_eq!(loaded.active_profile_id, Some("a".to_string()));
+        assert_eq!(loaded.last_used_profiles, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_debounced_saves_write_latest_value() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = create_test_persistence(dir.path());
+
+        let mut history = CommandHistory::default();
+        for command in ["one", "two", "three"] {
+            history.add_command(command.to_string(), None);
+            persistence.schedule_save(SettingsFile::CommandHistory, &history);
+        }
+
+        // デバウンス間隔が経過すると書き込まれる
+        std::thread::sleep(Duration::from_millis(200));
+        let loaded = persistence.load_command_history();
+        let commands: Vec<&str> = loaded.entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_drop_flushes_pending_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let persistence = SettingsPersistence::with_debounce(dir.path().to_path_buf(), Duration::from_secs(60));
+            persistence.schedule_save(SettingsFile::Profiles, &ProfileManager::default());
+        }
+
+        assert!(dir.path().join("profiles.json").exists());
+    }
+
+    #[test]
+    fn test_corrupt_file_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profiles.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let persistence = create_test_persistence(dir.path());
+        let loaded = persistence.load_profiles();
+
+        assert!(loaded.profiles.is_empty());
+        assert!(!path.exists());
+        let backups = backups(dir.path(), "profiles.json.corrupt-");
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "{ not json");
+    }
+
+    #[test]
+    fn test_invalid_structure_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("command_history.json"), r#"{"commands": 42}"#).unwrap();
+
+        let persistence = create_test_persistence(dir.path());
+        let loaded = persistence.load_command_history();
+
+        assert!(loaded.entries.is_empty());
+        assert_eq!(backups(dir.path(), "command_history.json.corrupt-").len(), 1);
+    }
+
+    #[test]
+    fn test_migrate_old_app_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        // 古い形式: logging や security などが存在しない
+        fs::write(
+            &path,
+            r#"{"version": "0.0.1", "window": {"width": 800}, "last_updated": "2024-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+
+        let persistence = create_test_persistence(dir.path());
+        let config = persistence.load_app_config();
+
+        assert_eq!(config.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(config.window.width, 800);
+        assert_eq!(config.window.height, 800);
+        assert!(config.logging.mask_sensitive_data);
+        assert!(dir.path().join("settings.json.v0.0.1.bak").exists());
+
+        // 移行後の内容で保存し直される
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_current_app_config_is_not_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = create_test_persistence(dir.path());
+        persistence.schedule_save(SettingsFile::AppConfig, &AppConfig::default());
+        persistence.flush();
+
+        persistence.load_app_config();
+
+        assert!(backups(dir.path(), "settings.json.v").is_empty());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert!(parse_version("0.1.0") > parse_version("0.0.9"));
+        assert!(parse_version("1.2.0-beta") == parse_version("1.2.0"));
+        assert!(parse_version("0.10.0") > parse_version("0.9.0"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,283 @@
+use super::macros::MacroTarget;
+use crate::models::{JobSchedule, JobState, SendJob, SendJobSpec};
+use chrono::{Local, NaiveDateTime, NaiveTime, Utc};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{debug, info, warn};
+
+// 定期送信ジョブを管理する。ジョブは接続とは独立しており、再接続後もそのまま続く。
+// ジョブはメモリ上にのみ保持し、保存しない
+pub struct JobScheduler<T> {
+    target: T,
+    jobs: Arc<Mutex<HashMap<String, SendJob>>>,
+    tasks: HashMap<String, JoinHandle<()>>,
+}
+
+impl<T: MacroTarget + Clone + Sync + 'static> JobScheduler<T> {
+    pub fn new(target: T) -> Self {
+        Self {
+            target,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            tasks: HashMap::new(),
+        }
+    }
+
+    pub async fn add_job(&mut self, spec: SendJobSpec, start: bool) -> Result<SendJob, String> {
+        spec.validate()?;
+
+        let mut job = SendJob::new(spec);
+        if !start {
+            job.state = JobState::Paused;
+        }
+        self.jobs.lock().await.insert(job.id.clone(), job.clone());
+        if start {
+            self.spawn(&job.id);
+        }
+        info!("Added scheduled job '{}'", job.spec.name);
+        Ok(job)
+    }
+
+    // 作成順に並べる
+    pub async fn list_jobs(&self) -> Vec<SendJob> {
+        let mut jobs: Vec<SendJob> = self.jobs.lock().await.values().cloned().collect();
+        jobs.sort_by_key(|job| job.created_at);
+        jobs
+    }
+
+    pub async fn pause_job(&mut self, job_id: &str) -> Result<SendJob, String> {
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.get_mut(job_id).ok_or_else(|| format!("Job not found: {}", job_id))?;
+        if let Some(task) = self.tasks.remove(job_id) {
+            task.abort();
+        }
+        if job.state == JobState::Running {
+            job.state = JobState::Paused;
+        }
+        Ok(job.clone())
+    }
+
+    // 一時停止したジョブを再開する。上限に達したジョブは回数をリセットして再開する。
+    // 終了処理中のタスクを生きているとみなして再開し損ねないよう、タスクは常に作り直す
+    pub async fn resume_job(&mut self, job_id: &str) -> Result<SendJob, String> {
+        let job = {
+            let mut jobs = self.jobs.lock().await;
+            let job = jobs.get_mut(job_id).ok_or_else(|| format!("Job not found: {}", job_id))?;
+            if job.state == JobState::Completed {
+                job.sent_count = 0;
+            }
+            job.state = JobState::Running;
+            job.clone()
+        };
+        if let Some(task) = self.tasks.remove(job_id) {
+            task.abort();
+        }
+        self.spawn(job_id);
+        Ok(job)
+    }
+
+    pub async fn remove_job(&mut self, job_id: &str) -> bool {
+        if let Some(task) = self.tasks.remove(job_id) {
+            task.abort();
+        }
+        self.jobs.lock().await.remove(job_id).is_some()
+    }
+
+    fn spawn(&mut self, job_id: &str) {
+        let task = tokio::spawn(run_job(job_id.to_string(), self.jobs.clone(), self.target.clone()));
+        self.tasks.insert(job_id.to_string(), task);
+    }
+}
+
+async fn run_job<T: MacroTarget>(job_id: String, jobs: Arc<Mutex<HashMap<String, SendJob>>>, mut target: T) {
+    loop {
+        let (delay, spec) = {
+            let jobs = jobs.lock().await;
+            let Some(job) = jobs.get(&job_id).filter(|job| job.state == JobState::Running) else {
+                return;
+            };
+            match next_delay(&job.spec.schedule, Local::now().naive_local()) {
+                Some(delay) => (delay, job.spec.clone()),
+                None => return,
+            }
+        };
+        tokio::time::sleep(delay).await;
+
+        // send_message と同じ経路で送信する。未接続の場合は失敗として記録し、回数には含めない
+        let result = match spec.encode() {
+            Ok((data, content, encoding)) => target.send(&data, content, encoding).await,
+            Err(e) => Err(e),
+        };
+
+        let mut jobs = jobs.lock().await;
+        let Some(job) = jobs.get_mut(&job_id) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                job.sent_count += 1;
+                job.last_sent_at = Some(Utc::now());
+                job.last_error = None;
+                debug!("Scheduled job '{}' sent ({} times)", job.spec.name, job.sent_count);
+            }
+            Err(e) => {
+                warn!("Scheduled job '{}' failed to send: {}", job.spec.name, e);
+                job.last_error = Some(e);
+            }
+        }
+        if job.spec.max_count.map(|max| job.sent_count >= max).unwrap_or(false) {
+            job.state = JobState::Completed;
+            info!("Scheduled job '{}' completed", job.spec.name);
+            return;
+        }
+    }
+}
+
+// 次に送信するまでの待ち時間
+fn next_delay(schedule: &JobSchedule, now: NaiveDateTime) -> Option<Duration> {
+    match schedule {
+        JobSchedule::Interval { interval_ms, jitter_ms } => {
+            let jitter = if *jitter_ms > 0 { random_u64() % (jitter_ms + 1) } else { 0 };
+            Some(Duration::from_millis(interval_ms + jitter))
+        }
+        JobSchedule::Daily { times } => next_daily_time(times, now).and_then(|next| (next - now).to_std().ok()),
+    }
+}
+
+// now より後で最も近い指定時刻
+fn next_daily_time(times: &[NaiveTime], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    times
+        .iter()
+        .map(|time| {
+            let today = now.date().and_time(*time);
+            if today > now {
+                today
+            } else {
+                today + chrono::Duration::days(1)
+            }
+        })
+        .min()
+}
+
+// ジッター用の乱数（暗号用途ではない）
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PayloadFormat;
+    use crate::services::macros::test_support::RecordingTarget;
+    use chrono::NaiveDate;
+
+    fn create_spec(max_count: Option<u64>) -> SendJobSpec {
+        SendJobSpec {
+            name: "poll".to_string(),
+            payload: "READ\r".to_string(),
+            format: PayloadFormat::Text,
+            schedule: JobSchedule::Interval { interval_ms: 10, jitter_ms: 5 },
+            max_count,
+        }
+    }
+
+    async fn wait_for_state(scheduler: &JobScheduler<RecordingTarget>, state: JobState) -> SendJob {
+        for _ in 0..200 {
+            let job = scheduler.list_jobs().await.remove(0);
+            if job.state == state {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job did not reach {:?}", state);
+    }
+
+    #[tokio::test]
+    async fn test_job_stops_at_count_limit() {
+        let target = RecordingTarget::default();
+        let mut scheduler = JobScheduler::new(target.clone());
+
+        scheduler.add_job(create_spec(Some(3)), true).await.unwrap();
+        let job = wait_for_state(&scheduler, JobState::Completed).await;
+
+        assert_eq!(job.sent_count, 3);
+        assert_eq!(target.sent().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_sends_are_not_counted_and_job_survives_reconnect() {
+        let target = RecordingTarget::default();
+        target.set_connected(false);
+        let mut scheduler = JobScheduler::new(target.clone());
+        let job = scheduler.add_job(create_spec(Some(2)), true).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let disconnected = scheduler.list_jobs().await.remove(0);
+        assert_eq!(disconnected.sent_count, 0);
+        assert_eq!(disconnected.last_error.as_deref(), Some("Connection closed"));
+
+        target.set_connected(true);
+        let completed = wait_for_state(&scheduler, JobState::Completed).await;
+        assert_eq!(completed.sent_count, 2);
+        assert_eq!(completed.last_error, None);
+
+        // 再開すると回数をリセットしてもう一度送信する
+        scheduler.resume_job(&job.id).await.unwrap();
+        wait_for_state(&scheduler, JobState::Completed).await;
+        assert_eq!(target.sent().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replaces_running_task() {
+        let target = RecordingTarget::default();
+        let mut scheduler = JobScheduler::new(target.clone());
+        let job = scheduler.add_job(create_spec(Some(3)), true).await.unwrap();
+
+        // 実行中のジョブを再開しても送信するタスクは1つだけ
+        scheduler.resume_job(&job.id).await.unwrap();
+        scheduler.resume_job(&job.id).await.unwrap();
+        assert_eq!(scheduler.tasks.len(), 1);
+
+        let completed = wait_for_state(&scheduler, JobState::Completed).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(completed.sent_count, 3);
+        assert_eq!(target.sent().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_remove() {
+        let target = RecordingTarget::default();
+        let mut scheduler = JobScheduler::new(target.clone());
+        let job = scheduler.add_job(create_spec(None), false).await.unwrap();
+        assert_eq!(job.state, JobState::Paused);
+
+        scheduler.resume_job(&job.id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let paused = scheduler.pause_job(&job.id).await.unwrap();
+        assert_eq!(paused.state, JobState::Paused);
+
+        let sent = target.sent().len();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(target.sent().len(), sent);
+
+        assert!(scheduler.remove_job(&job.id).await);
+        assert!(scheduler.list_jobs().await.is_empty());
+    }
+
+    #[test]
+    fn test_next_daily_time() {
+        let times = vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap(), NaiveTime::from_hms_opt(18, 0, 0).unwrap()];
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+
+        let morning = date.and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(next_daily_time(&times, morning), Some(date.and_hms_opt(18, 0, 0).unwrap()));
+
+        let night = date.and_hms_opt(18, 0, 0).unwrap();
+        let tomorrow = NaiveDate::from_ymd_opt(2024, 5, 2).unwrap();
+        assert_eq!(next_daily_time(&times, night), Some(tomorrow.and_hms_opt(8, 0, 0).unwrap()));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,413 @@
+use super::macros::{MacroTarget, ReceiveBuffer};
+use crate::models::{ConnectionConfig, ControlLine, LineEnding, TerminalMessage};
+use regex::Regex;
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Map, Position, Scope, INT};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+// sleep・expect の待機中に中断を確認する間隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+// スクリプトが確保できるデータ量の上限
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_ARRAY_SIZE: usize = 100_000;
+const MAX_MAP_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 64;
+
+// 実行時に接続先から決まる値
+#[derive(Debug, Clone)]
+pub struct ScriptContext {
+    pub line_ending: LineEnding,
+    pub secrets: HashMap<String, String>, // secret_id -> 値
+    pub profile: Option<ConnectionConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "status")]
+pub enum ScriptOutcome {
+    Passed,
+    Failed { reason: String },
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScriptResult {
+    pub outcome: ScriptOutcome,
+    pub output: Vec<String>, // print / log の出力
+}
+
+struct Session<T> {
+    target: T,
+    received: broadcast::Receiver<TerminalMessage>,
+    buffer: ReceiveBuffer,
+}
+
+// Rhai スクリプトを実行する。送受信は runtime 上で待つため、spawn_blocking から呼び出すこと。
+// スクリプトが false を返すか例外を投げた場合は失敗とする
+pub fn run_script<T: MacroTarget + 'static>(
+    source: &str,
+    target: T,
+    received: broadcast::Receiver<TerminalMessage>,
+    context: ScriptContext,
+    cancel: Arc<AtomicBool>,
+    runtime: Handle,
+    on_output: impl Fn(&str) + Send + Sync + 'static,
+) -> ScriptResult {
+    let session = Arc::new(Mutex::new(Session {
+        target,
+        received,
+        buffer: ReceiveBuffer::default(),
+    }));
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let on_output: Arc<dyn Fn(&str) + Send + Sync> = Arc::new(on_output);
+    let engine = create_engine(session, context.clone(), cancel.clone(), runtime, output.clone(), on_output);
+
+    let mut scope = Scope::new();
+    scope.push_constant("profile", profile_map(context.profile.as_ref()));
+
+    let result = engine.eval_with_scope::<Dynamic>(&mut scope, source);
+    let outcome = if cancel.load(Ordering::SeqCst) {
+        ScriptOutcome::Cancelled
+    } else {
+        match result {
+            Ok(value) if value.as_bool() == Ok(false) => ScriptOutcome::Failed {
+                reason: "Script returned false".to_string(),
+            },
+            Ok(_) => ScriptOutcome::Passed,
+            Err(e) => ScriptOutcome::Failed { reason: e.to_string() },
+        }
+    };
+    debug!("Script finished: {:?}", outcome);
+
+    let output = std::mem::take(&mut *output.lock().unwrap_or_else(|e| e.into_inner()));
+    ScriptResult { outcome, output }
+}
+
+fn create_engine<T: MacroTarget + 'static>(
+    session: Arc<Mutex<Session<T>>>,
+    context: ScriptContext,
+    cancel: Arc<AtomicBool>,
+    runtime: Handle,
+    output: Arc<Mutex<Vec<String>>>,
+    on_output: Arc<dyn Fn(&str) + Send + Sync>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS);
+    engine.disable_symbol("eval");
+
+    {
+        let cancel = cancel.clone();
+        engine.on_progress(move |_| cancel.load(Ordering::SeqCst).then_some(Dynamic::UNIT));
+    }
+
+    // 送信内容の表示や出力に資格情報の値が残らないよう、プレースホルダーに戻す
+    let hidden_secrets = Arc::new(secret_placeholders(&context.secrets));
+
+    let write_output = {
+        let output = output.clone();
+        let hidden_secrets = hidden_secrets.clone();
+        move |line: &str| {
+            let line = hide_secrets(line, &hidden_secrets);
+            output.lock().unwrap_or_else(|e| e.into_inner()).push(line.clone());
+            on_output(&line);
+        }
+    };
+    {
+        let write_output = write_output.clone();
+        engine.on_print(move |text| write_output(text));
+    }
+    {
+        let write_output = write_output.clone();
+        engine.on_debug(move |text, _, _| write_output(text));
+    }
+    engine.register_fn("log", move |text: &str| write_output(text));
+
+    // 送信
+    let send = {
+        let session = session.clone();
+        let runtime = runtime.clone();
+        move |data: Vec<u8>, content: String, encoding: &str| -> Result<(), Box<EvalAltResult>> {
+            let content = hide_secrets(&content, &hidden_secrets);
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            runtime
+                .block_on(session.target.send(&data, content, encoding))
+                .map_err(|e| e.into())
+        }
+    };
+    {
+        let send = send.clone();
+        engine.register_fn("send", move |text: &str| send(text.as_bytes().to_vec(), text.to_string(), "UTF-8"));
+    }
+    {
+        let send = send.clone();
+        let line_ending = context.line_ending.clone();
+        engine.register_fn("send_line", move |text: &str| {
+            let content = format!("{}{}", text, line_ending.to_string());
+            send(content.as_bytes().to_vec(), content, "UTF-8")
+        });
+    }
+    {
+        let send = send.clone();
+        engine.register_fn("send_bytes", move |data: Blob| {
+            let content = hex_content(&data);
+            send(data, content, "HEX")
+        });
+    }
+    engine.register_fn("send_bytes", move |values: Array| -> Result<(), Box<EvalAltResult>> {
+        let data = values
+            .iter()
+            .map(|value| {
+                value
+                    .as_int()
+                    .ok()
+                    .and_then(|byte| u8::try_from(byte).ok())
+                    .ok_or_else(|| format!("send_bytes expects values from 0 to 255, got {}", value))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let content = hex_content(&data);
+        send(data, content, "HEX")
+    });
+
+    // 受信データの待機。一致すればキャプチャの配列（0 番目は一致全体）、タイムアウトなら () を返す
+    {
+        let session = session.clone();
+        let cancel = cancel.clone();
+        let runtime = runtime.clone();
+        engine.register_fn("expect", move |pattern: &str, timeout_ms: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+            let regex = Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+            let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(0) as u64);
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            let Session { received, buffer, .. } = &mut *session;
+
+            loop {
+                if let Some(groups) = buffer.take_match(&regex) {
+                    return Ok(groups.into_iter().map(Dynamic::from).collect::<Array>().into());
+                }
+                if cancel.load(Ordering::SeqCst) {
+                    return Err(terminated());
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(Dynamic::UNIT);
+                }
+
+                let wait = (deadline - now).min(CANCEL_CHECK_INTERVAL);
+                match runtime.block_on(tokio::time::timeout(wait, received.recv())) {
+                    Err(_) => {}
+                    Ok(Ok(message)) => buffer.push(&message),
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        warn!("Script receive buffer lagged, {} messages skipped", skipped);
+                    }
+                    Ok(Err(RecvError::Closed)) => return Err("Connection closed".into()),
+                }
+            }
+        });
+    }
+
+    {
+        let cancel = cancel.clone();
+        engine.register_fn("sleep", move |ms: INT| -> Result<(), Box<EvalAltResult>> {
+            let deadline = std::time::Instant::now() + Duration::from_millis(ms.max(0) as u64);
+            loop {
+                if cancel.load(Ordering::SeqCst) {
+                    return Err(terminated());
+                }
+                let now = std::time::Instant::now();
+                if now >= deadline {
+                    return Ok(());
+                }
+                std::thread::sleep((deadline - now).min(CANCEL_CHECK_INTERVAL));
+            }
+        });
+    }
+
+    // 制御線
+    for (name, line) in [("set_dtr", ControlLine::Dtr), ("set_rts", ControlLine::Rts)] {
+        let session = session.clone();
+        let runtime = runtime.clone();
+        engine.register_fn(name, move |level: bool| -> Result<(), Box<EvalAltResult>> {
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            runtime
+                .block_on(session.target.set_control_line(line, level))
+                .map_err(|e| e.into())
+        });
+    }
+
+    // 接続中プロファイルが参照している資格情報
+    let secrets = context.secrets;
+    engine.register_fn("secret", move |secret_id: &str| -> Result<String, Box<EvalAltResult>> {
+        secrets
+            .get(secret_id)
+            .cloned()
+            .ok_or_else(|| format!("Secret '{}' is not available for the connected profile", secret_id).into())
+    });
+
+    engine
+}
+
+fn terminated() -> Box<EvalAltResult> {
+    EvalAltResult::ErrorTerminated(Dynamic::UNIT, Position::NONE).into()
+}
+
+// 資格情報の値と置き換え後のプレースホルダー。長い値から置き換えるよう並べる
+fn secret_placeholders(secrets: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut placeholders: Vec<(String, String)> = secrets
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(secret_id, value)| (value.clone(), format!("{{{{secret:{}}}}}", secret_id)))
+        .collect();
+    placeholders.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+    placeholders
+}
+
+fn hide_secrets(text: &str, placeholders: &[(String, String)]) -> String {
+    placeholders
+        .iter()
+        .fold(text.to_string(), |text, (value, placeholder)| text.replace(value, placeholder))
+}
+
+fn hex_content(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+// スクリプトから参照するプロファイルの値（一時的な接続では空）
+fn profile_map(profile: Option<&ConnectionConfig>) -> Map {
+    let mut map = Map::new();
+    let Some(profile) = profile else {
+        return map;
+    };
+
+    map.insert("id".into(), profile.id.clone().into());
+    map.insert("name".into(), profile.name.clone().into());
+    map.insert(
+        "tags".into(),
+        profile.tags.iter().cloned().map(Dynamic::from).collect::<Array>().into(),
+    );
+    if let Some(serial) = &profile.serial_config {
+        map.insert("serial_port".into(), serial.port.clone().into());
+        map.insert("baud_rate".into(), (serial.baud_rate as INT).into());
+    }
+    if let Some(tcp) = &profile.tcp_config {
+        map.insert("host".into(), tcp.host.clone().into());
+        map.insert("port".into(), (tcp.port as INT).into());
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::macros::test_support::RecordingTarget;
+
+    fn context() -> ScriptContext {
+        ScriptContext {
+            line_ending: LineEnding::Lf,
+            secrets: HashMap::from([("pw".to_string(), "hunter2".to_string())]),
+            profile: None,
+        }
+    }
+
+    async fn run(source: &str, messages: &[&str], cancel: Arc<AtomicBool>) -> (ScriptResult, Vec<Vec<u8>>) {
+        run_with(RecordingTarget::default(), source, messages, cancel).await
+    }
+
+    async fn run_with(
+        target: RecordingTarget,
+        source: &str,
+        messages: &[&str],
+        cancel: Arc<AtomicBool>,
+    ) -> (ScriptResult, Vec<Vec<u8>>) {
+        let (tx, rx) = broadcast::channel(16);
+        for message in messages {
+            tx.send(TerminalMessage::new_received(message.to_string(), "UTF-8".to_string())).unwrap();
+        }
+
+        let source = source.to_string();
+        let runtime = Handle::current();
+        let script_target = target.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            run_script(&source, script_target, rx, context(), cancel, runtime, |_| {})
+        })
+        .await
+        .unwrap();
+        drop(tx);
+        let sent = target.sent().into_iter().map(|(data, _)| data).collect();
+        (result, sent)
+    }
+
+    #[tokio::test]
+    async fn test_send_expect_and_parse() {
+        let source = r#"
+            let m = expect("serial=(\\w+)", 500);
+            if m == () { throw "no serial number"; }
+            log(`serial ${m[1]}`);
+            send_line("pass " + secret("pw"));
+            send_bytes([0x1b, 0x5b]);
+            true
+        "#;
+        let (result, sent) = run(source, &["boot ok serial=AB12\r\n"], Arc::new(AtomicBool::new(false))).await;
+
+        assert_eq!(result.outcome, ScriptOutcome::Passed);
+        assert_eq!(result.output, vec!["serial AB12".to_string()]);
+        assert_eq!(sent, vec![b"pass hunter2\n".to_vec(), vec![0x1b, 0x5b]]);
+    }
+
+    #[tokio::test]
+    async fn test_secret_values_are_hidden() {
+        let source = r#"
+            let pw = secret("pw");
+            send_line("pass " + pw);
+            log("using " + pw);
+        "#;
+        let target = RecordingTarget::default();
+        let (result, _) = run_with(target.clone(), source, &[], Arc::new(AtomicBool::new(false))).await;
+
+        assert_eq!(result.outcome, ScriptOutcome::Passed);
+        assert_eq!(result.output, vec!["using {{secret:pw}}".to_string()]);
+        assert_eq!(
+            target.sent(),
+            vec![(b"pass hunter2\n".to_vec(), "pass {{secret:pw}}\n".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_false_result_fail() {
+        let source = r#"
+            let m = expect("never", 50);
+            m != ()
+        "#;
+        let (result, _) = run(source, &[], Arc::new(AtomicBool::new(false))).await;
+        assert_eq!(
+            result.outcome,
+            ScriptOutcome::Failed { reason: "Script returned false".to_string() }
+        );
+
+        let target = RecordingTarget::default();
+        target.set_connected(false);
+        let (result, _) = run_with(target, "send(\"x\");", &[], Arc::new(AtomicBool::new(false))).await;
+        assert!(matches!(result.outcome, ScriptOutcome::Failed { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_script() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        let (result, _) = run("loop { sleep(10); }", &[], cancel).await;
+        assert_eq!(result.outcome, ScriptOutcome::Cancelled);
+    }
+}
\ No newline at end of file
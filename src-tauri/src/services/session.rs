@@ -0,0 +1,38 @@
+use super::CredentialVault;
+use crate::communication::{ConnectionManager, ConnectionResult, MessageSender};
+use crate::models::ConnectionConfig;
+use std::collections::HashMap;
+
+// 接続に使う設定と、そこから参照している秘密情報。
+// 秘密情報はメモリ上にのみ保持し、切断時に破棄する
+pub struct ResolvedProfile {
+    pub config: ConnectionConfig,
+    pub secrets: HashMap<String, String>, // secret_id -> 値
+}
+
+impl ResolvedProfile {
+    // 保存済みプロファイルが参照している秘密情報を資格情報ストアから取り出す
+    pub fn resolve(config: ConnectionConfig, vault: &mut CredentialVault) -> Result<Self, String> {
+        let secrets = vault.resolve_profile_secrets(&config)?;
+        Ok(Self { config, secrets })
+    }
+
+    // その場で指定した接続設定。秘密情報は参照しない
+    pub fn temporary(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            secrets: HashMap::new(),
+        }
+    }
+}
+
+// 接続し、トリガーの応答で使う秘密情報を渡す。アプリと CLI はこの手順で接続する
+pub async fn connect(
+    manager: &mut ConnectionManager,
+    profile: &ResolvedProfile,
+    message_tx: MessageSender,
+) -> ConnectionResult<()> {
+    manager.connect(profile.config.clone(), message_tx).await?;
+    manager.set_secrets(profile.secrets.clone());
+    Ok(())
+}
\ No newline at end of file
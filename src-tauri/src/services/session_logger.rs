@@ -0,0 +1,671 @@
+use super::masking::{continues_line, is_partial_line};
+use super::DataMasker;
+use crate::models::{LoggingConfig, MessageDirection, TerminalMessage};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info, warn};
+
+const LOG_FILE_EXTENSION: &str = "log";
+
+// 実行中でも他のツールから読めるよう、書き込みからこの時間内にフラッシュする
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+// ログファイルの情報
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LogFileInfo {
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+enum LogCommand {
+    Start(String),
+    End,
+    Close,
+    Write { line: String, date: NaiveDate },
+    Configure { max_file_size_bytes: u64, retention_days: u32 },
+    Flush(mpsc::Sender<()>),
+    Shutdown,
+}
+
+// セッションごとの送受信内容をファイルに保存するサービス。
+// ファイルへの書き込みとローテーションは専用のスレッドで行い、呼び出し側をブロックしない
+pub struct SessionLogger {
+    log_dir: PathBuf,
+    config: LoggingConfig,
+    masker: DataMasker,
+    session_active: bool,
+    pending: Option<TerminalMessage>, // 行末を受信していない受信データ。分割された機密情報もマスクできるよう行単位で記録する
+    current_path: Arc<Mutex<Option<PathBuf>>>,
+    sender: Mutex<mpsc::Sender<LogCommand>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SessionLogger {
+    pub fn new(log_dir: PathBuf, config: LoggingConfig) -> Self {
+        let current_path = Arc::new(Mutex::new(None));
+        let mut log_writer = LogWriter::new(log_dir.clone(), current_path.clone());
+        log_writer.configure(max_file_size_bytes(&config), config.retention_days);
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("session-log-writer".to_string())
+            .spawn(move || log_writer.run(receiver))
+            .expect("failed to spawn session log writer thread");
+
+        Self {
+            log_dir,
+            masker: DataMasker::new_lossy(&config),
+            config,
+            session_active: false,
+            pending: None,
+            current_path,
+            sender: Mutex::new(sender),
+            writer: Mutex::new(Some(writer)),
+        }
+    }
+
+    pub fn log_dir(&self) -> &Path {
+        &self.log_dir
+    }
+
+    pub fn update_config(&mut self, config: LoggingConfig) {
+        self.masker = DataMasker::new_lossy(&config);
+        self.send(LogCommand::Configure {
+            max_file_size_bytes: max_file_size_bytes(&config),
+            retention_days: config.retention_days,
+        });
+        self.config = config;
+
+        // 無効化された場合は現在のファイルを閉じる
+        if !self.is_enabled() {
+            self.pending = None;
+            self.send(LogCommand::Close);
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.config.enabled && self.config.auto_save
+    }
+
+    // 新しいセッションのログを開始する。ファイルは最初のメッセージで作成される
+    pub fn start_session(&mut self, session_name: &str) {
+        self.write_pending();
+        self.session_active = true;
+        self.send(LogCommand::Start(sanitize_file_name(session_name)));
+    }
+
+    pub fn end_session(&mut self) {
+        self.write_pending();
+        self.session_active = false;
+        self.send(LogCommand::End);
+    }
+
+    pub fn current_log_path(&self) -> Option<PathBuf> {
+        self.current_path.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn write_message(&mut self, message: &TerminalMessage) {
+        if !self.is_enabled() || !self.session_active {
+            return;
+        }
+
+        match self.pending.as_mut() {
+            Some(pending) if continues_line(pending, message) => pending.content.push_str(&message.content),
+            _ => {
+                self.write_pending();
+                self.pending = Some(message.clone());
+            }
+        }
+
+        if !self.pending.as_ref().is_some_and(is_partial_line) {
+            self.write_pending();
+        }
+    }
+
+    fn write_pending(&mut self) {
+        if let Some(message) = self.pending.take() {
+            let line = format_log_line(&message, &self.masker);
+            let date = message.timestamp.with_timezone(&Local).date_naive();
+            self.send(LogCommand::Write { line, date });
+        }
+    }
+
+    // 書き込み待ちの内容をすべてファイルに書き込むまで待つ。行の途中の受信データも書き込む
+    pub fn flush(&mut self) {
+        self.write_pending();
+        let (ack_tx, ack_rx) = mpsc::channel();
+        if self.try_send(LogCommand::Flush(ack_tx)).is_ok() {
+            let _ = ack_rx.recv();
+        }
+    }
+
+    // 保存期間を過ぎたログファイルを削除する
+    pub fn cleanup_expired(&self) -> io::Result<usize> {
+        cleanup_expired(&self.log_dir, self.config.retention_days, self.current_log_path().as_deref())
+    }
+
+    pub fn list_log_files(&self) -> io::Result<Vec<LogFileInfo>> {
+        if !self.log_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.log_dir)? {
+            let path = entry?.path();
+            if !is_log_file(&path) {
+                continue;
+            }
+
+            let metadata = fs::metadata(&path)?;
+            files.push(LogFileInfo {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                path: path.to_string_lossy().to_string(),
+                size_bytes: metadata.len(),
+                modified: DateTime::<Utc>::from(metadata.modified()?),
+            });
+        }
+
+        // 新しい順
+        files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+        Ok(files)
+    }
+
+    fn send(&self, command: LogCommand) {
+        if self.try_send(command).is_err() {
+            warn!("Session log writer is not running; log entry was dropped");
+        }
+    }
+
+    fn try_send(&self, command: LogCommand) -> Result<(), mpsc::SendError<LogCommand>> {
+        self.sender
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send(command)
+    }
+}
+
+impl Drop for SessionLogger {
+    fn drop(&mut self) {
+        self.write_pending();
+        let _ = self.try_send(LogCommand::Shutdown);
+        if let Some(writer) = self.writer.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+// 現在書き込み中のログファイル
+struct SessionLogFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    date: NaiveDate,
+    written_bytes: u64,
+}
+
+// 書き込みスレッド側の状態
+struct LogWriter {
+    log_dir: PathBuf,
+    max_file_size_bytes: u64,
+    retention_days: u32,
+    session_name: Option<String>,
+    current: Option<SessionLogFile>,
+    current_path: Arc<Mutex<Option<PathBuf>>>,
+    flush_deadline: Option<Instant>, // フラッシュしていない書き込みがある場合の期限
+}
+
+impl LogWriter {
+    fn new(log_dir: PathBuf, current_path: Arc<Mutex<Option<PathBuf>>>) -> Self {
+        Self {
+            log_dir,
+            max_file_size_bytes: 0,
+            retention_days: 0,
+            session_name: None,
+            current: None,
+            current_path,
+            flush_deadline: None,
+        }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<LogCommand>) {
+        loop {
+            let command = match self.flush_deadline {
+                Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match command {
+                Ok(LogCommand::Start(session_name)) => self.start_session(session_name),
+                Ok(LogCommand::End) => {
+                    self.close_current();
+                    self.session_name = None;
+                }
+                Ok(LogCommand::Close) => self.close_current(),
+                Ok(LogCommand::Write { line, date }) => {
+                    if let Err(e) = self.write_line(&line, date) {
+                        warn!("Failed to write session log: {}", e);
+                    }
+                }
+                Ok(LogCommand::Configure { max_file_size_bytes, retention_days }) => {
+                    self.configure(max_file_size_bytes, retention_days);
+                }
+                Ok(LogCommand::Flush(ack)) => {
+                    self.flush();
+                    let _ = ack.send(());
+                }
+                Err(RecvTimeoutError::Timeout) => self.flush(),
+                Ok(LogCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                    self.close_current();
+                    break;
+                }
+            }
+        }
+    }
+
+    fn configure(&mut self, max_file_size_bytes: u64, retention_days: u32) {
+        self.max_file_size_bytes = max_file_size_bytes;
+        self.retention_days = retention_days;
+    }
+
+    fn start_session(&mut self, session_name: String) {
+        self.close_current();
+        self.session_name = Some(session_name);
+
+        if let Err(e) = cleanup_expired(&self.log_dir, self.retention_days, None) {
+            warn!("Failed to clean up expired session logs: {}", e);
+        }
+    }
+
+    fn write_line(&mut self, line: &str, date: NaiveDate) -> io::Result<()> {
+        if self.session_name.is_none() {
+            return Ok(());
+        }
+
+        // 日付が変わった場合、またはサイズ上限を超える場合はローテーション
+        let needs_rotation = match &self.current {
+            Some(current) => {
+                current.date != date
+                    || (self.max_file_size_bytes > 0
+                        && current.written_bytes > 0
+                        && current.written_bytes + line.len() as u64 > self.max_file_size_bytes)
+            }
+            None => true,
+        };
+
+        if needs_rotation {
+            self.close_current();
+            let opened = self.open_new_file(date)?;
+            self.set_current_path(Some(opened.path.clone()));
+            self.current = Some(opened);
+        }
+
+        if let Some(current) = self.current.as_mut() {
+            current.file.write_all(line.as_bytes())?;
+            current.written_bytes += line.len() as u64;
+            self.flush_deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    fn open_new_file(&self, date: NaiveDate) -> io::Result<SessionLogFile> {
+        fs::create_dir_all(&self.log_dir)?;
+
+        let session_name = self.session_name.as_deref().unwrap_or("session");
+        let opened_at = Local::now().format("%Y%m%d-%H%M%S");
+
+        // 同じ秒にローテーションした場合でも上書きしないよう連番を付ける
+        let mut path = self
+            .log_dir
+            .join(format!("{}_{}.{}", opened_at, session_name, LOG_FILE_EXTENSION));
+        let mut index = 1;
+        while path.exists() {
+            path = self.log_dir.join(format!(
+                "{}_{}_{}.{}",
+                opened_at, session_name, index, LOG_FILE_EXTENSION
+            ));
+            index += 1;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        info!("Opened session log file: {:?}", path);
+
+        Ok(SessionLogFile {
+            path,
+            file: BufWriter::new(file),
+            date,
+            written_bytes: 0,
+        })
+    }
+
+    fn flush(&mut self) {
+        if let Some(current) = self.current.as_mut() {
+            if let Err(e) = current.file.flush() {
+                warn!("Failed to flush session log {:?}: {}", current.path, e);
+            }
+        }
+        self.flush_deadline = None;
+    }
+
+    fn close_current(&mut self) {
+        self.flush();
+        if self.current.take().is_some() {
+            self.set_current_path(None);
+        }
+    }
+
+    fn set_current_path(&self, path: Option<PathBuf>) {
+        *self.current_path.lock().unwrap_or_else(|e| e.into_inner()) = path;
+    }
+}
+
+fn max_file_size_bytes(config: &LoggingConfig) -> u64 {
+    config.max_file_size_mb.saturating_mul(1024 * 1024)
+}
+
+// 保存期間を過ぎたログファイルを削除する。current は書き込み中のファイル
+fn cleanup_expired(log_dir: &Path, retention_days: u32, current: Option<&Path>) -> io::Result<usize> {
+    if retention_days == 0 || !log_dir.exists() {
+        return Ok(0);
+    }
+
+    let retention = Duration::from_secs(u64::from(retention_days) * 24 * 60 * 60);
+    let cutoff = SystemTime::now()
+        .checked_sub(retention)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut removed = 0;
+    for entry in fs::read_dir(log_dir)? {
+        let path = entry?.path();
+        if !is_log_file(&path) || Some(path.as_path()) == current {
+            continue;
+        }
+
+        let modified = fs::metadata(&path)?.modified()?;
+        if modified < cutoff {
+            match fs::remove_file(&path) {
+                Ok(_) => {
+                    debug!("Removed expired session log: {:?}", path);
+                    removed += 1;
+                }
+                Err(e) => warn!("Failed to remove expired session log {:?}: {}", path, e),
+            }
+        }
+    }
+
+    if removed > 0 {
+        info!("Removed {} expired session logs", removed);
+    }
+    Ok(removed)
+}
+
+// 1メッセージを1行で記録する。改行などの制御文字はエスケープする
+fn format_log_line(message: &TerminalMessage, masker: &DataMasker) -> String {
+    let direction = match message.direction {
+        MessageDirection::Sent => "送信",
+        MessageDirection::Received => "受信",
+        MessageDirection::System => "システム",
+    };
+
+    format!(
+        "[{}] {}: {}\n",
+        message.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S%.3f"),
+        direction,
+        masker.mask(&message.content).escape_debug()
+    )
+}
+
+// ファイル名に使えない文字を置き換える
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    if sanitized.is_empty() {
+        "session".to_string()
+    } else {
+        sanitized
+    }
+}
+
+fn is_log_file(path: &Path) -> bool {
+    path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some(LOG_FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_logger(dir: &Path) -> SessionLogger {
+        SessionLogger::new(dir.to_path_buf(), LoggingConfig::default())
+    }
+
+    fn create_test_writer(dir: &Path) -> LogWriter {
+        let mut writer = LogWriter::new(dir.to_path_buf(), Arc::new(Mutex::new(None)));
+        writer.start_session("device".to_string());
+        writer
+    }
+
+    fn create_test_message(content: &str, direction: MessageDirection) -> TerminalMessage {
+        match direction {
+            MessageDirection::Sent => TerminalMessage::new_sent(content.to_string(), "UTF-8".to_string()),
+            MessageDirection::Received => TerminalMessage::new_received(content.to_string(), "UTF-8".to_string()),
+            MessageDirection::System => TerminalMessage::new_system(content.to_string()),
+        }
+    }
+
+    fn read_logs(dir: &Path) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| is_log_file(path))
+            .collect();
+        paths.sort();
+        paths.iter().map(|path| fs::read_to_string(path).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_write_message_creates_session_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("My Device");
+        logger.write_message(&create_test_message("AT", MessageDirection::Sent));
+        logger.write_message(&create_test_message("OK\r\n", MessageDirection::Received));
+        logger.flush();
+
+        let path = logger.current_log_path().unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().ends_with("_My_Device.log"));
+
+        // 書き込み中でも内容を読み取れる
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("送信: AT"));
+        assert!(lines[1].ends_with("受信: OK\\r\\n"));
+    }
+
+    #[test]
+    fn test_writer_flushes_when_idle() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("device");
+        logger.write_message(&create_test_message("data\r\n", MessageDirection::Received));
+
+        // flush を呼ばなくても、一定時間内に読めるようになる
+        std::thread::sleep(FLUSH_INTERVAL * 3);
+        let logs = read_logs(dir.path());
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].ends_with("受信: data\\r\\n\n"));
+    }
+
+    #[test]
+    fn test_write_message_without_session_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.write_message(&create_test_message("ignored", MessageDirection::Received));
+        logger.flush();
+
+        assert!(read_logs(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_disabled_logging_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+        logger.update_config(LoggingConfig { enabled: false, ..LoggingConfig::default() });
+
+        logger.start_session("device");
+        logger.write_message(&create_test_message("data", MessageDirection::Received));
+        logger.flush();
+
+        assert!(read_logs(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_end_session_closes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("device");
+        logger.write_message(&create_test_message("data", MessageDirection::Received));
+        logger.end_session();
+        logger.flush();
+
+        assert!(logger.current_log_path().is_none());
+        assert_eq!(read_logs(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = create_test_writer(dir.path());
+        writer.configure(80, 0);
+
+        let masker = DataMasker::new_lossy(&LoggingConfig::default());
+        let date = Local::now().date_naive();
+        for i in 0..4 {
+            let message = create_test_message(&format!("line {}", i), MessageDirection::Received);
+            writer.write_line(&format_log_line(&message, &masker), date).unwrap();
+        }
+        writer.close_current();
+
+        let logs = read_logs(dir.path());
+        assert!(logs.len() > 1);
+        assert_eq!(logs.iter().map(|log| log.lines().count()).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_rotation_by_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = create_test_writer(dir.path());
+
+        let first_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let second_date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        writer.write_line("day one\n", first_date).unwrap();
+        let first_path = writer.current.as_ref().unwrap().path.clone();
+        writer.write_line("day two\n", second_date).unwrap();
+        let second_path = writer.current.as_ref().unwrap().path.clone();
+        writer.close_current();
+
+        assert_ne!(first_path, second_path);
+        assert!(fs::read_to_string(first_path).unwrap().contains("day one"));
+        assert!(fs::read_to_string(second_path).unwrap().contains("day two"));
+    }
+
+    #[test]
+    fn test_cleanup_expired_removes_old_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = create_test_logger(dir.path());
+
+        let old_path = dir.path().join("old_session.log");
+        let recent_path = dir.path().join("recent_session.log");
+        let other_path = dir.path().join("notes.txt");
+        for path in [&old_path, &recent_path, &other_path] {
+            fs::write(path, "data").unwrap();
+        }
+
+        let old_time = SystemTime::now() - Duration::from_secs(60 * 24 * 60 * 60);
+        File::options().write(true).open(&old_path).unwrap().set_modified(old_time).unwrap();
+        File::options().write(true).open(&other_path).unwrap().set_modified(old_time).unwrap();
+
+        let removed = logger.cleanup_expired().unwrap();
+
+        assert_eq!(removed, 1);
+        assert!(!old_path.exists());
+        assert!(recent_path.exists());
+        assert!(other_path.exists());
+    }
+
+    #[test]
+    fn test_list_log_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("device");
+        logger.write_message(&create_test_message("data", MessageDirection::Received));
+        logger.flush();
+
+        let files = logger.list_log_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].name.ends_with("_device.log"));
+        assert!(files[0].size_bytes > 0);
+    }
+
+    #[test]
+    fn test_write_message_masks_sensitive_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("device");
+        logger.write_message(&create_test_message("wifi password=hunter2", MessageDirection::Sent));
+        logger.flush();
+
+        let logs = read_logs(dir.path());
+        assert!(logs[0].contains("wifi password=********"));
+        assert!(!logs[0].contains("hunter2"));
+    }
+
+    #[test]
+    fn test_split_secret_is_masked() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = create_test_logger(dir.path());
+
+        logger.start_session("device");
+        for chunk in ["psk=hun", "ter2\r\n", "login: "] {
+            logger.write_message(&create_test_message(chunk, MessageDirection::Received));
+        }
+        logger.write_message(&create_test_message("admin", MessageDirection::Sent));
+        logger.flush();
+
+        let logs = read_logs(dir.path());
+        let lines: Vec<&str> = logs[0].lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("受信: psk=********\\r\\n"));
+        assert!(lines[1].ends_with("受信: login: "));
+        assert!(lines[2].ends_with("送信: admin"));
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("COM3 @ 115200"), "COM3___115200");
+        assert_eq!(sanitize_file_name("../etc/passwd"), "___etc_passwd");
+        assert_eq!(sanitize_file_name("  "), "session");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,606 @@
+use super::macros::MacroTarget;
+use crate::models::{BridgeClient, BridgeFraming, ClientRole, ControlLine, DataBits, Parity, SerialConfig, StopBits, TcpBridgeConfig};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+// telnet のコマンドとオプション
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const OPT_BINARY: u8 = 0;
+const OPT_ECHO: u8 = 1;
+const OPT_SUPPRESS_GO_AHEAD: u8 = 3;
+const OPT_COM_PORT: u8 = 44;
+
+// COM-PORT-OPTION のサブコマンド（RFC 2217）。サーバーからの応答は +100 した値を使う
+const CPO_SIGNATURE: u8 = 0;
+const CPO_SET_BAUDRATE: u8 = 1;
+const CPO_SET_DATASIZE: u8 = 2;
+const CPO_SET_PARITY: u8 = 3;
+const CPO_SET_STOPSIZE: u8 = 4;
+const CPO_SET_CONTROL: u8 = 5;
+const CPO_SET_LINESTATE_MASK: u8 = 10;
+const CPO_SET_MODEMSTATE_MASK: u8 = 11;
+const CPO_PURGE_DATA: u8 = 12;
+const CPO_SERVER_OFFSET: u8 = 100;
+const SIGNATURE: &str = "serialterm";
+
+// 壊れたサブネゴシエーションでメモリを使い切らないための上限
+const MAX_SUBNEGOTIATION_LEN: usize = 64;
+
+// 接続中のシリアルポートを TCP で公開する。各クライアントには受信データを複製して送り、
+// 読み書き可能なクライアントから届いたデータはデバイスへ送信する（送信メッセージとして記録される）
+pub struct TcpBridge {
+    config: TcpBridgeConfig,
+    address: SocketAddr,
+    registry: Arc<ClientRegistry>,
+    shutdown: watch::Sender<bool>,
+    listener: JoinHandle<()>,
+}
+
+impl TcpBridge {
+    // port に 0 を指定すると空いているポートを使う。serial は RFC 2217 の問い合わせへの応答に使う
+    pub async fn start<T>(
+        config: TcpBridgeConfig,
+        serial: Option<SerialConfig>,
+        target: T,
+        raw: broadcast::Receiver<Vec<u8>>,
+    ) -> io::Result<Self>
+    where
+        T: MacroTarget + Clone + Sync + 'static,
+    {
+        let bind_address: IpAddr = config.bind_address.parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid bind address: {}", config.bind_address))
+        })?;
+        let listener = TcpListener::bind((bind_address, config.port)).await?;
+        let address = listener.local_addr()?;
+        let registry = Arc::new(ClientRegistry::new());
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let listener = {
+            let registry = registry.clone();
+            let framing = config.framing;
+            let default_role = config.default_role;
+            tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, peer)) => {
+                            let client = BridgeClient::new(peer.to_string(), default_role);
+                            info!("Bridge client {} connected from {} ({:?})", client.id, peer, default_role);
+                            let session = ClientSession {
+                                id: client.id.clone(),
+                                framing,
+                                serial: serial.clone(),
+                                registry: registry.clone(),
+                                target: target.clone(),
+                                parser: TelnetParser::default(),
+                            };
+                            registry.add(client);
+                            tokio::spawn(session.run(stream, raw.resubscribe(), shutdown_rx.clone()));
+                        }
+                        Err(e) => warn!("Failed to accept bridge client: {}", e),
+                    }
+                }
+            })
+        };
+
+        info!("TCP bridge listening on {} ({:?})", address, config.framing);
+        Ok(Self {
+            config,
+            address,
+            registry,
+            shutdown,
+            listener,
+        })
+    }
+
+    pub fn config(&self) -> &TcpBridgeConfig {
+        &self.config
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn clients(&self) -> Vec<BridgeClient> {
+        self.registry.list()
+    }
+
+    // 次にクライアントからデータが届いた時点から新しい権限を適用する
+    pub fn set_client_role(&self, client_id: &str, role: ClientRole) -> Result<BridgeClient, String> {
+        self.registry
+            .set_role(client_id, role)
+            .ok_or_else(|| format!("Bridge client not found: {}", client_id))
+    }
+
+    // クライアントの接続・切断・権限の変更を通知する（転送量の変化は通知しない）
+    pub fn watch_clients(&self) -> watch::Receiver<Vec<BridgeClient>> {
+        self.registry.changes.subscribe()
+    }
+
+    // 待ち受けを止め、接続中のクライアントも切断する
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for TcpBridge {
+    fn drop(&mut self) {
+        self.listener.abort();
+        let _ = self.shutdown.send(true);
+        info!("TCP bridge on {} stopped", self.address);
+    }
+}
+
+// 接続中のクライアント一覧
+struct ClientRegistry {
+    clients: Mutex<Vec<BridgeClient>>,
+    changes: watch::Sender<Vec<BridgeClient>>,
+}
+
+impl ClientRegistry {
+    fn new() -> Self {
+        Self {
+            clients: Mutex::new(Vec::new()),
+            changes: watch::channel(Vec::new()).0,
+        }
+    }
+
+    fn list(&self) -> Vec<BridgeClient> {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn add(&self, client: BridgeClient) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.push(client);
+        self.changes.send_replace(clients.clone());
+    }
+
+    fn remove(&self, client_id: &str) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|client| client.id != client_id);
+        self.changes.send_replace(clients.clone());
+    }
+
+    fn role(&self, client_id: &str) -> Option<ClientRole> {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.iter().find(|client| client.id == client_id).map(|client| client.role)
+    }
+
+    fn set_role(&self, client_id: &str, role: ClientRole) -> Option<BridgeClient> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let client = clients.iter_mut().find(|client| client.id == client_id)?;
+        client.role = role;
+        let updated = client.clone();
+        self.changes.send_replace(clients.clone());
+        Some(updated)
+    }
+
+    fn add_traffic(&self, client_id: &str, from_client: usize, to_client: usize) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = clients.iter_mut().find(|client| client.id == client_id) {
+            client.bytes_from_client += from_client as u64;
+            client.bytes_to_client += to_client as u64;
+        }
+    }
+}
+
+// クライアントごとの状態
+struct ClientSession<T> {
+    id: String,
+    framing: BridgeFraming,
+    serial: Option<SerialConfig>,
+    registry: Arc<ClientRegistry>,
+    target: T,
+    parser: TelnetParser,
+}
+
+impl<T: MacroTarget> ClientSession<T> {
+    async fn run(
+        mut self,
+        mut stream: TcpStream,
+        mut raw: broadcast::Receiver<Vec<u8>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        if self.framing != BridgeFraming::Raw && stream.write_all(&initial_negotiation(self.framing)).await.is_err() {
+            self.registry.remove(&self.id);
+            return;
+        }
+
+        let mut buffer = [0u8; 1024];
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => break,
+                received = raw.recv() => match received {
+                    Ok(data) => {
+                        let framed = match self.framing {
+                            BridgeFraming::Raw => data.clone(),
+                            BridgeFraming::Telnet | BridgeFraming::Rfc2217 => escape_iac(&data),
+                        };
+                        if stream.write_all(&framed).await.is_err() {
+                            break;
+                        }
+                        self.registry.add_traffic(&self.id, 0, data.len());
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Bridge client {} skipped {} chunks of received data", self.id, skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                read = stream.read(&mut buffer) => match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let replies = self.handle_input(&buffer[..n]).await;
+                        if !replies.is_empty() && stream.write_all(&replies).await.is_err() {
+                            break;
+                        }
+                    }
+                },
+            }
+        }
+
+        self.registry.remove(&self.id);
+        info!("Bridge client {} disconnected", self.id);
+    }
+
+    // クライアントから届いたデータを処理し、クライアントへ返す telnet の応答を返す
+    async fn handle_input(&mut self, input: &[u8]) -> Vec<u8> {
+        let (data, replies, control_lines) = match self.framing {
+            BridgeFraming::Raw => (input.to_vec(), Vec::new(), Vec::new()),
+            BridgeFraming::Telnet | BridgeFraming::Rfc2217 => {
+                let input = self.parser.feed(input);
+                let mut replies: Vec<u8> = Vec::new();
+                let mut control_lines = Vec::new();
+                for (command, option) in input.negotiations {
+                    if let Some(reply) = negotiation_reply(self.framing, command, option) {
+                        replies.extend(reply);
+                    }
+                }
+                if self.framing == BridgeFraming::Rfc2217 {
+                    for subnegotiation in input.subnegotiations {
+                        if let Some(request) = com_port_request(&subnegotiation, self.serial.as_ref()) {
+                            replies.extend(request.reply);
+                            control_lines.extend(request.control_line);
+                        }
+                    }
+                }
+                (input.data, replies, control_lines)
+            }
+        };
+
+        let read_write = self.registry.role(&self.id) == Some(ClientRole::ReadWrite);
+        if !read_write {
+            if !data.is_empty() || !control_lines.is_empty() {
+                debug!("Ignored input from read-only bridge client {}", self.id);
+            }
+            return replies;
+        }
+
+        for (line, level) in control_lines {
+            if let Err(e) = self.target.set_control_line(line, level).await {
+                warn!("Bridge client {} failed to set {:?}: {}", self.id, line, e);
+            }
+        }
+        if !data.is_empty() {
+            let content = String::from_utf8_lossy(&data).to_string();
+            match self.target.send(&data, content, "UTF-8").await {
+                Ok(()) => self.registry.add_traffic(&self.id, data.len(), 0),
+                Err(e) => warn!("Bridge client {} failed to send data: {}", self.id, e),
+            }
+        }
+        replies
+    }
+}
+
+// telnet の受信データから取り出したもの
+#[derive(Debug, Default, PartialEq)]
+struct TelnetInput {
+    data: Vec<u8>,
+    negotiations: Vec<(u8, u8)>, // (DO/DONT/WILL/WONT, オプション)
+    subnegotiations: Vec<Vec<u8>>, // IAC SB 〜 IAC SE の中身
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ParseState {
+    #[default]
+    Data,
+    Iac,
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+// コマンドが TCP のパケットをまたいでも解釈できるよう、状態を持ち越す
+#[derive(Debug, Default)]
+struct TelnetParser {
+    state: ParseState,
+    subnegotiation: Vec<u8>,
+}
+
+impl TelnetParser {
+    fn feed(&mut self, input: &[u8]) -> TelnetInput {
+        let mut parsed = TelnetInput::default();
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (ParseState::Data, IAC) => ParseState::Iac,
+                (ParseState::Data, _) => {
+                    parsed.data.push(byte);
+                    ParseState::Data
+                }
+                (ParseState::Iac, IAC) => {
+                    parsed.data.push(IAC);
+                    ParseState::Data
+                }
+                (ParseState::Iac, DO | DONT | WILL | WONT) => ParseState::Negotiation(byte),
+                (ParseState::Iac, SB) => {
+                    self.subnegotiation.clear();
+                    ParseState::Subnegotiation
+                }
+                // NOP や AYT などは無視する
+                (ParseState::Iac, _) => ParseState::Data,
+                (ParseState::Negotiation(command), _) => {
+                    parsed.negotiations.push((command, byte));
+                    ParseState::Data
+                }
+                (ParseState::Subnegotiation, IAC) => ParseState::SubnegotiationIac,
+                (ParseState::Subnegotiation, _) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION_LEN {
+                        self.subnegotiation.push(byte);
+                    }
+                    ParseState::Subnegotiation
+                }
+                (ParseState::SubnegotiationIac, SE) => {
+                    parsed.subnegotiations.push(std::mem::take(&mut self.subnegotiation));
+                    ParseState::Data
+                }
+                (ParseState::SubnegotiationIac, IAC) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION_LEN {
+                        self.subnegotiation.push(IAC);
+                    }
+                    ParseState::Subnegotiation
+                }
+                (ParseState::SubnegotiationIac, _) => ParseState::Subnegotiation,
+            };
+        }
+        parsed
+    }
+}
+
+// サーバーから有効にするオプション。ECHO はデバイス側のエコーに任せるため
+fn offered_will(_framing: BridgeFraming) -> &'static [u8] {
+    &[OPT_BINARY, OPT_ECHO, OPT_SUPPRESS_GO_AHEAD]
+}
+
+// クライアントに有効にしてほしいオプション
+fn offered_do(framing: BridgeFraming) -> &'static [u8] {
+    match framing {
+        BridgeFraming::Rfc2217 => &[OPT_BINARY, OPT_COM_PORT],
+        BridgeFraming::Raw | BridgeFraming::Telnet => &[OPT_BINARY],
+    }
+}
+
+fn initial_negotiation(framing: BridgeFraming) -> Vec<u8> {
+    let mut negotiation = Vec::new();
+    for &option in offered_will(framing) {
+        negotiation.extend([IAC, WILL, option]);
+    }
+    for &option in offered_do(framing) {
+        negotiation.extend([IAC, DO, option]);
+    }
+    negotiation
+}
+
+// こちらから提案していないオプションは断る。提案済みのものへの応答や拒否には返信しない
+fn negotiation_reply(framing: BridgeFraming, command: u8, option: u8) -> Option<[u8; 3]> {
+    match command {
+        DO if !offered_will(framing).contains(&option) => Some([IAC, WONT, option]),
+        WILL if !offered_do(framing).contains(&option) => Some([IAC, DONT, option]),
+        _ => None,
+    }
+}
+
+fn escape_iac(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+// RFC 2217 の要求への応答と、要求された DTR/RTS の変更
+#[derive(Debug, PartialEq)]
+struct ComPortRequest {
+    reply: Vec<u8>,
+    control_line: Option<(ControlLine, bool)>,
+}
+
+// 通信設定の変更要求には応じず、接続中の設定を返す（クライアントは実際の設定を知ることができる）
+fn com_port_request(subnegotiation: &[u8], serial: Option<&SerialConfig>) -> Option<ComPortRequest> {
+    let (&option, rest) = subnegotiation.split_first()?;
+    let (&command, value) = rest.split_first()?;
+    if option != OPT_COM_PORT {
+        return None;
+    }
+
+    let mut control_line = None;
+    let reply_value = match command {
+        CPO_SIGNATURE => SIGNATURE.as_bytes().to_vec(),
+        CPO_SET_BAUDRATE => match serial {
+            Some(serial) => {
+                if value != [0; 4] && value != serial.baud_rate.to_be_bytes() {
+                    debug!("Ignored RFC 2217 baud rate change request");
+                }
+                serial.baud_rate.to_be_bytes().to_vec()
+            }
+            None => value.to_vec(),
+        },
+        CPO_SET_DATASIZE => match serial {
+            Some(serial) => vec![match serial.data_bits {
+                DataBits::Five => 5,
+                DataBits::Six => 6,
+                DataBits::Seven => 7,
+                DataBits::Eight => 8,
+            }],
+            None => value.to_vec(),
+        },
+        CPO_SET_PARITY => match serial {
+            Some(serial) => vec![match serial.parity {
+                Parity::None => 1,
+                Parity::Odd => 2,
+                Parity::Even => 3,
+                Parity::Mark => 4,
+                Parity::Space => 5,
+            }],
+            None => value.to_vec(),
+        },
+        CPO_SET_STOPSIZE => match serial {
+            Some(serial) => vec![match serial.stop_bits {
+                StopBits::One => 1,
+                StopBits::Two => 2,
+                StopBits::OnePointFive => 3,
+            }],
+            None => value.to_vec(),
+        },
+        CPO_SET_CONTROL => {
+            control_line = match value.first() {
+                Some(8) => Some((ControlLine::Dtr, true)),
+                Some(9) => Some((ControlLine::Dtr, false)),
+                Some(11) => Some((ControlLine::Rts, true)),
+                Some(12) => Some((ControlLine::Rts, false)),
+                _ => None,
+            };
+            value.to_vec()
+        }
+        CPO_SET_LINESTATE_MASK | CPO_SET_MODEMSTATE_MASK | CPO_PURGE_DATA => value.to_vec(),
+        _ => return None,
+    };
+
+    let mut reply = vec![IAC, SB, OPT_COM_PORT, command + CPO_SERVER_OFFSET];
+    reply.extend(escape_iac(&reply_value));
+    reply.extend([IAC, SE]);
+    Some(ComPortRequest { reply, control_line })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FlowControl;
+    use crate::services::macros::test_support::RecordingTarget;
+    use std::time::Duration;
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition was not met in time");
+    }
+
+    #[tokio::test]
+    async fn test_bridge_roles_and_forwarding() {
+        let target = RecordingTarget::default();
+        let (raw_tx, raw_rx) = broadcast::channel(16);
+        let config = TcpBridgeConfig {
+            bind_address: "127.0.0.1".to_string(),
+            port: 0,
+            framing: BridgeFraming::Raw,
+            default_role: ClientRole::ReadOnly,
+        };
+        let bridge = TcpBridge::start(config, None, target.clone(), raw_rx).await.unwrap();
+
+        let mut viewer = TcpStream::connect(bridge.address()).await.unwrap();
+        let mut operator = TcpStream::connect(bridge.address()).await.unwrap();
+        wait_until(|| bridge.clients().len() == 2).await;
+
+        // デバイスからの受信データはすべてのクライアントへ届く
+        raw_tx.send(b"boot ok\r\n".to_vec()).unwrap();
+        for client in [&mut viewer, &mut operator] {
+            let mut received = [0u8; 9];
+            client.read_exact(&mut received).await.unwrap();
+            assert_eq!(&received, b"boot ok\r\n");
+        }
+
+        // 読み取り専用のクライアントから送ったデータは捨てる
+        viewer.write_all(b"reboot\r").await.unwrap();
+        let operator_id = bridge.clients()[1].id.clone();
+        let updated = bridge.set_client_role(&operator_id, ClientRole::ReadWrite).unwrap();
+        assert_eq!(updated.role, ClientRole::ReadWrite);
+        operator.write_all(b"status\r").await.unwrap();
+        wait_until(|| !target.sent().is_empty()).await;
+        assert_eq!(target.sent_bytes(), b"status\r");
+        assert_eq!(bridge.clients()[1].bytes_from_client, 7);
+
+        assert!(bridge.set_client_role("unknown", ClientRole::ReadWrite).is_err());
+
+        drop(viewer);
+        wait_until(|| bridge.clients().len() == 1).await;
+        assert_eq!(*bridge.watch_clients().borrow(), bridge.clients());
+    }
+
+    #[test]
+    fn test_telnet_parser_across_packets() {
+        let mut parser = TelnetParser::default();
+        let first = parser.feed(&[b'a', IAC, IAC, b'b', IAC, DO]);
+        assert_eq!(first.data, vec![b'a', IAC, b'b']);
+        assert!(first.negotiations.is_empty());
+
+        let second = parser.feed(&[OPT_ECHO, IAC, SB, OPT_COM_PORT, CPO_SET_CONTROL, IAC]);
+        assert_eq!(second.negotiations, vec![(DO, OPT_ECHO)]);
+        assert!(second.subnegotiations.is_empty());
+
+        let third = parser.feed(&[SE, b'c']);
+        assert_eq!(third.subnegotiations, vec![vec![OPT_COM_PORT, CPO_SET_CONTROL]]);
+        assert_eq!(third.data, vec![b'c']);
+
+        assert_eq!(negotiation_reply(BridgeFraming::Telnet, DO, OPT_ECHO), None);
+        assert_eq!(negotiation_reply(BridgeFraming::Telnet, WILL, OPT_COM_PORT), Some([IAC, DONT, OPT_COM_PORT]));
+        assert_eq!(negotiation_reply(BridgeFraming::Rfc2217, WILL, OPT_COM_PORT), None);
+        assert_eq!(escape_iac(&[1, IAC, 2]), vec![1, IAC, IAC, 2]);
+    }
+
+    #[test]
+    fn test_com_port_requests() {
+        let serial = SerialConfig {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+        };
+
+        // ボーレートの変更要求には接続中の値を返す
+        let request = com_port_request(&[OPT_COM_PORT, CPO_SET_BAUDRATE, 0, 0, 0x25, 0x80], Some(&serial)).unwrap();
+        let mut expected = vec![IAC, SB, OPT_COM_PORT, CPO_SET_BAUDRATE + CPO_SERVER_OFFSET];
+        expected.extend(115200u32.to_be_bytes());
+        expected.extend([IAC, SE]);
+        assert_eq!(request.reply, expected);
+        assert_eq!(request.control_line, None);
+
+        let request = com_port_request(&[OPT_COM_PORT, CPO_SET_CONTROL, 8], Some(&serial)).unwrap();
+        assert_eq!(request.control_line, Some((ControlLine::Dtr, true)));
+        assert_eq!(request.reply, vec![IAC, SB, OPT_COM_PORT, CPO_SET_CONTROL + CPO_SERVER_OFFSET, 8, IAC, SE]);
+
+        let request = com_port_request(&[OPT_COM_PORT, CPO_SET_PARITY, 0], Some(&serial)).unwrap();
+        assert_eq!(request.reply[4], 1);
+
+        assert!(com_port_request(&[OPT_ECHO, CPO_SET_CONTROL, 8], Some(&serial)).is_none());
+        assert!(com_port_request(&[OPT_COM_PORT], Some(&serial)).is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,444 @@
+use super::macros::{MacroTarget, ReceiveBuffer};
+use super::DataMasker;
+use crate::models::{LineEnding, MessageDirection, TerminalMessage, TestStep, TestSuite};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    Skipped, // 前のステップが失敗したため実行していない
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StepReport {
+    pub index: usize,
+    pub name: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    pub message: Option<String>, // 失敗理由
+}
+
+// テストスイートの実行結果。transcript には送受信したデータを時系列で記録する
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TestReport {
+    pub suite: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub passed: bool,
+    pub steps: Vec<StepReport>,
+    pub captures: BTreeMap<String, String>,
+    pub transcript: Vec<TerminalMessage>,
+}
+
+impl TestReport {
+    pub fn count(&self, status: StepStatus) -> usize {
+        self.steps.iter().filter(|step| step.status == status).count()
+    }
+
+    // 送受信記録・失敗理由・キャプチャした値の機密情報をマスクする。表示や保存の前に呼ぶ
+    pub fn mask(&mut self, masker: &DataMasker) {
+        self.transcript = masker.mask_transcript(std::mem::take(&mut self.transcript));
+        for step in &mut self.steps {
+            if let Some(message) = &mut step.message {
+                *message = masker.mask(message).into_owned();
+            }
+        }
+        for value in self.captures.values_mut() {
+            *value = masker.mask(value).into_owned();
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize report: {}", e))
+    }
+
+    // 各ステップを testcase とし、transcript を system-out に出力する
+    pub fn to_junit_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let tests = self.steps.len();
+        let failures = self.count(StepStatus::Failed);
+        let skipped = self.count(StepStatus::Skipped);
+        let time = seconds(self.duration_ms);
+
+        xml.push_str(&format!(
+            "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+            escape_xml(&self.suite),
+            tests,
+            failures,
+            skipped,
+            time
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\" timestamp=\"{}\">\n",
+            escape_xml(&self.suite),
+            tests,
+            failures,
+            skipped,
+            time,
+            self.started_at.format("%Y-%m-%dT%H:%M:%S")
+        ));
+
+        for step in &self.steps {
+            let open = format!(
+                "    <testcase name=\"{}. {}\" classname=\"{}\" time=\"{}\"",
+                step.index + 1,
+                escape_xml(&step.name),
+                escape_xml(&self.suite),
+                seconds(step.duration_ms)
+            );
+            match step.status {
+                StepStatus::Passed => xml.push_str(&format!("{}/>\n", open)),
+                StepStatus::Failed => {
+                    let message = escape_xml(step.message.as_deref().unwrap_or_default());
+                    xml.push_str(&format!(
+                        "{}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        open, message, message
+                    ));
+                }
+                StepStatus::Skipped => xml.push_str(&format!("{}>\n      <skipped/>\n    </testcase>\n", open)),
+            }
+        }
+
+        xml.push_str("    <system-out>");
+        for message in &self.transcript {
+            let marker = match message.direction {
+                MessageDirection::Sent => ">>",
+                MessageDirection::Received => "<<",
+                MessageDirection::System => "--",
+            };
+            xml.push_str(&escape_xml(&format!(
+                "[{}] {} {}\n",
+                message.timestamp.format("%H:%M:%S%.3f"),
+                marker,
+                message.content
+            )));
+        }
+        xml.push_str("</system-out>\n");
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+// XML 1.0 で使えない制御文字は \xNN の形で残す
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 実行中の受信データとキャプチャした値
+struct RunState {
+    received: broadcast::Receiver<TerminalMessage>,
+    buffer: ReceiveBuffer,
+    captures: BTreeMap<String, String>,
+    transcript: Vec<TerminalMessage>,
+}
+
+impl RunState {
+    fn record_received(&mut self, message: TerminalMessage) {
+        self.buffer.push(&message);
+        self.transcript.push(message);
+    }
+
+    // 届いている受信データを取り込み、transcript の順序を実際の送受信に合わせる
+    fn drain(&mut self) {
+        loop {
+            match self.received.try_recv() {
+                Ok(message) => self.record_received(message),
+                Err(TryRecvError::Lagged(skipped)) => {
+                    warn!("Test runner receive buffer lagged, {} messages skipped", skipped);
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => return,
+            }
+        }
+    }
+
+    // ${name} をキャプチャした値に置き換える
+    fn substitute(&self, text: &str) -> String {
+        self.captures
+            .iter()
+            .fold(text.to_string(), |text, (name, value)| text.replace(&format!("${{{}}}", name), value))
+    }
+}
+
+// テストスイートを実行する。received は実行前に購読しておいた受信メッセージ。
+// ステップが失敗した時点で残りはスキップする
+pub async fn run_test_suite<T: MacroTarget>(
+    suite: &TestSuite,
+    target: &mut T,
+    received: broadcast::Receiver<TerminalMessage>,
+    line_ending: LineEnding,
+) -> TestReport {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let mut state = RunState {
+        received,
+        buffer: ReceiveBuffer::default(),
+        captures: BTreeMap::new(),
+        transcript: Vec::new(),
+    };
+    let mut steps = Vec::with_capacity(suite.steps.len());
+    let mut failed = false;
+
+    info!("Running test suite '{}'", suite.name);
+    for (index, step) in suite.steps.iter().enumerate() {
+        let name = step.display_name();
+        if failed {
+            steps.push(StepReport {
+                index,
+                name,
+                status: StepStatus::Skipped,
+                duration_ms: 0,
+                message: None,
+            });
+            continue;
+        }
+
+        let step_started = Instant::now();
+        let result = execute_step(step, suite, target, &mut state, &line_ending).await;
+        let duration_ms = step_started.elapsed().as_millis() as u64;
+        debug!("Test step {} '{}': {:?}", index + 1, name, result);
+
+        let (status, message) = match result {
+            Ok(()) => (StepStatus::Passed, None),
+            Err(reason) => {
+                failed = true;
+                (StepStatus::Failed, Some(reason))
+            }
+        };
+        steps.push(StepReport {
+            index,
+            name,
+            status,
+            duration_ms,
+            message,
+        });
+    }
+    state.drain();
+
+    TestReport {
+        suite: suite.name.clone(),
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        passed: !failed,
+        steps,
+        captures: state.captures,
+        transcript: state.transcript,
+    }
+}
+
+async fn execute_step<T: MacroTarget>(
+    step: &TestStep,
+    suite: &TestSuite,
+    target: &mut T,
+    state: &mut RunState,
+    line_ending: &LineEnding,
+) -> Result<(), String> {
+    match step {
+        TestStep::Send { text, append_line_ending, .. } => {
+            state.drain();
+            let mut content = state.substitute(text);
+            if *append_line_ending {
+                content.push_str(line_ending.to_string());
+            }
+            target.send(content.as_bytes(), content.clone(), "UTF-8").await?;
+            state.transcript.push(TerminalMessage::new_sent(content, "UTF-8".to_string()));
+            Ok(())
+        }
+        TestStep::Expect { pattern, timeout_ms, .. } => {
+            let regex = Regex::new(pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+            let timeout = timeout_ms.unwrap_or(suite.default_timeout_ms);
+            let deadline = Instant::now() + Duration::from_millis(timeout);
+
+            loop {
+                if let Some(groups) = state.buffer.take_match(&regex) {
+                    for (name, value) in regex.capture_names().zip(groups) {
+                        if let Some(name) = name {
+                            state.captures.insert(name.to_string(), value);
+                        }
+                    }
+                    return Ok(());
+                }
+
+                match tokio::time::timeout_at(deadline, state.received.recv()).await {
+                    Err(_) => return Err(format!("Timed out after {} ms waiting for /{}/", timeout, pattern)),
+                    Ok(Ok(message)) => state.record_received(message),
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        warn!("Test runner receive buffer lagged, {} messages skipped", skipped);
+                    }
+                    Ok(Err(RecvError::Closed)) => return Err("Connection closed".to_string()),
+                }
+            }
+        }
+        TestStep::Assert { value, min, max, .. } => {
+            let text = state
+                .captures
+                .get(value)
+                .ok_or_else(|| format!("'{}' was not captured", value))?;
+            let number: f64 = text
+                .trim()
+                .parse()
+                .map_err(|_| format!("'{}' is not a number: {}", value, text))?;
+            if min.map(|min| number < min).unwrap_or(false) || max.map(|max| number > max).unwrap_or(false) {
+                let range = format!(
+                    "{}..{}",
+                    min.map(|min| min.to_string()).unwrap_or_default(),
+                    max.map(|max| max.to_string()).unwrap_or_default()
+                );
+                return Err(format!("{} = {} is out of range {}", value, number, range));
+            }
+            Ok(())
+        }
+        TestStep::Wait { ms, .. } => {
+            tokio::time::sleep(Duration::from_millis(*ms)).await;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::communication::test_support::{create_test_echo_config, spawn_echo_server};
+    use crate::communication::{message_pipeline, ConnectionManager, PipelineConfig};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    async fn connect_to_echo_server() -> Arc<Mutex<ConnectionManager>> {
+        let port = spawn_echo_server().await;
+        let mut manager = ConnectionManager::new();
+        let (tx, _rx) = message_pipeline(PipelineConfig::default());
+        manager.connect(create_test_echo_config(port), tx).await.unwrap();
+        Arc::new(Mutex::new(manager))
+    }
+
+    fn parse_suite(max_voltage: f64) -> TestSuite {
+        TestSuite::parse(&format!(
+            r#"
+name: echo board
+default_timeout_ms: 1000
+steps:
+  - type: send
+    text: "FW 1.4 V=3.31"
+  - type: expect
+    pattern: 'FW (?P<version>\d+\.\d+)'
+  - type: expect
+    pattern: 'V=(?P<voltage>[0-9.]+)'
+  - type: assert
+    name: supply voltage
+    value: voltage
+    min: 3.2
+    max: {}
+  - type: send
+    text: "version ${{version}}"
+"#,
+            max_voltage
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_suite_passes_against_echo_server() {
+        let mut target = connect_to_echo_server().await;
+        let received = target.lock().await.subscribe();
+
+        let report = run_test_suite(&parse_suite(3.4), &mut target, received, LineEnding::Lf).await;
+        target.lock().await.disconnect().await.unwrap();
+
+        assert!(report.passed, "{:?}", report.steps);
+        assert_eq!(report.count(StepStatus::Passed), 5);
+        assert_eq!(report.captures.get("version").map(String::as_str), Some("1.4"));
+        assert_eq!(report.captures.get("voltage").map(String::as_str), Some("3.31"));
+        assert_eq!(report.transcript[0].direction, MessageDirection::Sent);
+        assert_eq!(report.transcript[0].content, "FW 1.4 V=3.31\n");
+        assert!(report
+            .transcript
+            .iter()
+            .any(|message| message.direction == MessageDirection::Sent && message.content == "version 1.4\n"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_assert_skips_remaining_steps() {
+        let mut target = connect_to_echo_server().await;
+        let received = target.lock().await.subscribe();
+
+        let report = run_test_suite(&parse_suite(3.3), &mut target, received, LineEnding::Lf).await;
+        target.lock().await.disconnect().await.unwrap();
+
+        assert!(!report.passed);
+        let statuses: Vec<StepStatus> = report.steps.iter().map(|step| step.status).collect();
+        assert_eq!(
+            statuses,
+            vec![StepStatus::Passed, StepStatus::Passed, StepStatus::Passed, StepStatus::Failed, StepStatus::Skipped]
+        );
+        assert_eq!(report.steps[3].message.as_deref(), Some("voltage = 3.31 is out of range 3.2..3.3"));
+
+        let xml = report.to_junit_xml();
+        assert!(xml.contains("tests=\"5\" failures=\"1\" errors=\"0\" skipped=\"1\""));
+        assert!(xml.contains("<testcase name=\"4. supply voltage\" classname=\"echo board\""));
+        assert!(xml.contains("<failure message=\"voltage = 3.31 is out of range 3.2..3.3\">"));
+        assert!(xml.contains("&gt;&gt; FW 1.4 V=3.31"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["steps"][4]["status"], "Skipped");
+    }
+
+    #[test]
+    fn test_mask_hides_secrets_in_exported_report() {
+        // 1行が2回の受信に分かれている
+        let transcript = vec![
+            TerminalMessage::new_received("login password=hun".to_string(), "UTF-8".to_string()),
+            TerminalMessage::new_received("ter2\n".to_string(), "UTF-8".to_string()),
+        ];
+        let mut report = TestReport {
+            suite: "secrets".to_string(),
+            started_at: Utc::now(),
+            duration_ms: 10,
+            passed: false,
+            steps: vec![StepReport {
+                index: 0,
+                name: "login".to_string(),
+                status: StepStatus::Failed,
+                duration_ms: 10,
+                message: Some("unexpected reply: password=hunter2".to_string()),
+            }],
+            captures: BTreeMap::from([("token".to_string(), "password=hunter2".to_string())]),
+            transcript,
+        };
+
+        report.mask(&DataMasker::default());
+
+        let xml = report.to_junit_xml();
+        let json = report.to_json().unwrap();
+        assert!(!xml.contains("hunter2"), "{}", xml);
+        assert!(!json.contains("hunter2"), "{}", json);
+        assert!(xml.contains("login password=********"));
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml("<a & \"b\">\x1b[0m"), "&lt;a &amp; &quot;b&quot;&gt;\\x1B[0m");
+    }
+}
\ No newline at end of file
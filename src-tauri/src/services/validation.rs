@@ -0,0 +1,424 @@
+use crate::models::{ConnectionConfig, ConnectionType, DataBits, Parity, SerialConfig, StopBits, TcpConfig};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::Duration;
+
+// 一般的に使われるボーレート
+const STANDARD_BAUD_RATES: &[u32] = &[
+    50, 75, 110, 134, 150, 200, 300, 600, 1200, 1800, 2400, 4800, 9600, 14400, 19200, 28800, 38400,
+    57600, 76800, 115200, 128000, 230400, 250000, 256000, 460800, 500000, 576000, 921600, 1000000,
+    1152000, 1500000, 2000000, 2500000, 3000000, 3500000, 4000000,
+];
+const MIN_BAUD_RATE: u32 = 50;
+const MAX_BAUD_RATE: u32 = 4_000_000;
+
+const MIN_TIMEOUT: Duration = Duration::from_millis(100);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// フォームの項目に対応づけられる検証結果。field は "tcp_config.host" のようなパス
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn error(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+// ネットワークやデバイスにアクセスしない検証。
+// available_ports が None の場合（ポート一覧を取得できなかった場合）はポートの存在確認を行わない
+pub fn validate_profile(
+    profile: &ConnectionConfig,
+    existing_profiles: &[ConnectionConfig],
+    available_ports: Option<&[String]>,
+) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let name = profile.name.trim();
+    if name.is_empty() {
+        issues.push(ValidationIssue::error("name", "required", "Profile name is required"));
+    } else if existing_profiles
+        .iter()
+        .any(|other| other.id != profile.id && other.name.trim().eq_ignore_ascii_case(name))
+    {
+        issues.push(ValidationIssue::error(
+            "name",
+            "duplicate_name",
+            format!("Another profile is already named '{}'", name),
+        ));
+    }
+
+    match profile.connection_type {
+        ConnectionType::Serial => {
+            match &profile.serial_config {
+                Some(serial_config) => validate_serial(serial_config, available_ports, &mut issues),
+                None => issues.push(ValidationIssue::error(
+                    "serial_config",
+                    "config_mismatch",
+                    "Serial connections require serial settings",
+                )),
+            }
+            if profile.tcp_config.is_some() {
+                issues.push(ValidationIssue::warning(
+                    "tcp_config",
+                    "config_mismatch",
+                    "TCP settings are ignored for serial connections",
+                ));
+            }
+        }
+        ConnectionType::Tcp => {
+            match &profile.tcp_config {
+                Some(tcp_config) => validate_tcp(tcp_config, &mut issues),
+                None => issues.push(ValidationIssue::error(
+                    "tcp_config",
+                    "config_mismatch",
+                    "TCP connections require TCP settings",
+                )),
+            }
+            if profile.serial_config.is_some() {
+                issues.push(ValidationIssue::warning(
+                    "serial_config",
+                    "config_mismatch",
+                    "Serial settings are ignored for TCP connections",
+                ));
+            }
+        }
+    }
+
+    for (index, trigger) in profile.triggers.iter().enumerate() {
+        if let Err(e) = trigger.validate() {
+            issues.push(ValidationIssue::error(&format!("triggers[{}]", index), "invalid_trigger", e));
+        }
+    }
+
+    issues
+}
+
+fn validate_serial(config: &SerialConfig, available_ports: Option<&[String]>, issues: &mut Vec<ValidationIssue>) {
+    let port = config.port.trim();
+    if port.is_empty() {
+        issues.push(ValidationIssue::error("serial_config.port", "required", "Select a serial port"));
+    } else if let Some(ports) = available_ports {
+        // USB シリアルは抜かれていることがあるため警告にとどめる
+        if !ports.iter().any(|p| p == port) {
+            issues.push(ValidationIssue::warning(
+                "serial_config.port",
+                "port_not_found",
+                format!("Serial port '{}' is not currently available", port),
+            ));
+        }
+    }
+
+    let baud_rate = config.baud_rate;
+    if !(MIN_BAUD_RATE..=MAX_BAUD_RATE).contains(&baud_rate) {
+        issues.push(ValidationIssue::error(
+            "serial_config.baud_rate",
+            "implausible_baud_rate",
+            format!("Baud rate must be between {} and {}", MIN_BAUD_RATE, MAX_BAUD_RATE),
+        ));
+    } else if !STANDARD_BAUD_RATES.contains(&baud_rate) {
+        issues.push(ValidationIssue::warning(
+            "serial_config.baud_rate",
+            "nonstandard_baud_rate",
+            format!("{} is not a standard baud rate; check that the device supports it", baud_rate),
+        ));
+    }
+
+    // serialport クレートは Mark/Space パリティと 1.5 ストップビットに対応していない
+    if matches!(config.parity, Parity::Mark | Parity::Space) {
+        issues.push(ValidationIssue::error(
+            "serial_config.parity",
+            "unsupported_parity",
+            "Mark and space parity are not supported by the serial driver",
+        ));
+    }
+    if config.stop_bits == StopBits::OnePointFive {
+        if config.data_bits == DataBits::Five {
+            issues.push(ValidationIssue::warning(
+                "serial_config.stop_bits",
+                "unsupported_stop_bits",
+                "1.5 stop bits will be sent as 2 stop bits",
+            ));
+        } else {
+            issues.push(ValidationIssue::error(
+                "serial_config.stop_bits",
+                "unsupported_stop_bits",
+                "1.5 stop bits can only be used with 5 data bits",
+            ));
+        }
+    }
+}
+
+fn validate_tcp(config: &TcpConfig, issues: &mut Vec<ValidationIssue>) {
+    let host = config.host.trim();
+    if host.is_empty() {
+        issues.push(ValidationIssue::error("tcp_config.host", "required", "Host address is required"));
+    } else if !is_valid_host(host) {
+        issues.push(ValidationIssue::error(
+            "tcp_config.host",
+            "invalid_host",
+            format!("'{}' is not a valid IP address or host name", host),
+        ));
+    }
+
+    if config.port == 0 {
+        issues.push(ValidationIssue::error(
+            "tcp_config.port",
+            "invalid_port",
+            "Port must be between 1 and 65535",
+        ));
+    }
+
+    if config.timeout < MIN_TIMEOUT || config.timeout > MAX_TIMEOUT {
+        issues.push(ValidationIssue::error(
+            "tcp_config.timeout",
+            "timeout_out_of_range",
+            format!(
+                "Timeout must be between {} ms and {} s",
+                MIN_TIMEOUT.as_millis(),
+                MAX_TIMEOUT.as_secs()
+            ),
+        ));
+    }
+}
+
+// IP アドレス（[::1] のような括弧付きも可）または RFC 1123 形式のホスト名
+pub fn is_valid_host(host: &str) -> bool {
+    if is_ip_address(host) {
+        return true;
+    }
+
+    let name = host.strip_suffix('.').unwrap_or(host);
+    if name.is_empty() || name.len() > 253 {
+        return false;
+    }
+    // 数字とドットだけのものは IP アドレスの書き間違いとみなす
+    if name.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return false;
+    }
+    name.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+// [::1] のような括弧付きの IPv6 アドレスも IP アドレスとみなす
+fn is_ip_address(host: &str) -> bool {
+    host.strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse::<IpAddr>()
+        .is_ok()
+}
+
+// ホスト名を名前解決できるか確認する。IP アドレスの場合は何もしない。
+// ネットワークに接続していないだけのこともあるため警告として返す
+pub async fn check_host_resolves(host: &str, port: u16) -> Option<ValidationIssue> {
+    let host = host.trim();
+    if is_ip_address(host) || !is_valid_host(host) {
+        return None;
+    }
+
+    match tokio::time::timeout(RESOLVE_TIMEOUT, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => match addresses.next() {
+            Some(_) => None,
+            None => Some(ValidationIssue::warning(
+                "tcp_config.host",
+                "unresolvable_host",
+                format!("Host name '{}' has no addresses", host),
+            )),
+        },
+        Ok(Err(_)) => Some(ValidationIssue::warning(
+            "tcp_config.host",
+            "unresolvable_host",
+            format!("Host name '{}' could not be resolved", host),
+        )),
+        Err(_) => Some(ValidationIssue::warning(
+            "tcp_config.host",
+            "unresolvable_host",
+            format!("Resolving '{}' timed out", host),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{TriggerMode, TriggerRule};
+
+    fn codes(issues: &[ValidationIssue]) -> Vec<(&str, &str)> {
+        issues.iter().map(|issue| (issue.field.as_str(), issue.code.as_str())).collect()
+    }
+
+    fn serial_profile(port: &str) -> ConnectionConfig {
+        ConnectionConfig::new_serial(
+            "Device".to_string(),
+            SerialConfig {
+                port: port.to_string(),
+                ..SerialConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_valid_profiles_have_no_issues() {
+        let ports = vec!["/dev/ttyUSB0".to_string()];
+        assert!(validate_profile(&serial_profile("/dev/ttyUSB0"), &[], Some(&ports)).is_empty());
+
+        let tcp = ConnectionConfig::new_tcp("Server".to_string(), TcpConfig::default());
+        assert!(validate_profile(&tcp, &[], None).is_empty());
+    }
+
+    #[test]
+    fn test_config_variant_mismatch() {
+        let mut profile = serial_profile("COM1");
+        profile.connection_type = ConnectionType::Tcp;
+
+        let issues = validate_profile(&profile, &[], None);
+
+        assert_eq!(
+            codes(&issues),
+            vec![("tcp_config", "config_mismatch"), ("serial_config", "config_mismatch")]
+        );
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_duplicate_name() {
+        let existing = serial_profile("COM1");
+        let mut profile = serial_profile("COM2");
+        profile.name = " device ".to_string();
+
+        let issues = validate_profile(&profile, std::slice::from_ref(&existing), None);
+        assert_eq!(codes(&issues), vec![("name", "duplicate_name")]);
+
+        // 自分自身とは重複しない
+        assert!(validate_profile(&existing, std::slice::from_ref(&existing), None).is_empty());
+    }
+
+    #[test]
+    fn test_serial_checks() {
+        let mut profile = serial_profile("COM9");
+        let serial = profile.serial_config.as_mut().unwrap();
+        serial.baud_rate = 12345;
+        serial.parity = Parity::Mark;
+        serial.stop_bits = StopBits::OnePointFive;
+
+        let issues = validate_profile(&profile, &[], Some(&["COM1".to_string()]));
+
+        assert_eq!(
+            codes(&issues),
+            vec![
+                ("serial_config.port", "port_not_found"),
+                ("serial_config.baud_rate", "nonstandard_baud_rate"),
+                ("serial_config.parity", "unsupported_parity"),
+                ("serial_config.stop_bits", "unsupported_stop_bits"),
+            ]
+        );
+        assert_eq!(issues[2].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_implausible_baud_rate() {
+        let mut profile = serial_profile("COM1");
+        profile.serial_config.as_mut().unwrap().baud_rate = 0;
+
+        let issues = validate_profile(&profile, &[], None);
+
+        assert_eq!(codes(&issues), vec![("serial_config.baud_rate", "implausible_baud_rate")]);
+    }
+
+    #[test]
+    fn test_tcp_checks() {
+        let mut profile = ConnectionConfig::new_tcp("Server".to_string(), TcpConfig::default());
+        let tcp = profile.tcp_config.as_mut().unwrap();
+        tcp.host = "bad_host!".to_string();
+        tcp.port = 0;
+        tcp.timeout = Duration::from_secs(600);
+
+        let issues = validate_profile(&profile, &[], None);
+
+        assert_eq!(
+            codes(&issues),
+            vec![
+                ("tcp_config.host", "invalid_host"),
+                ("tcp_config.port", "invalid_port"),
+                ("tcp_config.timeout", "timeout_out_of_range"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_trigger() {
+        let mut profile = serial_profile("COM1");
+        profile.triggers.push(TriggerRule {
+            id: "t1".to_string(),
+            name: "login".to_string(),
+            pattern: "login:(".to_string(),
+            response: "root".to_string(),
+            line_ending: None,
+            delay_ms: 0,
+            mode: TriggerMode::OneShot,
+            cooldown_ms: 0,
+            enabled: true,
+        });
+
+        let issues = validate_profile(&profile, &[], None);
+
+        assert_eq!(codes(&issues), vec![("triggers[0]", "invalid_trigger")]);
+    }
+
+    #[test]
+    fn test_is_valid_host() {
+        assert!(is_valid_host("192.168.0.1"));
+        assert!(is_valid_host("::1"));
+        assert!(is_valid_host("[fe80::1]"));
+        assert!(is_valid_host("device-01.local"));
+        assert!(is_valid_host("example.com."));
+
+        assert!(!is_valid_host("999.1.1.1"));
+        assert!(!is_valid_host("-device"));
+        assert!(!is_valid_host("dev ice"));
+        assert!(!is_valid_host("a..b"));
+        assert!(!is_valid_host(&"a".repeat(64)));
+    }
+
+    #[tokio::test]
+    async fn test_check_host_resolves_skips_ip_addresses() {
+        assert_eq!(check_host_resolves("127.0.0.1", 80).await, None);
+        assert_eq!(check_host_resolves("::1", 80).await, None);
+        assert_eq!(check_host_resolves("[::1]", 80).await, None);
+        assert_eq!(check_host_resolves("[fe80::1]", 80).await, None);
+        assert_eq!(check_host_resolves("localhost", 80).await, None);
+    }
+}
\ No newline at end of file
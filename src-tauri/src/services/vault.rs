@@ -0,0 +1,508 @@
+use crate::models::{ConnectionConfig, SecretKind};
+use crate::utils::crypto::{self, CryptoError, EncryptedData, KdfParams, SecretKey, SALT_LEN};
+use crate::utils::fs::write_atomic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, error, info};
+
+const VAULT_FORMAT_VERSION: u32 = 1;
+// マスターパスワードの照合用に暗号化しておく値
+const VERIFIER_PLAINTEXT: &[u8] = b"credential-vault";
+
+#[derive(Error, Debug)]
+pub enum VaultError {
+    #[error("Vault is locked")]
+    Locked,
+
+    #[error("Incorrect master password")]
+    WrongPassword,
+
+    #[error("Master password must not be empty")]
+    EmptyPassword,
+
+    #[error("Vault has not been created")]
+    NotInitialized,
+
+    #[error("Vault already exists")]
+    AlreadyInitialized,
+
+    #[error("Secret not found: {0}")]
+    NotFound(String),
+
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] CryptoError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid vault file: {0}")]
+    InvalidFile(#[from] serde_json::Error),
+}
+
+pub type VaultResult<T> = Result<T, VaultError>;
+
+// 秘密情報の一覧表示用。値は含まない
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecretInfo {
+    pub id: String,
+    pub name: String,
+    pub kind: SecretKind,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VaultStatus {
+    pub initialized: bool,
+    pub unlocked: bool,
+    pub secret_count: usize,
+    pub auto_lock_timeout_minutes: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StoredSecret {
+    #[serde(flatten)]
+    info: SecretInfo,
+    value: EncryptedData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    salt: String,
+    verifier: EncryptedData,
+    secrets: Vec<StoredSecret>,
+}
+
+// マスターパスワードからの鍵導出に必要な情報。
+// Argon2id は時間がかかるため、ストアをロックしたまま非同期ランタイム上で実行しないこと
+#[derive(Clone)]
+pub struct KeyDerivation {
+    salt: Vec<u8>,
+    params: KdfParams,
+}
+
+impl KeyDerivation {
+    pub fn derive(&self, master_password: &str) -> VaultResult<SecretKey> {
+        if master_password.is_empty() {
+            return Err(VaultError::EmptyPassword);
+        }
+        Ok(crypto::derive_key(master_password, &self.salt, &self.params)?)
+    }
+}
+
+// マスターパスワードから導出した鍵で秘密情報を暗号化して保存する
+pub struct CredentialVault {
+    path: PathBuf,
+    file: Option<VaultFile>,
+    key: Option<SecretKey>,
+    kdf_params: KdfParams,
+    auto_lock_timeout: Option<Duration>,
+    last_activity: Instant,
+}
+
+impl CredentialVault {
+    pub fn open(path: PathBuf) -> VaultResult<Self> {
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => Some(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            file,
+            key: None,
+            kdf_params: KdfParams::default(),
+            auto_lock_timeout: None,
+            last_activity: Instant::now(),
+        })
+    }
+
+    #[cfg(test)]
+    fn with_kdf_params(mut self, params: KdfParams) -> Self {
+        self.kdf_params = params;
+        self
+    }
+
+    pub fn set_auto_lock_timeout(&mut self, minutes: Option<u32>) {
+        self.auto_lock_timeout = minutes
+            .filter(|minutes| *minutes > 0)
+            .map(|minutes| Duration::from_secs(u64::from(minutes) * 60));
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn status(&self) -> VaultStatus {
+        VaultStatus {
+            initialized: self.is_initialized(),
+            unlocked: self.is_unlocked(),
+            secret_count: self.file.as_ref().map(|file| file.secrets.len()).unwrap_or(0),
+            auto_lock_timeout_minutes: self
+                .auto_lock_timeout
+                .map(|timeout| (timeout.as_secs() / 60) as u32),
+        }
+    }
+
+    // 指定したマスターパスワードでストアを新規作成し、ロックを解除した状態にする
+    pub fn create(&mut self, master_password: &str) -> VaultResult<()> {
+        let derivation = self.create_derivation()?;
+        let key = derivation.derive(master_password)?;
+        self.finish_create(derivation, key)
+    }
+
+    pub fn unlock(&mut self, master_password: &str) -> VaultResult<()> {
+        let key = self.unlock_derivation()?.derive(master_password)?;
+        self.finish_unlock(key)
+    }
+
+    // 新規作成用の鍵導出情報。導出後に finish_create を呼ぶ
+    pub fn create_derivation(&self) -> VaultResult<KeyDerivation> {
+        if self.is_initialized() {
+            return Err(VaultError::AlreadyInitialized);
+        }
+        Ok(KeyDerivation {
+            salt: crypto::random_bytes(SALT_LEN),
+            params: self.kdf_params.clone(),
+        })
+    }
+
+    pub fn finish_create(&mut self, derivation: KeyDerivation, key: SecretKey) -> VaultResult<()> {
+        // 鍵の導出中に別の操作で作成された場合は上書きしない
+        if self.is_initialized() {
+            return Err(VaultError::AlreadyInitialized);
+        }
+
+        self.file = Some(VaultFile {
+            version: VAULT_FORMAT_VERSION,
+            kdf: derivation.params,
+            salt: crypto::encode_base64(&derivation.salt),
+            verifier: crypto::encrypt(&key, VERIFIER_PLAINTEXT)?,
+            secrets: Vec::new(),
+        });
+        if let Err(e) = self.save() {
+            self.file = None;
+            return Err(e);
+        }
+        info!("Created credential vault: {:?}", self.path);
+
+        self.set_key(key);
+        Ok(())
+    }
+
+    // ロック解除用の鍵導出情報。導出後に finish_unlock を呼ぶ
+    pub fn unlock_derivation(&self) -> VaultResult<KeyDerivation> {
+        let file = self.file.as_ref().ok_or(VaultError::NotInitialized)?;
+        Ok(KeyDerivation {
+            salt: crypto::decode_base64(&file.salt)?,
+            params: file.kdf.clone(),
+        })
+    }
+
+    pub fn finish_unlock(&mut self, key: SecretKey) -> VaultResult<()> {
+        let file = self.file.as_ref().ok_or(VaultError::NotInitialized)?;
+        match crypto::decrypt(&key, &file.verifier) {
+            Ok(verifier) if verifier == VERIFIER_PLAINTEXT => {}
+            Ok(_) | Err(CryptoError::Decryption) => return Err(VaultError::WrongPassword),
+            Err(e) => return Err(e.into()),
+        }
+
+        self.set_key(key);
+        Ok(())
+    }
+
+    fn set_key(&mut self, key: SecretKey) {
+        self.key = Some(key);
+        self.last_activity = Instant::now();
+        info!("Credential vault unlocked");
+    }
+
+    pub fn lock(&mut self) {
+        if self.key.take().is_some() {
+            info!("Credential vault locked");
+        }
+    }
+
+    // 無操作時間が自動ロックの設定を超えていればロックする。ロックした場合は true
+    pub fn check_auto_lock(&mut self) -> bool {
+        match self.auto_lock_timeout {
+            Some(timeout) if self.is_unlocked() && self.last_activity.elapsed() >= timeout => {
+                debug!("Auto-locking credential vault after {:?} of inactivity", timeout);
+                self.lock();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn list_secrets(&mut self) -> VaultResult<Vec<SecretInfo>> {
+        self.ensure_unlocked()?;
+        Ok(self
+            .file
+            .as_ref()
+            .map(|file| file.secrets.iter().map(|secret| secret.info.clone()).collect())
+            .unwrap_or_default())
+    }
+
+    // 秘密情報を保存する。id を指定した場合は既存の値を更新する
+    pub fn store_secret(
+        &mut self,
+        id: Option<String>,
+        name: String,
+        kind: SecretKind,
+        value: &str,
+    ) -> VaultResult<SecretInfo> {
+        self.ensure_unlocked()?;
+        let encrypted = crypto::encrypt(self.key()?, value.as_bytes())?;
+        let now = Utc::now();
+        let file = self.file.as_mut().ok_or(VaultError::Locked)?;
+
+        let info = match id {
+            Some(id) => {
+                let secret = file
+                    .secrets
+                    .iter_mut()
+                    .find(|secret| secret.info.id == id)
+                    .ok_or_else(|| VaultError::NotFound(id.clone()))?;
+                secret.info.name = name;
+                secret.info.kind = kind;
+                secret.info.updated_at = now;
+                secret.value = encrypted;
+                secret.info.clone()
+            }
+            None => {
+                let info = SecretInfo {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    name,
+                    kind,
+                    created_at: now,
+                    updated_at: now,
+                };
+                file.secrets.push(StoredSecret {
+                    info: info.clone(),
+                    value: encrypted,
+                });
+                info
+            }
+        };
+
+        self.save()?;
+        Ok(info)
+    }
+
+    pub fn get_secret(&mut self, id: &str) -> VaultResult<String> {
+        self.ensure_unlocked()?;
+        let secret = self
+            .file
+            .as_ref()
+            .and_then(|file| file.secrets.iter().find(|secret| secret.info.id == id))
+            .ok_or_else(|| VaultError::NotFound(id.to_string()))?;
+
+        let plaintext = crypto::decrypt(self.key()?, &secret.value)?;
+        String::from_utf8(plaintext)
+            .map_err(|_| VaultError::Crypto(CryptoError::InvalidEncoding("secret is not valid UTF-8".to_string())))
+    }
+
+    pub fn delete_secret(&mut self, id: &str) -> VaultResult<()> {
+        self.ensure_unlocked()?;
+        let file = self.file.as_mut().ok_or(VaultError::Locked)?;
+
+        let original_len = file.secrets.len();
+        file.secrets.retain(|secret| secret.info.id != id);
+        if file.secrets.len() == original_len {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        self.save()
+    }
+
+    pub fn contains_secret(&self, id: &str) -> bool {
+        self.file
+            .as_ref()
+            .map(|file| file.secrets.iter().any(|secret| secret.info.id == id))
+            .unwrap_or(false)
+    }
+
+    // プロファイルが参照している秘密情報をまとめて取り出す（secret_id -> 値）
+    pub fn resolve_profile_secrets(&mut self, profile: &ConnectionConfig) -> Result<HashMap<String, String>, String> {
+        let mut secrets = HashMap::new();
+        if profile.secret_refs.is_empty() {
+            return Ok(secrets);
+        }
+        if !self.is_unlocked() {
+            return Err(format!("Unlock the credential vault to connect with '{}'", profile.name));
+        }
+
+        for secret_ref in &profile.secret_refs {
+            let value = self.get_secret(&secret_ref.secret_id).map_err(|e| {
+                error!("Failed to resolve secret {} for profile {}: {}", secret_ref.secret_id, profile.name, e);
+                format!("Failed to resolve secret: {}", e)
+            })?;
+            secrets.insert(secret_ref.secret_id.clone(), value);
+        }
+        Ok(secrets)
+    }
+
+    // 自動ロックの判定を行い、操作時刻を更新する
+    fn ensure_unlocked(&mut self) -> VaultResult<()> {
+        self.check_auto_lock();
+        if !self.is_unlocked() {
+            return Err(VaultError::Locked);
+        }
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    fn key(&self) -> VaultResult<&SecretKey> {
+        self.key.as_ref().ok_or(VaultError::Locked)
+    }
+
+    fn save(&self) -> VaultResult<()> {
+        if let Some(file) = &self.file {
+            let data = serde_json::to_vec_pretty(file)?;
+            write_atomic(&self.path, &data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn create_test_vault(dir: &Path) -> CredentialVault {
+        CredentialVault::open(dir.join("vault.json"))
+            .unwrap()
+            .with_kdf_params(KdfParams {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            })
+    }
+
+    #[test]
+    fn test_create_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+        assert!(!vault.is_initialized());
+
+        // 未作成のストアはロック解除で作成されない
+        assert!(matches!(vault.unlock("master"), Err(VaultError::NotInitialized)));
+        assert!(!dir.path().join("vault.json").exists());
+
+        vault.create("master").unwrap();
+
+        assert!(vault.is_initialized());
+        assert!(vault.is_unlocked());
+        assert!(dir.path().join("vault.json").exists());
+        assert!(matches!(vault.create("other"), Err(VaultError::AlreadyInitialized)));
+    }
+
+    #[test]
+    fn test_store_and_get_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+        vault.create("master").unwrap();
+
+        let info = vault
+            .store_secret(None, "Router".to_string(), SecretKind::LoginPassword, "hunter2")
+            .unwrap();
+
+        assert_eq!(vault.get_secret(&info.id).unwrap(), "hunter2");
+        assert_eq!(vault.list_secrets().unwrap().len(), 1);
+
+        // ファイルには平文が含まれない
+        let content = fs::read_to_string(dir.path().join("vault.json")).unwrap();
+        assert!(!content.contains("hunter2"));
+        assert!(content.contains("Router"));
+    }
+
+    #[test]
+    fn test_reopen_and_unlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = {
+            let mut vault = create_test_vault(dir.path());
+            vault.create("master").unwrap();
+            vault
+                .store_secret(None, "PSK".to_string(), SecretKind::Other, "wifi-key")
+                .unwrap()
+                .id
+        };
+
+        let mut vault = create_test_vault(dir.path());
+        assert!(vault.is_initialized());
+        assert!(matches!(vault.get_secret(&id), Err(VaultError::Locked)));
+        assert!(matches!(vault.unlock("wrong"), Err(VaultError::WrongPassword)));
+
+        vault.unlock("master").unwrap();
+        assert_eq!(vault.get_secret(&id).unwrap(), "wifi-key");
+    }
+
+    #[test]
+    fn test_update_and_delete_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+        vault.create("master").unwrap();
+
+        let info = vault
+            .store_secret(None, "Proxy".to_string(), SecretKind::ProxyPassword, "old")
+            .unwrap();
+        vault
+            .store_secret(Some(info.id.clone()), "Proxy".to_string(), SecretKind::ProxyPassword, "new")
+            .unwrap();
+        assert_eq!(vault.get_secret(&info.id).unwrap(), "new");
+
+        vault.delete_secret(&info.id).unwrap();
+        assert!(!vault.contains_secret(&info.id));
+        assert!(matches!(vault.delete_secret(&info.id), Err(VaultError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+        vault.create("master").unwrap();
+
+        vault.lock();
+
+        assert!(!vault.is_unlocked());
+        assert!(matches!(vault.list_secrets(), Err(VaultError::Locked)));
+    }
+
+    #[test]
+    fn test_auto_lock_after_idle_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+        vault.set_auto_lock_timeout(Some(1));
+        vault.create("master").unwrap();
+
+        assert!(!vault.check_auto_lock());
+
+        vault.last_activity = Instant::now() - Duration::from_secs(61);
+        assert!(vault.check_auto_lock());
+        assert!(!vault.is_unlocked());
+    }
+
+    #[test]
+    fn test_empty_password_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut vault = create_test_vault(dir.path());
+
+        assert!(matches!(vault.create(""), Err(VaultError::EmptyPassword)));
+        assert!(!vault.is_initialized());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,172 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("Key derivation failed: {0}")]
+    KeyDerivation(String),
+
+    #[error("Encryption failed")]
+    Encryption,
+
+    #[error("Decryption failed")]
+    Decryption,
+
+    #[error("Invalid encoding: {0}")]
+    InvalidEncoding(String),
+}
+
+// 鍵導出 (Argon2id) のパラメーター
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+// 暗号化されたデータ。nonce と暗号文は Base64 で保持する
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EncryptedData {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+// 導出された鍵。破棄時にゼロで上書きする
+pub struct SecretKey([u8; KEY_LEN]);
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.fill(0);
+    }
+}
+
+pub fn derive_key(password: &str, salt: &[u8], params: &KdfParams) -> Result<SecretKey, CryptoError> {
+    let argon_params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LEN))
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params);
+
+    let mut key = [0u8; KEY_LEN];
+    argon2
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    Ok(SecretKey(key))
+}
+
+pub fn encrypt(key: &SecretKey, plaintext: &[u8]) -> Result<EncryptedData, CryptoError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| CryptoError::Encryption)?;
+
+    Ok(EncryptedData {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+// 鍵が異なる場合や改ざんされている場合は Decryption エラーになる
+pub fn decrypt(key: &SecretKey, data: &EncryptedData) -> Result<Vec<u8>, CryptoError> {
+    let nonce = decode_base64(&data.nonce)?;
+    if nonce.len() != 12 {
+        return Err(CryptoError::InvalidEncoding("invalid nonce length".to_string()));
+    }
+    let ciphertext = decode_base64(&data.ciphertext)?;
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.0));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| CryptoError::Decryption)
+}
+
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+pub fn encode_base64(data: &[u8]) -> String {
+    BASE64.encode(data)
+}
+
+pub fn decode_base64(data: &str) -> Result<Vec<u8>, CryptoError> {
+    BASE64
+        .decode(data)
+        .map_err(|e| CryptoError::InvalidEncoding(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テストでは軽量なパラメーターを使う
+    fn test_params() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let salt = random_bytes(SALT_LEN);
+        let key = derive_key("master", &salt, &test_params()).unwrap();
+
+        let encrypted = encrypt(&key, b"wifi-psk").unwrap();
+        assert_ne!(decode_base64(&encrypted.ciphertext).unwrap(), b"wifi-psk");
+
+        let decrypted = decrypt(&key, &encrypted).unwrap();
+        assert_eq!(decrypted, b"wifi-psk");
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_key_fails() {
+        let salt = random_bytes(SALT_LEN);
+        let key = derive_key("master", &salt, &test_params()).unwrap();
+        let wrong_key = derive_key("wrong", &salt, &test_params()).unwrap();
+
+        let encrypted = encrypt(&key, b"secret").unwrap();
+
+        assert!(matches!(decrypt(&wrong_key, &encrypted), Err(CryptoError::Decryption)));
+    }
+
+    #[test]
+    fn test_nonce_is_unique() {
+        let key = derive_key("master", &random_bytes(SALT_LEN), &test_params()).unwrap();
+
+        let first = encrypt(&key, b"same").unwrap();
+        let second = encrypt(&key, b"same").unwrap();
+
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.ciphertext, second.ciphertext);
+    }
+
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let salt = random_bytes(SALT_LEN);
+        let first = derive_key("master", &salt, &test_params()).unwrap();
+        let second = derive_key("master", &salt, &test_params()).unwrap();
+
+        assert_eq!(first.0, second.0);
+    }
+}
\ No newline at end of file
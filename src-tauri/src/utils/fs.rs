@@ -0,0 +1,91 @@
+use chrono::Local;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// 一時ファイルに書き込んでからリネームし、書き込み途中のファイルが残らないようにする
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// ファイルを「<名前>.<接尾辞>-<日時>」にリネームして退避する
+pub fn backup_file(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+
+    let mut backup_path = path.with_file_name(format!("{}.{}-{}", file_name, suffix, timestamp));
+    let mut index = 1;
+    while backup_path.exists() {
+        backup_path = path.with_file_name(format!("{}.{}-{}_{}", file_name, suffix, timestamp, index));
+        index += 1;
+    }
+
+    fs::rename(path, &backup_path)?;
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_creates_and_replaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("settings.json");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+
+        // 一時ファイルが残っていない
+        let entries: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_backup_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profiles.json");
+        fs::write(&path, "broken").unwrap();
+
+        let first = backup_file(&path, "corrupt").unwrap();
+        fs::write(&path, "broken again").unwrap();
+        let second = backup_file(&path, "corrupt").unwrap();
+
+        assert!(!path.exists());
+        assert_ne!(first, second);
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("profiles.json.corrupt-"));
+        assert_eq!(fs::read_to_string(second).unwrap(), "broken again");
+    }
+}
\ No newline at end of file
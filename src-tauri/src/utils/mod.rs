@@ -1,2 +1,3 @@
-// 将来のユーティリティ関数実装用
-// 暗号化、ファイル操作、設定管理などの共通機能をここに実装する予定
\ No newline at end of file
+// 共通のユーティリティ関数
+pub mod crypto;
+pub mod fs;
\ No newline at end of file
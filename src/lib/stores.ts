@@ -1,7 +1,7 @@
 import { writable } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
-import type { AppState, ConnectionConfig, TerminalMessage, ApiResponse } from './types';
+import type { AppState, ConnectionConfig, TerminalMessage, MessageBatch, ApiResponse, Alert } from './types';
 import { generateId, validateSerialPort, validateTcpConnection } from './utils';
 
 // 初期状態
@@ -29,6 +29,10 @@ export const currentInput = writable<string>('');
 export const inputMode = writable<'text' | 'hex'>('text');
 export const availablePorts = writable<string[]>([]);
 
+// 強調表示ルールの通知（新しいものが末尾）
+const MAX_ALERTS = 100;
+export const alerts = writable<Alert[]>([]);
+
 // イベントリスナー管理
 let listenersInitialized = false;
 let lastMessageId = '';
@@ -152,24 +156,13 @@ export const actions = {
     if (!currentState.connection.isConnected || !input.trim()) return;
 
     try {
-      const message: TerminalMessage = {
-        id: generateId(),
-        timestamp: new Date().toISOString(),
-        direction: 'sent',
-        content: input.trim(),
-        type: mode
-      };
-
+      // 送信メッセージはバックエンドで記録され、受信メッセージと同じイベントで届く
       const response: ApiResponse<string> = await invoke('send_message', {
         message: input.trim(),
         format: mode
       });
 
       if (response.success) {
-        appState.update(state => ({
-          ...state,
-          messages: [...state.messages, message]
-        }));
         currentInput.set('');
       } else {
         actions.addMessage('error', response.error || 'メッセージ送信に失敗しました');
@@ -212,31 +205,47 @@ export const actions = {
     }
     
     try {
-      // 受信メッセージのリスナー
-      await listen('terminal-message-received', (event) => {
-        const backendMessage = event.payload as any;
-        
-        // 重複メッセージをチェック
-        const messageId = backendMessage.id || generateId();
-        if (messageId === lastMessageId) {
-          return;
-        }
-        lastMessageId = messageId;
-        
-        // バックエンドのメッセージを適切なフォーマットに変換
-        const frontendMessage: TerminalMessage = {
-          id: messageId,
-          timestamp: backendMessage.timestamp || new Date().toISOString(),
-          direction: 'received',
-          content: backendMessage.content || '',
-          type: backendMessage.type || 'text'
-        };
+      // 送受信メッセージのリスナー（一定間隔ごとにまとめて届く）
+      await listen<MessageBatch>('terminal-messages-received', (event) => {
+        const batch = event.payload;
+
+        const received: TerminalMessage[] = batch.messages
+          .filter((backendMessage) => {
+            // 重複メッセージをチェック
+            if (backendMessage.id === lastMessageId) {
+              return false;
+            }
+            lastMessageId = backendMessage.id;
+            return true;
+          })
+          .map((backendMessage): TerminalMessage => ({
+            id: backendMessage.id || generateId(),
+            timestamp: backendMessage.timestamp || new Date().toISOString(),
+            direction: backendMessage.direction,
+            content: backendMessage.content || '',
+            type: backendMessage.type || 'text',
+            highlights: backendMessage.highlights || []
+          }));
 
         // メッセージをストアに追加
-        appState.update(state => ({
-          ...state,
-          messages: [...state.messages, frontendMessage]
-        }));
+        if (received.length > 0) {
+          appState.update(state => ({
+            ...state,
+            messages: [...state.messages, ...received]
+          }));
+        }
+
+        // バッファ溢れで破棄されたメッセージを通知
+        if (batch.dropped > 0) {
+          actions.addMessage('error', `受信バッファが溢れたため ${batch.dropped} 件のメッセージを破棄しました`);
+        }
+      });
+
+      // 強調表示ルールの通知のリスナー
+      await listen<Alert>('alert-raised', (event) => {
+        const alert = event.payload;
+        alerts.update(current => [...current, alert].slice(-MAX_ALERTS));
+        actions.addMessage('error', `[${alert.severity}] ${alert.rule_name}: ${alert.matched}`);
       });
 
       // 接続状態変更のリスナー  
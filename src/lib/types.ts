@@ -13,9 +13,37 @@ export interface ConnectionConfig {
 export interface TerminalMessage {
   id: string;
   timestamp: string;
-  direction: 'sent' | 'received';
+  direction: 'sent' | 'received' | 'system';
   content: string;
   type: 'text' | 'hex';
+  highlights?: HighlightSpan[]; // 受信時に強調表示ルールと一致した範囲
+}
+
+export type Severity = 'Info' | 'Warning' | 'Error' | 'Critical';
+
+// 強調表示ルールに一致した範囲。位置は UTF-16 単位（文字列の添字と同じ）
+export interface HighlightSpan {
+  rule_id: string;
+  start: number;
+  end: number;
+  severity: Severity;
+  color: string | null; // null の場合は重要度ごとの色
+}
+
+// 重要度が Critical、または notify を指定したルールに一致したときの通知（alert-raised イベント）
+export interface Alert {
+  rule_id: string;
+  rule_name: string;
+  severity: Severity;
+  notify: boolean;
+  message_id: string;
+  matched: string;
+  timestamp: string;
+}
+
+export interface MessageBatch {
+  messages: TerminalMessage[];
+  dropped: number;
 }
 
 export interface ConnectionState {
@@ -1,5 +1,7 @@
 // ユーティリティ関数
 
+import type { HighlightSpan } from './types';
+
 export function generateId(): string {
   return crypto.randomUUID();
 }
@@ -14,6 +16,41 @@ export function formatTimestamp(date: Date): string {
   });
 }
 
+// 強調表示の範囲でメッセージを区切る。重なった範囲は先に始まるものを優先する
+export function splitHighlights(content: string, spans: HighlightSpan[] = []): { text: string; span?: HighlightSpan }[] {
+  const segments: { text: string; span?: HighlightSpan }[] = [];
+  let position = 0;
+  for (const span of [...spans].sort((a, b) => a.start - b.start)) {
+    const start = Math.max(span.start, position);
+    const end = Math.min(span.end, content.length);
+    if (start >= end) continue;
+    if (start > position) {
+      segments.push({ text: content.slice(position, start) });
+    }
+    segments.push({ text: content.slice(start, end), span });
+    position = end;
+  }
+  if (position < content.length) {
+    segments.push({ text: content.slice(position) });
+  }
+  return segments;
+}
+
+// 色の指定がない強調表示は重要度ごとの色で表示する
+export function highlightColor(span: HighlightSpan): string {
+  if (span.color) return span.color;
+  switch (span.severity) {
+    case 'Critical':
+      return '#ff5555';
+    case 'Error':
+      return '#f7768e';
+    case 'Warning':
+      return '#e0af68';
+    default:
+      return '#7aa2f7';
+  }
+}
+
 export function validateSerialPort(port: string): boolean {
   return port.trim().length > 0;
 }